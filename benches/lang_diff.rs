@@ -0,0 +1,106 @@
+// Сравнение файла локализации размером около 5 МБ: текущая реализация diff_lang_content против прежней
+// (разбор обоих файлов в HashMap). Проверяет, что результат совпадает, и печатает время и число выделений памяти.
+// Запуск: cargo bench --bench lang_diff --features testing
+use krevetka::lang::{diff_lang_content, format_lang_changes, LangDiff, LangParseOptions};
+use krevetka::testing::reference_diff;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const RUNS: usize = 5;
+
+// Около 60 тысяч строк по ~85 байт. Новая версия: каждый сотый ключ изменён, каждый двухсотый удалён,
+// добавлены новые ключи, пара ключей повторяется
+fn fixture() -> (String, String) {
+    let mut old = String::new();
+    let mut new = String::new();
+    let mut i = 0;
+    while old.len() < 5 * 1024 * 1024 {
+        let line = format!("item.weapon_{}.description = Описание предмета номер {} для проверки\n", i, i);
+        old.push_str(&line);
+        if i % 200 == 7 {
+            // удалён
+        } else if i % 100 == 3 {
+            new.push_str(&format!("item.weapon_{}.description = Новое описание предмета {}\n", i, i));
+        } else {
+            new.push_str(&line);
+        }
+        if i % 500 == 11 {
+            new.push_str(&format!("item.armor_{}.name = Новая броня {}\n", i, i));
+        }
+        i += 1;
+    }
+    new.push_str("item.weapon_1.description = Повтор\n");
+    (old, new)
+}
+
+// Лучшее время из RUNS запусков и выделения памяти за один запуск
+fn measure(run: impl Fn() -> LangDiff) -> (Duration, usize, usize, LangDiff) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    let mut allocations = (0, 0);
+    for _ in 0..RUNS {
+        let (count, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+        let start = Instant::now();
+        let diff = black_box(run());
+        best = best.min(start.elapsed());
+        allocations = (
+            ALLOCATIONS.load(Ordering::Relaxed) - count,
+            ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+        );
+        result = Some(diff);
+    }
+    (best, allocations.0, allocations.1, result.unwrap())
+}
+
+fn main() {
+    let (old, new) = fixture();
+    println!("Файл: {:.1} МБ, строк: {}", old.len() as f64 / (1024.0 * 1024.0), old.lines().count());
+
+    let (reference_time, reference_count, reference_bytes, expected) =
+        measure(|| reference_diff("ru", black_box(&old), black_box(&new), &LangParseOptions::default()));
+    let (time, count, bytes, actual) = measure(|| diff_lang_content("ru", black_box(&old), black_box(&new)));
+
+    assert_eq!(actual, expected, "diff отличается от прежней реализации");
+    assert_eq!(format_lang_changes(&actual.changes()), format_lang_changes(&expected.changes()));
+    println!("Изменений: {}, вывод совпадает с прежней реализацией", actual.changes().len());
+    println!(
+        "HashMap (прежняя): {:>8.2} мс, выделений {:>7}, {:>6.1} МБ",
+        reference_time.as_secs_f64() * 1000.0,
+        reference_count,
+        reference_bytes as f64 / (1024.0 * 1024.0)
+    );
+    println!(
+        "слияние (текущая): {:>8.2} мс, выделений {:>7}, {:>6.1} МБ",
+        time.as_secs_f64() * 1000.0,
+        count,
+        bytes as f64 / (1024.0 * 1024.0)
+    );
+}
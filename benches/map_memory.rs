@@ -0,0 +1,120 @@
+// Пиковая память при обновлении копии карты на 500 тысяч записей: прежний путь (копия разворачивается
+// через to_entries, новые записи клонируются, diff_map_entries копирует оба среза в индексы) против
+// MapWatch::replace_baseline. Проверяет, что diff совпадает, и печатает прирост памяти сверх уже
+// загруженных записей. Запуск: cargo bench --bench map_memory --features testing
+use krevetka::compact::CompactMap;
+use krevetka::config::MapConfig;
+use krevetka::diff::{diff_map_entries, MapDiff};
+use krevetka::map::{filter_entries, read_map_entries, MapEntry, MapHash, MapReadOptions};
+use krevetka::monitor::MapWatch;
+use krevetka::testing::{MapFileBuilder, TempDir};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        grow(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            grow(new_size - layout.size());
+        } else {
+            CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAlloc = PeakAlloc;
+
+const ENTRIES: usize = 500_000;
+
+fn changed_entries(old: &[MapEntry]) -> Vec<MapEntry> {
+    old.iter()
+        .enumerate()
+        .filter(|(i, _)| i % 200 != 7)
+        .map(|(i, e)| MapEntry {
+            path: e.path.clone(),
+            hash: if i % 100 == 3 { MapHash::from([(i % 251) as u8; 20]) } else { e.hash },
+        })
+        .collect()
+}
+
+// Прежняя версия sync_map_changes и diff_filtered
+fn previous(baseline: &mut CompactMap, new_entries: Vec<MapEntry>, map_config: &MapConfig) -> MapDiff {
+    let old_entries = baseline.to_entries();
+    let copy = new_entries.clone();
+    *baseline = CompactMap::from_entries(&copy);
+    drop(copy);
+    let (old_entries, old_filtered) = filter_entries(old_entries, &map_config.include, &map_config.exclude);
+    let (new_entries, new_filtered) = filter_entries(new_entries, &map_config.include, &map_config.exclude);
+    let mut diff = diff_map_entries(&old_entries, &new_entries);
+    let filtered = diff_map_entries(&old_filtered, &new_filtered);
+    diff.filtered = filtered.total();
+    diff.total_old += filtered.total_old;
+    diff.total_new += filtered.total_new;
+    diff
+}
+
+// Время и пик памяти сверх занятой до запуска
+fn measure(run: impl FnOnce() -> MapDiff) -> (Duration, usize, MapDiff) {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let start = Instant::now();
+    let diff = black_box(run());
+    (start.elapsed(), PEAK.load(Ordering::Relaxed) - before, diff)
+}
+
+fn print_result(label: &str, time: Duration, peak: usize) {
+    println!(
+        "{:<22} {:>8.2} мс, пик {:>6.1} МБ",
+        label,
+        time.as_secs_f64() * 1000.0,
+        peak as f64 / (1024.0 * 1024.0)
+    );
+}
+
+fn main() {
+    let options = MapReadOptions::default();
+    let map_config = MapConfig::default();
+    let dir = TempDir::new("bench_map_memory");
+    let env_map = dir.join("stalcraft.map");
+    MapFileBuilder::synthetic(ENTRIES).write_to(&env_map).unwrap();
+    let old = read_map_entries(&env_map, &options).unwrap();
+    let new = changed_entries(&old);
+
+    let mut baseline = CompactMap::from_entries(&old);
+    drop(old);
+    let input = new.clone();
+    let (previous_time, previous_peak, expected) = measure(|| previous(&mut baseline, input, &map_config));
+    drop(baseline);
+
+    let mut watch = MapWatch::new(&env_map, &options).unwrap();
+    let input = new.clone();
+    let (time, peak, actual) = measure(|| watch.replace_baseline(input, &map_config, false));
+
+    assert_eq!(actual, expected, "diff отличается от прежнего пути");
+    println!("Записей: {}, изменения: {}", ENTRIES, expected.stats());
+    print_result("прежний путь", previous_time, previous_peak);
+    print_result("replace_baseline", time, peak);
+}
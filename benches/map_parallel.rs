@@ -0,0 +1,77 @@
+// Чтение и сравнение карты на 500 тысяч записей: последовательные read_map_entries и diff_map_entries
+// против параллельных версий. Проверяет, что результаты совпадают, и печатает лучшее время из RUNS запусков.
+// Запуск: cargo bench --bench map_parallel --features testing
+use krevetka::diff::{diff_map_entries, diff_map_entries_parallel};
+use krevetka::map::{read_map_entries, read_map_entries_parallel, write_map_entries, MapEntry, MapHash, MapReadOptions};
+use krevetka::testing::{MapFileBuilder, TempDir};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+const ENTRIES: usize = 500_000;
+
+// Новая версия карты: каждая сотая запись изменена, каждая двухсотая удалена, каждая пятисотая перемещена,
+// добавлены новые файлы
+fn changed_entries(old: &[MapEntry]) -> Vec<MapEntry> {
+    let mut new = Vec::with_capacity(old.len());
+    for (i, entry) in old.iter().enumerate() {
+        if i % 200 == 7 {
+            continue;
+        }
+        let mut entry = entry.clone();
+        if i % 100 == 3 {
+            entry.hash = MapHash::from([(i % 251) as u8; 20]);
+        } else if i % 500 == 11 {
+            entry.path = entry.path.replace("assets/", "assets/moved/");
+        }
+        new.push(entry);
+        if i % 300 == 5 {
+            new.push(MapEntry {
+                path: format!("assets/new/file_{}.png", i),
+                hash: MapHash::from([(i % 13) as u8; 20]),
+            });
+        }
+    }
+    new
+}
+
+fn measure<T>(run: impl Fn() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let value = black_box(run());
+        best = best.min(start.elapsed());
+        result = Some(value);
+    }
+    (best, result.unwrap())
+}
+
+fn print_time(label: &str, time: Duration) {
+    println!("{:<28} {:>8.2} мс", label, time.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    let options = MapReadOptions::default();
+    let dir = TempDir::new("bench_map_parallel");
+    let old_path = dir.join("old.map");
+    let new_path = dir.join("new.map");
+    MapFileBuilder::synthetic(ENTRIES).write_to(&old_path).unwrap();
+    let old = read_map_entries(&old_path, &options).unwrap();
+    write_map_entries(&new_path, &changed_entries(&old), &options).unwrap();
+    println!("Записей: {}, файл: {:.1} МБ", ENTRIES, old_path.metadata().unwrap().len() as f64 / (1024.0 * 1024.0));
+
+    let (read_time, sequential) = measure(|| read_map_entries(black_box(&new_path), &options).unwrap());
+    let (parallel_read_time, parallel) = measure(|| read_map_entries_parallel(black_box(&new_path), &options).unwrap());
+    assert_eq!(parallel, sequential, "параллельное чтение отличается от последовательного");
+    print_time("read_map_entries", read_time);
+    print_time("read_map_entries_parallel", parallel_read_time);
+
+    let new = sequential;
+    let (diff_time, expected) = measure(|| diff_map_entries(black_box(&old), black_box(&new)));
+    let (parallel_diff_time, actual) = measure(|| diff_map_entries_parallel(black_box(&old), black_box(&new)));
+    assert_eq!(actual, expected, "параллельный diff отличается от последовательного");
+    println!("Изменения: {}", expected.stats());
+    print_time("diff_map_entries", diff_time);
+    print_time("diff_map_entries_parallel", parallel_diff_time);
+}
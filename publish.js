@@ -4,20 +4,63 @@ import path from "path";
 
 const octokit = new Octokit({ auth: process.env.GITHUB_TOKEN });
 
+// Патчноут основного сервера и по одному на каждую дополнительную ветку (docs/ots, docs/<runtime>)
+const pages = [
+    path.join("docs", "index.html"),
+    ...fs.readdirSync("docs", { withFileTypes: true })
+        .filter((entry) => entry.isDirectory())
+        .map((entry) => path.join("docs", entry.name, "index.html")),
+    // Полные diff локализации, если патчноут показывает не все изменения (lang.max_rendered)
+    ...fs.readdirSync("docs")
+        .filter((name) => name.endsWith(".diff"))
+        .map((name) => path.join("docs", name)),
+    // Те же изменения для ботов и вики (changes.json) и для форумов (changelog_<дата>.md)
+    path.join("docs", "changes.json"),
+    ...fs.readdirSync("docs")
+        .filter((name) => name.startsWith("changelog_") && name.endsWith(".md"))
+        .map((name) => path.join("docs", name)),
+    // Ленты Atom основного сервера и каждой дополнительной ветки
+    path.join("docs", "feed.xml"),
+    ...fs.readdirSync("docs", { withFileTypes: true })
+        .filter((entry) => entry.isDirectory())
+        .map((entry) => path.join("docs", entry.name, "feed.xml")),
+    // Страницы патчноутов: index.html - только их список, сами патчноуты лежат в patches/
+    ...[path.join("docs"), ...fs.readdirSync("docs", { withFileTypes: true })
+        .filter((entry) => entry.isDirectory())
+        .map((entry) => path.join("docs", entry.name))]
+        .map((dir) => path.join(dir, "patches"))
+        .filter((dir) => fs.existsSync(dir))
+        .flatMap((dir) => fs.readdirSync(dir)
+            .filter((name) => name.endsWith(".html"))
+            .map((name) => path.join(dir, name))),
+    // Картинки оформления (changelog.branding: logo, pattern), скопированные генератором из assets
+    ...[path.join("docs"), ...fs.readdirSync("docs", { withFileTypes: true })
+        .filter((entry) => entry.isDirectory())
+        .map((entry) => path.join("docs", entry.name))]
+        .flatMap((dir) => fs.readdirSync(dir)
+            .filter((name) => /\.(png|jpe?g|gif|svg|webp)$/.test(name))
+            .map((name) => path.join(dir, name))),
+];
+
 async function uploadFile() {
     try {
-        const filePath = path.join("docs", "index.html");
-        const content = fs.readFileSync(filePath, { encoding: "base64" });
         const date = new Date().toISOString().split("T")[0];
 
-        await octokit.repos.createOrUpdateFileContents({
-            owner: "BuildersSC",
-            repo: "Krevetka",
-            path: "docs/index.html",
-            message: `Update ChangeLog on ${date}`,
-            content: content,
-            branch: "gh-pages",
-        });
+        for (const filePath of pages) {
+            if (!fs.existsSync(filePath)) {
+                continue;
+            }
+            const content = fs.readFileSync(filePath, { encoding: "base64" });
+
+            await octokit.repos.createOrUpdateFileContents({
+                owner: "BuildersSC",
+                repo: "Krevetka",
+                path: filePath.split(path.sep).join("/"),
+                message: `Update ChangeLog on ${date}`,
+                content: content,
+                branch: "gh-pages",
+            });
+        }
 
         console.log("File uploaded successfully!");
     } catch (err) {
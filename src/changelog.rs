@@ -1,26 +1,788 @@
-use crate::map::{MapEntry, MapError};
+use crate::config::{BrandingConfig, ChangelogConfig, LangConfig};
+use crate::diff::{DiffStats, MapDiff, ModifiedEntry};
+use crate::labels::Labels;
+use crate::lang::{
+    cross_check_locales, format_lang_changes, history_timestamp, list_lang_history, parse_filtered_trailer,
+    parse_lang_diff_file, split_color_codes, unescape_lang_value, write_atomic, LangCategories, LangChange,
+    LangChangeRecord, LangDiff, LangDiffHeader, LangEntryCounts, LangError,
+};
+use crate::map::Branch;
+use crate::unified::{diff_lines, LineOp};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io;
 use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChangelogError {
+    #[error("Ошибка записи патчноута: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Ошибка чтения истории локализации: {0}")]
+    LangError(#[from] LangError),
+    #[error("Ошибка записи changes.json: {0}")]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// Как показывать значения локализации в патчноуте
+#[derive(Debug, Clone, Default)]
+pub struct LangRenderOptions {
+    pub render_colors: bool,
+    // Сколько изменений показать на странице, 0 - без ограничения
+    pub max_rendered: usize,
+    // Разделы патчноута по категориям ключей (Оружие, Броня...)
+    pub categories: LangCategories,
+}
+
+impl LangRenderOptions {
+    pub fn from_config(config: &LangConfig) -> Self {
+        LangRenderOptions {
+            render_colors: config.render_colors,
+            max_rendered: config.max_rendered,
+            categories: LangCategories::with_overrides(&config.categories),
+        }
+    }
+}
+
+// Файл в папке истории с временем последней успешной публикации
+const LAST_PUBLISH_FILE: &str = "last_publish";
+
+/// Запоминает время публикации: следующий патчноут покажет только более поздние изменения локализации
+pub fn mark_published(history_dir: &Path) -> Result<(), ChangelogError> {
+    fs::create_dir_all(history_dir)?;
+    fs::write(history_dir.join(LAST_PUBLISH_FILE), history_timestamp())?;
+    Ok(())
+}
+
+/// Изменения локализации из истории, записанные после последней публикации, по одному LangDiff на локаль.
+/// Если между публикациями было несколько diff, изменения одного ключа сводятся к итогу от первого diff до последнего
+pub fn lang_changes_since_publish(history_dir: &Path) -> Result<Vec<LangDiff>, ChangelogError> {
+    let last_publish = fs::read_to_string(history_dir.join(LAST_PUBLISH_FILE))
+        .map(|content| content.trim().to_string())
+        .unwrap_or_default();
+    let mut diffs: Vec<LangDiff> = Vec::new();
+    for entry in list_lang_history(history_dir)? {
+        if entry.timestamp <= last_publish {
+            continue;
+        }
+        let content = fs::read_to_string(&entry.path)?;
+        // Пустой diff (файл создан, но изменений не записано) не даёт локали раздела в патчноуте
+        if content.trim().is_empty() {
+            continue;
+        }
+        let changes = parse_lang_diff_file(&content);
+        let header = LangDiffHeader::parse(&content);
+        let filtered = parse_filtered_trailer(&content);
+        // Повторяющиеся ключи описывают текущее состояние файла, поэтому берутся из последнего diff
+        let duplicates = header.duplicates;
+        match diffs.iter_mut().find(|diff| diff.locale == entry.locale) {
+            Some(diff) => {
+                *diff = LangDiff {
+                    path: diff.path.take(),
+                    duplicates,
+                    fallback_lines: diff.fallback_lines + header.fallback_lines,
+                    filtered: diff.filtered + filtered,
+                    ..LangDiff::from_changes(&entry.locale, merge_lang_changes(diff.changes(), changes))
+                };
+            }
+            None => diffs.push(LangDiff {
+                path: header.path,
+                duplicates,
+                fallback_lines: header.fallback_lines,
+                filtered,
+                ..LangDiff::from_changes(&entry.locale, changes)
+            }),
+        }
+    }
+    Ok(diffs)
+}
+
+// Добавляет к изменениям более поздние; изменение уже встречавшегося ключа заменяет прежнее на месте.
+// Переименования не сводятся: у них два ключа, и каждое остаётся отдельной записью
+fn merge_lang_changes(changes: Vec<LangChange>, later: Vec<LangChange>) -> Vec<LangChange> {
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (i, change) in changes.iter().enumerate() {
+        if !matches!(change, LangChange::Renamed { .. }) {
+            positions.insert(change.key().to_string(), i);
+        }
+    }
+    let mut merged: Vec<Option<LangChange>> = changes.into_iter().map(Some).collect();
+    for change in later {
+        if matches!(change, LangChange::Renamed { .. }) {
+            merged.push(Some(change));
+            continue;
+        }
+        match positions.get(change.key()).and_then(|&i| merged[i].take().map(|earlier| (i, earlier))) {
+            Some((i, earlier)) => merged[i] = combine_lang_changes(earlier, change),
+            None => {
+                positions.insert(change.key().to_string(), merged.len());
+                merged.push(Some(change));
+            }
+        }
+    }
+    merged.into_iter().flatten().collect()
+}
+
+// Итог двух изменений одного ключа подряд; None, если ключ вернулся к исходному состоянию
+fn combine_lang_changes(earlier: LangChange, later: LangChange) -> Option<LangChange> {
+    match (earlier, later) {
+        (LangChange::Added { key, .. }, LangChange::Modified { new, .. }) => Some(LangChange::Added { key, value: new }),
+        (LangChange::Added { .. }, LangChange::Removed { .. }) => None,
+        (LangChange::Modified { key, old, .. }, LangChange::Removed { .. }) => {
+            Some(LangChange::Removed { key, value: old })
+        }
+        (LangChange::Modified { key, old, .. }, LangChange::Modified { new, revert, .. }) => {
+            (old != new).then_some(LangChange::Modified { key, old, new, revert })
+        }
+        (LangChange::Removed { key, value: old }, LangChange::Added { value: new, .. }) => {
+            (old != new).then_some(LangChange::Modified { key, old, new, revert: false })
+        }
+        (_, later) => Some(later),
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
-enum ChangeType {
+pub enum ChangeType {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    // Хэш в карте сменился, а распакованный файл совпадает со старой версией
+    HashOnly,
+}
+
+pub fn change_markup(change_type: &ChangeType) -> (&'static str, &'static str) {
+    match change_type {
+        ChangeType::Added => ("added", "+"),
+        ChangeType::Modified => ("modified", "~"),
+        ChangeType::Deleted => ("deleted", "-"),
+        ChangeType::Renamed => ("renamed", "»"),
+        ChangeType::HashOnly => ("hash-only", "≈"),
+    }
+}
+
+// Цвет вида изменения для форума (BBCode), та же палитра, что в стилях страницы
+fn change_color(change_type: &ChangeType) -> &'static str {
+    match change_type {
+        ChangeType::Added => "#a0d468",
+        ChangeType::Modified | ChangeType::HashOnly => "#ffd700",
+        ChangeType::Deleted => "#ff6b6b",
+        ChangeType::Renamed => "#6fc3df",
+    }
+}
+
+// Сравнение имён с учётом чисел: "texture_2.png" идёт раньше "texture_10.png".
+// При равенстве по числам решает обычное сравнение строк, чтобы порядок был однозначным
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                    }
+                    digits.trim_start_matches('0').to_string()
+                };
+                let x_num = take_number(&mut a_chars);
+                let y_num = take_number(&mut b_chars);
+                let ordering = x_num.len().cmp(&y_num.len()).then_with(|| x_num.cmp(&y_num));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+pub fn generate_changelog(
+    diff: Option<&MapDiff>,
+    output_dir: &Path,
+    branch: &Branch,
+    lang_diffs: &[LangDiff],
+    render: &LangRenderOptions,
+    changelog: &ChangelogConfig,
+) -> Result<DiffStats, ChangelogError> {
+    let labels = changelog.labels();
+    let now = chrono::Local::now();
+    let title = match branch {
+        Branch::Live => now.format(labels.date).to_string(),
+        _ => format!("{} {}", labels.branch(branch), now.format(labels.date)),
+    };
+    // Изменения локализации всех веток из lang.branches публикуются в патчноуте основного сервера
+    let lang_diffs = (*branch == Branch::Live).then_some(lang_diffs);
+
+    // Каждый патчноут - отдельная страница patches/<дата_время>.html, index.html - их список
+    migrate_legacy_index(output_dir, changelog)?;
+    copy_branding_assets(output_dir, &changelog.branding)?;
+    let patches_dir = output_dir.join(PATCHES_DIR);
+    let stem = unique_page_stem(&patches_dir, &now.format("%Y-%m-%d_%H%M").to_string());
+    let page = format!("{}/{}.html", PATCHES_DIR, stem);
+    // Шаблоны changelog.ignore и одна сводка на все выходные файлы: страницу, Markdown, changes.json и ленту
+    // Без diff карты (изменилась только локализация) раздел файловой структуры заменяется пометкой
+    let diff = &diff.map_or_else(MapDiff::default, |diff| diff.without_ignored(&changelog.ignore));
+    let stats = diff.stats();
+    let content = PageContent {
+        lang_diffs,
+        render,
+        changelog,
+    };
+    write_changelog(diff, &stats, output_dir, &page, &title, &content)?;
+    // Markdown для форумов и GitHub Discussions пишется в том же проходе, что и HTML, поэтому они не расходятся
+    let markdown_path = output_dir.join(format!("changelog_{}.md", stem));
+    write_changelog_markdown(diff, &stats, &markdown_path, &title, &content)?;
+    let changes = ChangesJson::new(diff, &stats, branch, lang_diffs.unwrap_or_default());
+    write_atomic(&output_dir.join("changes.json"), serde_json::to_string_pretty(&changes)?.as_bytes())?;
+    update_patch_index(
+        output_dir,
+        branch,
+        PatchPage {
+            file: page.clone(),
+            title: format!("{} {}", changelog.title_prefix(), title),
+            generated: now.to_rfc3339(),
+            summary: Some(changes.summary),
+        },
+        changelog,
+    )?;
+
+    let site_page = match branch {
+        Branch::Live => page,
+        _ => format!("{}/{}", branch.env_dir_name(), page),
+    };
+    let link = site_link(&changelog.site_url, &site_page);
+    let text = generate_changelog_text(diff, &stats, &title, lang_diffs.unwrap_or_default(), changelog, &link);
+    write_atomic(&output_dir.join("changelog.txt"), text.as_bytes())?;
+    let telegram = format_telegram_message(diff, &stats, &title, lang_diffs.unwrap_or_default(), changelog, &link);
+    write_atomic(&output_dir.join("telegram.md"), telegram.as_bytes())?;
+    let bbcode = generate_changelog_bbcode(diff, &stats, &title, lang_diffs.unwrap_or_default(), changelog, &link);
+    write_atomic(&output_dir.join("changelog.bbcode"), bbcode.as_bytes())?;
+    let entry = FeedEntry {
+        id: format!("{}/{}", feed_id(branch), now.format("%Y%m%d_%H%M%S")),
+        title: format!("{} {}", changelog.title_prefix(), title),
+        link,
+        published: now.to_rfc3339(),
+        summary: feed_summary(&stats, lang_diffs.unwrap_or_default(), labels),
+    };
+    update_feed(output_dir, branch, entry, changelog)?;
+    Ok(stats)
+}
+
+// Папка страниц патчноутов внутри docs (и docs/<ветка>)
+const PATCHES_DIR: &str = "patches";
+// Список страниц для index.html; страницы не перечитываются, сводка берётся отсюда
+const PATCH_INDEX_FILE: &str = "index.json";
+
+/// Страница патчноута в docs/patches и её строка в списке index.html
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatchPage {
+    // Путь относительно папки с index.html
+    pub file: String,
+    pub title: String,
+    // Время генерации в RFC 3339
+    pub generated: String,
+    // Нет у страницы, перенесённой из прежнего index.html
+    pub summary: Option<ChangesSummary>,
+}
+
+// Имя страницы без расширения; если за эту минуту страница уже есть, добавляется счётчик: _2, _3...
+fn unique_page_stem(patches_dir: &Path, stem: &str) -> String {
+    let mut candidate = stem.to_string();
+    let mut counter = 2;
+    while patches_dir.join(format!("{}.html", candidate)).exists() {
+        candidate = format!("{}_{}", stem, counter);
+        counter += 1;
+    }
+    candidate
+}
+
+fn load_patch_index(output_dir: &Path) -> Vec<PatchPage> {
+    fs::read_to_string(output_dir.join(PATCHES_DIR).join(PATCH_INDEX_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// До страниц в patches/ патчноут писался прямо в index.html; такая страница переносится в patches/,
+// чтобы список её не затёр
+fn migrate_legacy_index(output_dir: &Path, changelog: &ChangelogConfig) -> Result<(), ChangelogError> {
+    let index = output_dir.join("index.html");
+    let index_path = output_dir.join(PATCHES_DIR).join(PATCH_INDEX_FILE);
+    if index_path.exists() || !index.exists() {
+        return Ok(());
+    }
+    let modified: chrono::DateTime<chrono::Local> = fs::metadata(&index)?.modified()?.into();
+    let patches_dir = output_dir.join(PATCHES_DIR);
+    fs::create_dir_all(&patches_dir)?;
+    let stem = unique_page_stem(&patches_dir, &modified.format("%Y-%m-%d_%H%M").to_string());
+    let file = format!("{}/{}.html", PATCHES_DIR, stem);
+    fs::rename(&index, output_dir.join(&file))?;
+    let page = PatchPage {
+        file,
+        title: format!("{} {}", changelog.title_prefix(), modified.format(changelog.labels().date)),
+        generated: modified.to_rfc3339(),
+        summary: None,
+    };
+    write_atomic(&index_path, serde_json::to_string_pretty(&[page])?.as_bytes())?;
+    Ok(())
+}
+
+/// Добавляет страницу в список и пересобирает index.html: все патчноуты ветки, новые сверху
+pub fn update_patch_index(
+    output_dir: &Path,
+    branch: &Branch,
+    page: PatchPage,
+    changelog: &ChangelogConfig,
+) -> Result<(), ChangelogError> {
+    let labels = changelog.labels();
+    let mut pages = load_patch_index(output_dir);
+    pages.retain(|p| p.file != page.file);
+    pages.push(page);
+    pages.sort_by(|a, b| b.generated.cmp(&a.generated).then_with(|| natural_cmp(&b.file, &a.file)));
+    let patches_dir = output_dir.join(PATCHES_DIR);
+    fs::create_dir_all(&patches_dir)?;
+    write_atomic(&patches_dir.join(PATCH_INDEX_FILE), serde_json::to_string_pretty(&pages)?.as_bytes())?;
+
+    let title = match branch {
+        Branch::Live => labels.patches.to_string(),
+        _ => format!("{}: {}", labels.patches, labels.branch(branch)),
+    };
+    let mut html_content = page_start(&title, None, changelog);
+    html_content.push_str(&format!(
+        "    <h1>{}</h1>\n    <div class=\"stats\">{}: {}</div>\n    <ul class=\"patch-index\">\n",
+        html_escape::encode_text(&title),
+        labels.total,
+        pages.len()
+    ));
+    for page in &pages {
+        let generated = chrono::DateTime::parse_from_rfc3339(&page.generated)
+            .map(|time| time.format(labels.date_time).to_string())
+            .unwrap_or_default();
+        let summary = match &page.summary {
+            Some(summary) => {
+                let mut text = format!("+{} ~{} -{}", summary.added, summary.modified, summary.deleted);
+                if summary.renamed > 0 {
+                    text.push_str(&format!(" »{}", summary.renamed));
+                }
+                if summary.lang_changes > 0 {
+                    text.push_str(&format!(", {}: {}", labels.lang.to_lowercase(), summary.lang_changes));
+                }
+                text
+            }
+            None => String::new(),
+        };
+        html_content.push_str(&format!(
+            r#"      <li><a href="{}">{}</a> <span class="patch-time">{}</span> <span class="patch-summary">{}</span></li>
+"#,
+            html_escape::encode_double_quoted_attribute(&page.file),
+            html_escape::encode_text(&page.title),
+            generated,
+            summary
+        ));
+    }
+    html_content.push_str("    </ul>\n");
+    html_content.push_str(&page_end(&changelog.branding));
+    write_atomic(&output_dir.join("index.html"), html_content.as_bytes())?;
+    Ok(())
+}
+
+/// Версия формата docs/changes.json; меняется, когда поля удаляются или меняют смысл
+pub const CHANGES_JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Содержимое docs/changes.json рядом с index.html: то же, что и на странице, для ботов и вики
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangesJson {
+    pub schema_version: u32,
+    // Время генерации в RFC 3339
+    pub generated: String,
+    // Папка ветки: live, ots или имя runtime
+    pub branch: String,
+    pub summary: ChangesSummary,
+    pub files: Vec<FileChangeRecord>,
+    pub lang: Vec<LangFileChanges>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangesSummary {
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub total_old: usize,
+    pub total_new: usize,
+    pub lang_changes: usize,
+    // Изменения, скрытые шаблонами changelog.ignore
+    #[serde(default)]
+    pub ignored: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChangeKind {
     Added,
     Modified,
+    // Хэш в карте сменился, а распакованный файл совпадает со старой версией
+    HashOnly,
     Deleted,
+    Renamed,
+}
+
+/// Изменённый файл карты; хэши - hex-строки, old_hash нет у added, new_hash - у deleted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChangeRecord {
+    pub kind: FileChangeKind,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+/// Изменения одного файла локализации в формате changes/<ветка>/<локаль>_changes.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangFileChanges {
+    pub file: String,
+    pub locale: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub changes: Vec<LangChangeRecord>,
+}
+
+impl ChangesJson {
+    pub fn new(diff: &MapDiff, stats: &DiffStats, branch: &Branch, lang_diffs: &[LangDiff]) -> Self {
+        let record = |kind, path: &str, old_hash: Option<String>, new_hash: Option<String>| FileChangeRecord {
+            kind,
+            path: path.to_string(),
+            old_path: None,
+            old_hash,
+            new_hash,
+        };
+        let mut files: Vec<FileChangeRecord> = diff
+            .added
+            .iter()
+            .map(|e| record(FileChangeKind::Added, &e.path, None, Some(e.hash.to_string())))
+            .chain(diff.modified.iter().map(|e| {
+                let kind = if e.content_unchanged { FileChangeKind::HashOnly } else { FileChangeKind::Modified };
+                record(kind, &e.path, Some(e.old_hash.to_string()), Some(e.new_hash.to_string()))
+            }))
+            .chain(diff.deleted.iter().map(|e| record(FileChangeKind::Deleted, &e.path, Some(e.hash.to_string()), None)))
+            .chain(diff.renamed.iter().map(|e| FileChangeRecord {
+                old_path: Some(e.old_path.clone()),
+                ..record(FileChangeKind::Renamed, &e.new_path, Some(e.hash.to_string()), Some(e.hash.to_string()))
+            }))
+            .collect();
+        files.sort_by(|a, b| natural_cmp(&a.path, &b.path));
+
+        let lang: Vec<LangFileChanges> = lang_diffs
+            .iter()
+            .filter(|d| !d.is_empty())
+            .map(|d| LangFileChanges {
+                file: d.file_name(),
+                locale: d.locale.clone(),
+                branch: d.branch.clone(),
+                changes: d.changes().into_iter().map(LangChangeRecord::from).collect(),
+            })
+            .collect();
+        ChangesJson {
+            schema_version: CHANGES_JSON_SCHEMA_VERSION,
+            generated: chrono::Local::now().to_rfc3339(),
+            branch: branch.env_dir_name().to_string(),
+            summary: ChangesSummary {
+                added: stats.added,
+                modified: stats.modified,
+                deleted: stats.deleted,
+                renamed: stats.renamed,
+                total_old: stats.total_old,
+                total_new: stats.total_new,
+                lang_changes: lang.iter().map(|l| l.changes.len()).sum(),
+                ignored: diff.ignored,
+            },
+            files,
+            lang,
+        }
+    }
+}
+
+// Записи ленты рядом с feed.xml: лента пересобирается из них, поэтому id прошлых записей не меняются
+const FEED_STATE_FILE: &str = "feed.json";
+// Сколько изменений локализации перечислить в описании записи ленты
+const FEED_SUMMARY_LANG_CHANGES: usize = 3;
+
+/// Запись ленты Atom feed.xml об одном патчноуте
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    // Постоянный tag: URI, по нему читалки отличают новые записи от уже прочитанных
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    // Время публикации в RFC 3339
+    pub published: String,
+    pub summary: String,
+}
+
+// Постоянный идентификатор ленты ветки; id записей строятся от него
+fn feed_id(branch: &Branch) -> String {
+    format!("tag:github.com,2025:BuildersSC/Krevetka/{}", branch.env_dir_name())
+}
+
+fn site_link(site_url: &str, page: &str) -> String {
+    format!("{}/{}", site_url.trim_end_matches('/'), page)
+}
+
+// Описание записи: счётчики изменений карты и первые изменения локализации
+fn feed_summary(stats: &DiffStats, lang_diffs: &[LangDiff], labels: &Labels) -> String {
+    let mut summary = format!("{}: {}", labels.files, stats);
+    let changes: Vec<LangChange> = lang_diffs.iter().flat_map(LangDiff::changes).collect();
+    if !changes.is_empty() {
+        let first: Vec<String> = changes
+            .iter()
+            .take(FEED_SUMMARY_LANG_CHANGES)
+            .map(|change| {
+                let symbol = match change {
+                    LangChange::Added { .. } => "+",
+                    LangChange::Modified { .. } => "~",
+                    LangChange::Removed { .. } => "-",
+                    LangChange::Renamed { .. } => "»",
+                };
+                format!("{}{}", symbol, change.key())
+            })
+            .collect();
+        let more = if changes.len() > first.len() { ", …" } else { "" };
+        let count = (labels.changes_count)(changes.len());
+        summary.push_str(&format!(". {}: {} ({}{})", labels.lang, count, first.join(", "), more));
+    }
+    summary
+}
+
+/// Добавляет запись в ленту output_dir/feed.xml и пересобирает её: новые записи сверху,
+/// не больше changelog.feed_entries (0 - все). Запись с тем же id заменяется, а не повторяется
+pub fn update_feed(
+    output_dir: &Path,
+    branch: &Branch,
+    entry: FeedEntry,
+    changelog: &ChangelogConfig,
+) -> Result<(), ChangelogError> {
+    let state_path = output_dir.join(FEED_STATE_FILE);
+    // Повреждённый список записей не должен навсегда ломать ленту: она начинается заново
+    let mut entries: Vec<FeedEntry> = fs::read_to_string(&state_path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    entries.retain(|e| e.id != entry.id);
+    entries.insert(0, entry);
+    if changelog.feed_entries > 0 {
+        entries.truncate(changelog.feed_entries);
+    }
+    write_atomic(&state_path, serde_json::to_string_pretty(&entries)?.as_bytes())?;
+    write_atomic(&output_dir.join("feed.xml"), render_feed(branch, &entries, changelog).as_bytes())?;
+    Ok(())
+}
+
+// Лента Atom (RFC 4287): обязательные id, title и updated у ленты и записей, автор на уровне ленты
+fn render_feed(branch: &Branch, entries: &[FeedEntry], changelog: &ChangelogConfig) -> String {
+    let (labels, site_url) = (changelog.labels(), &changelog.site_url);
+    let text = |value: &str| html_escape::encode_text(value).to_string();
+    let attribute = |value: &str| html_escape::encode_double_quoted_attribute(value).to_string();
+    let title = match branch {
+        Branch::Live => labels.feed_title.to_string(),
+        _ => format!("{} ({})", labels.feed_title, labels.branch(branch)),
+    };
+    let (page, feed) = match branch {
+        Branch::Live => (String::new(), "feed.xml".to_string()),
+        _ => (format!("{}/", branch.env_dir_name()), format!("{}/feed.xml", branch.env_dir_name())),
+    };
+    let updated = entries.first().map_or_else(|| chrono::Local::now().to_rfc3339(), |e| e.published.clone());
+
+    let mut xml = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>{}</id>
+  <title>{}</title>
+  <updated>{}</updated>
+  <author><name>Krevetka</name></author>
+  <link rel="self" href="{}"/>
+  <link rel="alternate" type="text/html" href="{}"/>
+"#,
+        text(&feed_id(branch)),
+        text(&title),
+        text(&updated),
+        attribute(&site_link(site_url, &feed)),
+        attribute(&site_link(site_url, &page))
+    );
+    for entry in entries {
+        xml.push_str(&format!(
+            r#"  <entry>
+    <id>{}</id>
+    <title>{}</title>
+    <link rel="alternate" type="text/html" href="{}"/>
+    <published>{}</published>
+    <updated>{}</updated>
+    <summary>{}</summary>
+  </entry>
+"#,
+            text(&entry.id),
+            text(&entry.title),
+            attribute(&entry.link),
+            text(&entry.published),
+            text(&entry.published),
+            text(&entry.summary)
+        ));
+    }
+    xml.push_str("</feed>\n");
+    xml
 }
 
-pub fn generate_changelog(old_entries: &[MapEntry], new_entries: &[MapEntry], output_dir: &Path) -> Result<(), MapError> {
+/// Патчноут для ручного сравнения двух карт, без раздела локализации
+pub fn generate_comparison_changelog(
+    diff: &MapDiff,
+    output_dir: &Path,
+    title: &str,
+    changelog: &ChangelogConfig,
+) -> Result<DiffStats, ChangelogError> {
+    let title = html_escape::encode_text(title);
     fs::create_dir_all(output_dir)?;
-    let timestamp = chrono::Local::now().format("%d.%m.%Y");
+    copy_branding_assets(output_dir, &changelog.branding)?;
+    let diff = &diff.without_ignored(&changelog.ignore);
+    let stats = diff.stats();
+    let content = PageContent {
+        lang_diffs: None,
+        render: &LangRenderOptions::default(),
+        changelog,
+    };
+    write_changelog(diff, &stats, output_dir, "index.html", &title, &content)?;
+    Ok(stats)
+}
+
+// Поиск по странице патчноута без внешних библиотек: скрывает файлы и строки локализации без совпадения,
+// разделы без совпадений и раскрывает разделы с ними. Поле поиска скрыто атрибутом hidden и показывается
+// только скриптом, поэтому без JS страница видна целиком
+const SEARCH_SCRIPT: &str = r#"    <script>
+        (function () {
+            var input = document.getElementById("search");
+            if (!input) {
+                return;
+            }
+            input.hidden = false;
+            var items = Array.prototype.slice.call(document.querySelectorAll(".file, .diff-line"));
+            var sections = Array.prototype.slice.call(document.querySelectorAll("details"));
+            // Файл ищется по полному пути: имя файла и подписи папок дерева над ним
+            items.forEach(function (item) {
+                var text = item.textContent.trim();
+                for (var parent = item.parentElement; parent; parent = parent.parentElement) {
+                    if (parent.matches("details.directory:not(.lang-group)")) {
+                        text = parent.querySelector("summary").firstChild.textContent.trim() + "/" + text;
+                    }
+                }
+                item.dataset.search = text.toLowerCase();
+            });
+            sections.forEach(function (section) {
+                section.dataset.open = section.open ? "1" : "";
+            });
+            input.addEventListener("input", function () {
+                var query = input.value.trim().toLowerCase();
+                items.forEach(function (item) {
+                    item.hidden = query !== "" && item.dataset.search.indexOf(query) === -1;
+                });
+                sections.forEach(function (section) {
+                    if (query === "") {
+                        section.hidden = false;
+                        section.open = section.dataset.open === "1";
+                        return;
+                    }
+                    var found = section.querySelector(".file:not([hidden]), .diff-line:not([hidden])") !== null;
+                    section.hidden = !found;
+                    section.open = found;
+                });
+            });
+        })();
+    </script>
+"#;
+
+// Поле поиска под сводкой; см. SEARCH_SCRIPT
+fn search_input(labels: &Labels) -> String {
+    format!(
+        "    <input type=\"search\" id=\"search\" class=\"search\" placeholder=\"{}\" hidden>\n",
+        html_escape::encode_double_quoted_attribute(labels.search_placeholder)
+    )
+}
+
+// Подвал страницы: картинка branding.logo со ссылкой и текстовые ссылки branding.footer_links
+fn page_end(branding: &BrandingConfig) -> String {
+    let mut footer = String::from("    <div class=\"footer\">\n");
+    if !branding.logo.is_empty() {
+        footer.push_str(&format!(
+            "        <a href=\"{}\" target=\"_blank\">\n            <img src=\"{}\" alt=\"{} Logo\">\n        </a>\n",
+            html_escape::encode_double_quoted_attribute(&branding.logo_url),
+            html_escape::encode_double_quoted_attribute(&branding.logo),
+            html_escape::encode_double_quoted_attribute(&branding.source_name)
+        ));
+    }
+    for link in &branding.footer_links {
+        footer.push_str(&format!(
+            "        <a href=\"{}\" target=\"_blank\">{}</a>\n",
+            html_escape::encode_double_quoted_attribute(&link.url),
+            html_escape::encode_text(&link.name)
+        ));
+    }
+    footer.push_str("    </div>\n</body>\n</html>");
+    footer
+}
+
+// Копирует картинки оформления (branding.logo, branding.pattern) из branding.assets_dir в папку со страницами,
+// если их там нет или они изменились: страницы ссылаются на них относительными путями
+fn copy_branding_assets(output_dir: &Path, branding: &BrandingConfig) -> io::Result<()> {
+    for file in [&branding.logo, &branding.pattern] {
+        if file.is_empty() || file.contains("://") {
+            continue;
+        }
+        let source = Path::new(&branding.assets_dir).join(file);
+        // Без исходника картинка могла быть положена в docs вручную
+        if !source.is_file() {
+            continue;
+        }
+        let target = output_dir.join(file);
+        let content = fs::read(&source)?;
+        if fs::read(&target).ok().as_ref() != Some(&content) {
+            fs::create_dir_all(target.parent().unwrap_or(output_dir))?;
+            fs::write(&target, content)?;
+        }
+    }
+    Ok(())
+}
 
-    let mut html_content = format!(
+// Начало HTML страницы со стилями, общее для всех отчётов. base - адрес, от которого считаются
+// относительные ссылки (картинки, полные diff), для страниц во вложенных папках
+fn page_start(title: &str, base: Option<&str>, changelog: &ChangelogConfig) -> String {
+    let (branding, labels) = (&changelog.branding, changelog.labels());
+    let base = base.map_or(String::new(), |base| {
+        format!("\n    <base href=\"{}\">", html_escape::encode_double_quoted_attribute(base))
+    });
+    let pattern = if branding.pattern.is_empty() {
+        String::new()
+    } else {
+        format!("background-image: url('{}');", branding.pattern.replace(['\'', '\\'], ""))
+    };
+    format!(
         r#"<!DOCTYPE html>
-<html lang="ru">
+<html lang="{}">
 <head>
-    <meta charset="UTF-8">
+    <meta charset="UTF-8">{}
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <meta name="description" content="Изменения в файлах ассетов игры">
-    <title>Патчноут {}</title>
+    <meta name="description" content="{}">
+    <title>{}</title>
     <style>
         body {{
             background-color: #1e1e1e;
@@ -41,7 +803,7 @@ pub fn generate_changelog(old_entries: &[MapEntry], new_entries: &[MapEntry], ou
             left: 0;
             width: 100%;
             height: 100%;
-            background-image: url('pattern_anti_spectrum.png');
+            {}
             background-repeat: repeat;
             background-size: 200px;
             opacity: 0.03;
@@ -70,6 +832,16 @@ pub fn generate_changelog(old_entries: &[MapEntry], new_entries: &[MapEntry], ou
         .added {{ color: #a0d468; }}
         .deleted {{ color: #ff6b6b; }}
         .modified {{ color: #ffd700; }}
+        .renamed {{ color: #6fc3df; }}
+        .hash-only {{ color: #ffd700; opacity: 0.5; }}
+        .renames {{
+            margin-top: 20px;
+            position: relative;
+            z-index: 1;
+        }}
+        .renames .file {{
+            margin-left: 16px;
+        }}
         .lang-changes {{
             margin-top: 30px;
             padding: 20px;
@@ -85,6 +857,127 @@ pub fn generate_changelog(old_entries: &[MapEntry], new_entries: &[MapEntry], ou
             border-radius: 4px;
             background: rgba(0, 0, 0, 0.2);
         }}
+        .old-value {{
+            color: #ff6b6b;
+            text-decoration: line-through;
+            opacity: 0.7;
+        }}
+        .new-value {{
+            color: #a0d468;
+            text-decoration: none;
+        }}
+        .placeholder-mismatch {{
+            border-left: 3px solid #ff9800;
+        }}
+        .placeholder-warning {{
+            color: #ff9800;
+            font-weight: bold;
+        }}
+        .revert-badge {{
+            background: #5c6bc0;
+            color: #fff;
+            border-radius: 3px;
+            padding: 0 4px;
+            margin-left: 6px;
+            font-size: 0.85em;
+        }}
+        .word-removed {{
+            color: #ff6b6b;
+            text-decoration: line-through;
+        }}
+        .word-added {{
+            color: #a0d468;
+        }}
+        .color-0 {{ color: #000000; }}
+        .color-1 {{ color: #0000aa; }}
+        .color-2 {{ color: #00aa00; }}
+        .color-3 {{ color: #00aaaa; }}
+        .color-4 {{ color: #aa0000; }}
+        .color-5 {{ color: #aa00aa; }}
+        .color-6 {{ color: #ffaa00; }}
+        .color-7 {{ color: #aaaaaa; }}
+        .color-8 {{ color: #555555; }}
+        .color-9 {{ color: #5555ff; }}
+        .color-a {{ color: #55ff55; }}
+        .color-b {{ color: #55ffff; }}
+        .color-c {{ color: #ff5555; }}
+        .color-d {{ color: #ff55ff; }}
+        .color-e {{ color: #ffff55; }}
+        .color-f {{ color: #ffffff; }}
+        .stats {{
+            opacity: 0.5;
+            margin-bottom: 16px;
+        }}
+        .summary {{
+            font-size: 16px;
+            margin-bottom: 16px;
+        }}
+        .no-changes {{
+            opacity: 0.5;
+            font-style: italic;
+        }}
+        .badges {{
+            font-size: 13px;
+            margin-left: 8px;
+        }}
+        .deeper {{
+            font-style: italic;
+        }}
+        .search {{
+            width: 100%;
+            max-width: 480px;
+            padding: 6px 10px;
+            margin-bottom: 16px;
+            font-size: 16px;
+            color: inherit;
+            background: transparent;
+            border: 1px solid #555;
+            border-radius: 4px;
+        }}
+        .lang-file > summary h2 {{
+            display: inline;
+        }}
+        .lang-group .diff-line {{
+            margin-left: 16px;
+        }}
+        .group-count {{
+            opacity: 0.5;
+        }}
+        .patch-index li {{
+            margin-bottom: 6px;
+        }}
+        .patch-time,
+        .patch-summary {{
+            opacity: 0.5;
+            margin-left: 8px;
+        }}
+        .lang-category > .category {{
+            font-size: 18px;
+            margin-top: 8px;
+        }}
+        .locale-gaps {{
+            border-collapse: collapse;
+            margin-left: 16px;
+        }}
+        .locale-gaps th, .locale-gaps td {{
+            padding: 4px 12px;
+            border-bottom: 1px solid #444;
+            text-align: left;
+        }}
+        .truncated-note {{
+            margin: 12px 16px;
+            padding: 8px 12px;
+            border-left: 3px solid #ff9800;
+            background: rgba(255, 152, 0, 0.1);
+        }}
+        .truncated-note a {{
+            color: #ff9800;
+        }}
+        .filtered-note {{
+            margin-left: 16px;
+            opacity: 0.5;
+            font-style: italic;
+        }}
         .no-changes {{
             text-align: center;
             padding: 20px;
@@ -125,150 +1018,2071 @@ pub fn generate_changelog(old_entries: &[MapEntry], new_entries: &[MapEntry], ou
     </style>
 </head>
 <body>
-    <h1>Патчноут {}</h1>
-    <h2>Изменения файловой структуры</h2>
-    <h3>Источник: <a href="https://github.com/Art3mLapa" target="_blank">Krevetka</a></h3>
-    <div class="changes">
 "#,
-        timestamp, timestamp
-    );
+        labels.html_lang, base, labels.page_description, title, pattern
+    )
+}
 
-    let mut changes: std::collections::BTreeMap<String, Vec<(String, ChangeType)>> = std::collections::BTreeMap::new();
-    let old_map: std::collections::HashMap<_, _> = old_entries.iter().map(|e| (&e.path, &e.hash)).collect();
-    let new_map: std::collections::HashMap<_, _> = new_entries.iter().map(|e| (&e.path, &e.hash)).collect();
+// Что выводить на странице патчноута и в Markdown, общее для обоих форматов
+struct PageContent<'a> {
+    lang_diffs: Option<&'a [LangDiff]>,
+    render: &'a LangRenderOptions,
+    changelog: &'a ChangelogConfig,
+}
 
-    for (path, new_hash) in new_map.iter() {
-        let change_type = match old_map.get(path) {
-            Some(old_hash) if old_hash != new_hash => ChangeType::Modified,
-            None => ChangeType::Added,
-            _ => continue,
-        };
+// Хэши из карты для сверки с дампами ассетов: старый и новый у изменённого файла, один у остальных
+fn modified_hashes(entry: &ModifiedEntry) -> String {
+    format!("old: {:x} → new: {:x}", entry.old_hash, entry.new_hash)
+}
+
+// Изменённые пути по папкам: папка -> (имя файла, вид изменения, хэши)
+type DirChanges = std::collections::BTreeMap<String, Vec<(String, ChangeType, String)>>;
+
+fn changes_by_dir(diff: &MapDiff) -> DirChanges {
+    let mut changes = DirChanges::new();
+    let changed_paths = diff
+        .added
+        .iter()
+        .map(|e| (&e.path, ChangeType::Added, format!("{:x}", e.hash)))
+        .chain(diff.modified.iter().map(|e| {
+            let change_type = if e.content_unchanged {
+                ChangeType::HashOnly
+            } else {
+                ChangeType::Modified
+            };
+            (&e.path, change_type, modified_hashes(e))
+        }))
+        .chain(diff.deleted.iter().map(|e| (&e.path, ChangeType::Deleted, format!("{:x}", e.hash))));
+
+    for (path, change_type, hashes) in changed_paths {
         let (dir, file) = match path.rfind('/') {
             Some(idx) => (path[..idx].to_string(), path[idx + 1..].to_string()),
             None => (String::new(), path.to_string()),
         };
-        changes.entry(dir).or_insert_with(Vec::new).push((file, change_type));
+        changes.entry(dir).or_default().push((file, change_type, hashes));
     }
+    changes
+}
 
-    for path in old_map.keys() {
-        if !new_map.contains_key(path) {
-            let (dir, file) = match path.rfind('/') {
-                Some(idx) => (path[..idx].to_string(), path[idx + 1..].to_string()),
-                None => (String::new(), path.to_string()),
-            };
-            changes.entry(dir).or_insert_with(Vec::new).push((file, ChangeType::Deleted));
+// Папка в дереве патчноута: её изменённые файлы, подпапки и число изменений во всём поддереве
+#[derive(Default)]
+struct DirNode {
+    files: Vec<(String, ChangeType, String)>,
+    children: Vec<(String, DirNode)>,
+    added: usize,
+    modified: usize,
+    deleted: usize,
+}
+
+impl DirNode {
+    fn build(changes: DirChanges) -> DirNode {
+        let mut root = DirNode::default();
+        for (path, files) in changes {
+            let mut node = &mut root;
+            for part in path.split('/').filter(|s| !s.is_empty()) {
+                let index = match node.children.iter().position(|(name, _)| name == part) {
+                    Some(index) => index,
+                    None => {
+                        node.children.push((part.to_string(), DirNode::default()));
+                        node.children.len() - 1
+                    }
+                };
+                node = &mut node.children[index].1;
+            }
+            node.files.extend(files);
         }
+        root.aggregate();
+        root
     }
 
-    let mut dir_tree: std::collections::BTreeMap<String, Vec<(String, String, ChangeType)>> = std::collections::BTreeMap::new();
-    for (path, files) in changes {
-        let parts = path.split('/').filter(|s| !s.is_empty()).map(String::from).collect::<Vec<_>>();
-        let mut current_path = String::new();
-        for part in &parts {
-            let new_path = if current_path.is_empty() {
-                part.to_string()
-            } else {
-                format!("{}/{}", current_path, part)
-            };
-            dir_tree.entry(new_path.clone()).or_insert_with(Vec::new);
-            current_path = new_path;
+    // Сортирует файлы и подпапки и считает изменения снизу вверх: сначала подпапки, затем свои файлы
+    fn aggregate(&mut self) {
+        self.files.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+        self.children.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+        let (mut added, mut modified, mut deleted) = (0, 0, 0);
+        for (_, child) in &mut self.children {
+            child.aggregate();
+            added += child.added;
+            modified += child.modified;
+            deleted += child.deleted;
+        }
+        for (_, change_type, _) in &self.files {
+            match change_type {
+                ChangeType::Added => added += 1,
+                ChangeType::Modified | ChangeType::HashOnly => modified += 1,
+                ChangeType::Deleted => deleted += 1,
+                ChangeType::Renamed => {}
+            }
+        }
+        (self.added, self.modified, self.deleted) = (added, modified, deleted);
+    }
+
+    // Ненулевые счётчики изменений поддерева: (вид изменения, `+12`)
+    fn counts(&self) -> Vec<(ChangeType, String)> {
+        [
+            (ChangeType::Added, self.added),
+            (ChangeType::Modified, self.modified),
+            (ChangeType::Deleted, self.deleted),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(change_type, count)| (change_type.clone(), format!("{}{}", change_markup(change_type).1, count)))
+        .collect()
+    }
+
+    // Счётчики изменений поддерева для свёрнутого раздела: `+12 ~3 -1`, нулевые не показываются
+    fn badges(&self) -> String {
+        let badges: Vec<String> = self
+            .counts()
+            .into_iter()
+            .map(|(change_type, count)| format!("<span class=\"{}\">{}</span>", change_markup(&change_type).0, count))
+            .collect();
+        format!("<span class=\"badges\">{}</span>", badges.join(" "))
+    }
+
+    // Подпапка с подписью, в которую свёрнута цепочка папок без файлов и с единственной подпапкой
+    fn collapsed_children(&self) -> impl Iterator<Item = (String, &DirNode)> {
+        self.children.iter().map(|(name, child)| {
+            let mut label = name.clone();
+            let mut node = child;
+            while let ([(name, only_child)], true) = (node.children.as_slice(), node.files.is_empty()) {
+                label = format!("{}/{}", label, name);
+                node = only_child;
+            }
+            (label, node)
+        })
+    }
+
+    // Дерево для текстового патчноута: файлы и подпапки со счётчиками до глубины max_depth,
+    // содержимое более глубоких папок видно только по счётчикам
+    fn render_text(&self, level: usize, max_depth: usize, lines: &mut Vec<String>) {
+        if level >= max_depth {
+            return;
+        }
+        let indent = "  ".repeat(level);
+        for (name, change_type, _) in &self.files {
+            lines.push(format!("{}{} {}", indent, change_markup(change_type).1, name));
         }
-        if let Some(entries) = dir_tree.get_mut(&path) {
-            entries.extend(files.iter().map(|(name, change_type)| (name.clone(), path.clone(), change_type.clone())));
+        for (label, node) in self.collapsed_children() {
+            let counts: Vec<String> = node.counts().into_iter().map(|(_, count)| count).collect();
+            lines.push(format!("{}{}/ {}", indent, label, counts.join(" ")));
+            node.render_text(level + 1, max_depth, lines);
         }
     }
 
-    fn generate_html(
-        path: &str,
-        dir_tree: &std::collections::BTreeMap<String, Vec<(String, String, ChangeType)>>,
-        html: &mut String,
-        indent: usize,
-    ) {
-        let indent_str = " ".repeat(indent * 2);
-        if !path.is_empty() {
+    // Файлы папки path (уровень level, корень - 0) и её подпапки. Цепочка папок без файлов и с единственной
+    // подпапкой сворачивается в один узел `assets/stalker/items`, как в дереве файлов GitHub. Подпапки глубже
+    // changelog.max_depth не раскрываются: вместо них одна строка со счётчиками поддерева и полным путём
+    // в подсказке, чтобы страница с глубоким деревом не тормозила на телефонах
+    fn render_children(&self, path: &str, html: &mut String, level: usize, changelog: &ChangelogConfig) {
+        let indent_str = tree_indent(level);
+        for (name, change_type, hashes) in &self.files {
+            let (html_class, symbol) = change_markup(change_type);
+            let title = if changelog.hide_hashes { String::new() } else { format!(" title=\"{}\"", hashes) };
             html.push_str(&format!(
-                "{}<details class=\"directory\" open>\n{}  <summary class=\"name\">{}</summary>\n",
+                "{}  <div class=\"file {}\"{}>\n{}    {} {}\n{}  </div>\n",
                 indent_str,
+                html_class,
+                title,
                 indent_str,
-                path.split('/').last().unwrap_or(path)
+                symbol,
+                html_escape::encode_text(name),
+                indent_str
             ));
-            if let Some(files) = dir_tree.get(path) {
-                if !files.is_empty() {
-                    html.push_str(&format!("{}  <div class=\"path\">{}</div>\n", indent_str, path));
-                }
-            }
         }
 
-        if let Some(files) = dir_tree.get(path) {
-            for (name, _, change_type) in files {
-                let (html_class, symbol) = match change_type {
-                    ChangeType::Added => ("added", "+"),
-                    ChangeType::Modified => ("modified", "~"),
-                    ChangeType::Deleted => ("deleted", "-"),
-                };
+        for (label, node) in self.collapsed_children() {
+            let child_path = if path.is_empty() { label.clone() } else { format!("{}/{}", path, label) };
+            if changelog.max_depth > 0 && level >= changelog.max_depth {
                 html.push_str(&format!(
-                    "{}  <div class=\"file {}\">\n{}    {} {}\n{}  </div>\n",
-                    indent_str, html_class, indent_str, symbol, name, indent_str
+                    "{}<div class=\"file deeper\" title=\"{}\">{}/ {}</div>\n",
+                    tree_indent(level + 1),
+                    html_escape::encode_double_quoted_attribute(&child_path),
+                    html_escape::encode_text(&label),
+                    node.badges()
                 ));
+            } else {
+                node.render(&child_path, &label, html, level + 1, changelog);
             }
         }
+    }
 
-        let current_prefix = if path.is_empty() { String::new() } else { format!("{}/", path) };
-        let subdirs: Vec<_> = dir_tree
-            .keys()
-            .filter(|k| k.starts_with(&current_prefix) && *k != path && k[current_prefix.len()..].split('/').count() == 1)
-            .collect();
-        for subdir in subdirs {
-            generate_html(subdir, dir_tree, html, if path.is_empty() { 0 } else { indent + 2 });
+    // Папки в спойлерах для форума. Спойлеры вкладываются не глубже max_depth, содержимое более глубоких
+    // папок выводится списком внутри последнего спойлера, с путём от него
+    fn render_bbcode(&self, prefix: &str, level: usize, max_depth: usize, out: &mut String) {
+        for (name, change_type, _) in &self.files {
+            out.push_str(&format!(
+                "[color={}]{}[/color] {}\n",
+                change_color(change_type),
+                change_markup(change_type).1,
+                bb_plain(&format!("{}{}", prefix, name))
+            ));
+        }
+        for (label, node) in self.collapsed_children() {
+            if level < max_depth {
+                let counts: Vec<String> = node.counts().into_iter().map(|(_, count)| count).collect();
+                out.push_str(&format!("[spoiler=\"{}/ {}\"]\n", bb_attribute(&label), counts.join(" ")));
+                node.render_bbcode("", level + 1, max_depth, out);
+                out.push_str("[/spoiler]\n");
+            } else {
+                node.render_bbcode(&format!("{}{}/", prefix, label), level, max_depth, out);
+            }
         }
+    }
 
-        if !path.is_empty() {
-            html.push_str(&format!("{}</details>\n", indent_str));
+    fn render(&self, path: &str, label: &str, html: &mut String, level: usize, changelog: &ChangelogConfig) {
+        let indent_str = tree_indent(level);
+        html.push_str(&format!(
+            "{}<details class=\"directory\" open>\n{}  <summary class=\"name\">{} {}</summary>\n",
+            indent_str,
+            indent_str,
+            html_escape::encode_text(label),
+            self.badges()
+        ));
+        if !self.files.is_empty() {
+            html.push_str(&format!("{}  <div class=\"path\">{}</div>\n", indent_str, html_escape::encode_text(path)));
         }
+        self.render_children(path, html, level, changelog);
+        html.push_str(&format!("{}</details>\n", indent_str));
     }
+}
 
-    let mut tree_html = String::new();
-    generate_html("", &dir_tree, &mut tree_html, 0);
-    html_content.push_str(&tree_html);
+// Отступ разметки папки уровня level: папки верхнего уровня без отступа, каждая вложенная - на 4 пробела глубже
+fn tree_indent(level: usize) -> String {
+    " ".repeat(level.saturating_sub(1) * 4)
+}
 
-    html_content.push_str(
-        r#"</div>
-    <h2>Изменения в файле локализации</h2>
-    <div class="lang-changes">
-"#,
-    );
+/// Текстовый патчноут для объявлений в Discord: сводка, дерево папок до changelog.text_depth и первые
+/// changelog.text_lang_changes изменений локализации. Не длиннее changelog.text_limit символов: лишнее
+/// отрезается по строкам, и в конце добавляется ссылка full_url на полную версию
+pub fn generate_changelog_text(
+    diff: &MapDiff,
+    stats: &DiffStats,
+    title: &str,
+    lang_diffs: &[LangDiff],
+    changelog: &ChangelogConfig,
+    full_url: &str,
+) -> String {
+    let labels = changelog.labels();
+    let mut text = format!("**{} {}**\n", labels.patch, title);
+    if stats.is_empty() {
+        text.push_str(&format!("{}\n", labels.structure_unchanged));
+    } else {
+        text.push_str(&format!("{}: {}, {}: {}\n", labels.files, stats, labels.top_level_dirs, stats.directories));
+    }
+    if !lang_diffs.is_empty() {
+        text.push_str(&format!("{}: {}\n", labels.lang_keys, LangEntryCounts::sum(lang_diffs)));
+    }
 
-    let diff_path = std::path::PathBuf::from("changes").join("lang_changes.diff");
-    if diff_path.exists() {
-        let diff_content = fs::read_to_string(&diff_path)?;
-        for line in diff_content.lines() {
-            let (class, content) = match line.chars().next() {
-                Some('+') => ("added", &line[1..]),
-                Some('-') => ("deleted", &line[1..]),
-                Some('~') => ("modified", &line[1..]),
-                _ => ("", line),
-            };
-            html_content.push_str(&format!(
-                r#"<div class="diff-line {}">{}</div>"#,
-                class,
-                html_escape::encode_text(&content)
-            ));
+    let mut tree = Vec::new();
+    DirNode::build(changes_by_dir(diff)).render_text(0, changelog.text_depth, &mut tree);
+    if !tree.is_empty() {
+        text.push_str(&format!("```\n{}\n```\n", tree.join("\n")));
+    }
+    if diff.ignored > 0 {
+        text.push_str(&format!("{}\n", (labels.hidden_by_filters)(diff.ignored)));
+    }
+
+    let mut budget = changelog.text_lang_changes;
+    for lang_diff in lang_diffs.iter().filter(|d| !d.is_empty()) {
+        if budget == 0 {
+            break;
         }
+        let changes = lang_diff.changes();
+        let shown = changes.len().min(budget);
+        budget -= shown;
+        let counts = LangEntryCounts::of(lang_diff);
+        text.push_str(&format!("\n{} {}: {}\n", labels.lang, lang_section_title(lang_diff, labels), counts));
+        text.push_str(&format!("```diff\n{}```\n", format_lang_changes(&changes[..shown])));
+        if shown < changes.len() {
+            text.push_str(&format!("{}\n", (labels.more_changes)(changes.len() - shown)));
+        }
+    }
+    truncate_text(&text, changelog.text_limit, &format!("…\n{}: {}", labels.full_version, full_url))
+}
+
+// Имена с [ ] оборачиваются в [plain], чтобы форум не принял их за теги
+fn bb_plain(text: &str) -> String {
+    if text.contains(['[', ']']) {
+        format!("[plain]{}[/plain]", text)
     } else {
-        html_content.push_str(r#"<div class="no-changes">Изменений в локализации не обнаружено</div>"#);
-    }
-
-    html_content.push_str(
-        r#"</div>
-    <div class="footer">
-        <a href="https://github.com/BuildersSC/Krevetka" target="_blank">
-            <img src="icon.png" alt="Krevetka Logo">
-        </a>
-    </div>
-</body>
-</html>"#,
-    );
+        text.to_string()
+    }
+}
 
-    fs::write(output_dir.join("index.html"), html_content)?;
-    Ok(())
+// Заголовок спойлера в кавычках: кавычки и скобки внутри него ломают разбор тега
+fn bb_attribute(text: &str) -> String {
+    text.replace('"', "'").replace('[', "(").replace(']', ")")
+}
+
+fn bb_counts(counts: &[(ChangeType, String)]) -> String {
+    let counts: Vec<String> = counts
+        .iter()
+        .map(|(change_type, count)| format!("[color={}]{}[/color]", change_color(change_type), count))
+        .collect();
+    counts.join(" ")
+}
+
+/// Патчноут для форума EXBO в BBCode: сводка, папки в спойлерах с цветными отметками изменений,
+/// изменения локализации и ссылка url на опубликованную страницу
+pub fn generate_changelog_bbcode(
+    diff: &MapDiff,
+    stats: &DiffStats,
+    title: &str,
+    lang_diffs: &[LangDiff],
+    changelog: &ChangelogConfig,
+    url: &str,
+) -> String {
+    let labels = changelog.labels();
+    let mut bbcode = format!("[size=6][b]{} {}[/b][/size]\n", labels.patch, title);
+    if stats.is_empty() {
+        bbcode.push_str(&format!("{}\n", labels.structure_unchanged));
+    } else {
+        let counts = [
+            (ChangeType::Added, format!("+{}", stats.added)),
+            (ChangeType::Modified, format!("~{}", stats.modified)),
+            (ChangeType::Deleted, format!("-{}", stats.deleted)),
+            (ChangeType::Renamed, format!("»{}", stats.renamed)),
+        ];
+        let shown = if stats.renamed > 0 { &counts[..] } else { &counts[..3] };
+        bbcode.push_str(&format!(
+            "{}: {}, {}: {}\n",
+            labels.files,
+            bb_counts(shown),
+            labels.top_level_dirs,
+            stats.directories
+        ));
+    }
+    if !lang_diffs.is_empty() {
+        bbcode.push_str(&format!("{}: {}\n", labels.lang_keys, LangEntryCounts::sum(lang_diffs)));
+    }
+    bbcode.push('\n');
+
+    DirNode::build(changes_by_dir(diff)).render_bbcode("", 0, changelog.bbcode_depth, &mut bbcode);
+    if !diff.renamed.is_empty() {
+        let mut renamed: Vec<_> = diff.renamed.iter().collect();
+        renamed.sort_by(|a, b| natural_cmp(&a.new_path, &b.new_path));
+        bbcode.push_str(&format!("[spoiler=\"{} ({})\"]\n", labels.renamed_files, renamed.len()));
+        for renamed in renamed {
+            bbcode.push_str(&format!(
+                "[color={}]»[/color] {} → {}\n",
+                change_color(&ChangeType::Renamed),
+                bb_plain(&renamed.old_path),
+                bb_plain(&renamed.new_path)
+            ));
+        }
+        bbcode.push_str("[/spoiler]\n");
+    }
+    if diff.ignored > 0 {
+        bbcode.push_str(&format!("[i]{}[/i]\n", (labels.hidden_by_filters)(diff.ignored)));
+    }
+
+    for lang_diff in lang_diffs.iter().filter(|d| !d.is_empty()) {
+        bbcode.push_str(&format!(
+            "[spoiler=\"{} {}: {}\"]\n[code]\n{}[/code]\n[/spoiler]\n",
+            labels.lang,
+            bb_attribute(&lang_section_title(lang_diff, labels)),
+            LangEntryCounts::of(lang_diff),
+            format_lang_changes(&lang_diff.changes())
+        ));
+    }
+
+    bbcode.push_str(&format!("\n[url={}]{}[/url]\n", url, labels.full_version_on_site));
+    bbcode
+}
+
+// Предел длины сообщения в Telegram
+const TELEGRAM_MESSAGE_LIMIT: usize = 4096;
+
+// Символы, которые в Telegram MarkdownV2 вне блоков кода нужно экранировать обратной косой чертой
+const TELEGRAM_SPECIAL: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+fn tg_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if TELEGRAM_SPECIAL.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Внутри `кода` и ```блоков``` экранируются только ` и \
+fn tg_escape_code(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+/// Сообщение для Telegram-канала в разметке MarkdownV2: жирная сводка, списки файлов по видам изменений,
+/// изменения локализации блоком кода и ссылка на полную страницу url. Длиннее 4096 символов не бывает:
+/// лишние строки отрезаются, ссылка остаётся
+pub fn format_telegram_message(
+    diff: &MapDiff,
+    stats: &DiffStats,
+    title: &str,
+    lang_diffs: &[LangDiff],
+    changelog: &ChangelogConfig,
+    url: &str,
+) -> String {
+    let labels = changelog.labels();
+    let mut message = format!("*{}*\n", tg_escape(&format!("{} {}", labels.patch, title)));
+    if stats.is_empty() {
+        message.push_str(&format!("*{}*\n", tg_escape(labels.structure_unchanged)));
+    } else {
+        message.push_str(&format!("*{}*\n", tg_escape(&format!("{}: {}", labels.files, stats))));
+    }
+    if !lang_diffs.is_empty() {
+        let counts = LangEntryCounts::sum(lang_diffs);
+        message.push_str(&format!("{}\n", tg_escape(&format!("{}: {}", labels.lang_keys, counts))));
+    }
+
+    let mut push_list = |heading: &str, items: Vec<String>| {
+        if items.is_empty() {
+            return;
+        }
+        message.push_str(&format!("\n*{}*\n", tg_escape(&format!("{} ({})", heading, items.len()))));
+        for item in items {
+            message.push_str(&format!("• {}\n", item));
+        }
+    };
+    let code = |path: &str| format!("`{}`", tg_escape_code(path));
+    let sorted = |mut paths: Vec<&str>| {
+        paths.sort_by(|a, b| natural_cmp(a, b));
+        paths.into_iter().map(code).collect::<Vec<_>>()
+    };
+    push_list(labels.added_heading, sorted(diff.added.iter().map(|e| e.path.as_str()).collect()));
+    push_list(labels.modified_heading, sorted(diff.modified.iter().map(|e| e.path.as_str()).collect()));
+    push_list(labels.deleted_heading, sorted(diff.deleted.iter().map(|e| e.path.as_str()).collect()));
+    let mut renamed: Vec<_> = diff.renamed.iter().collect();
+    renamed.sort_by(|a, b| natural_cmp(&a.new_path, &b.new_path));
+    push_list(
+        labels.moved_heading,
+        renamed.iter().map(|e| format!("{} → {}", code(&e.old_path), code(&e.new_path))).collect(),
+    );
+    if diff.ignored > 0 {
+        message.push_str(&format!("{}\n", tg_escape(&(labels.hidden_by_filters)(diff.ignored))));
+    }
+
+    for lang_diff in lang_diffs.iter().filter(|d| !d.is_empty()) {
+        let counts = LangEntryCounts::of(lang_diff);
+        let heading = format!("{} {}: {}", labels.lang, lang_section_title(lang_diff, labels), counts);
+        message.push_str(&format!("\n*{}*\n", tg_escape(&heading)));
+        message.push_str(&format!("```diff\n{}```\n", tg_escape_code(&format_lang_changes(&lang_diff.changes()))));
+    }
+
+    // Ссылка: в адресе экранируются только ) и \
+    let link = format!("[{}]({})", tg_escape(labels.full_version), url.replace('\\', "\\\\").replace(')', "\\)"));
+    let full = format!("{}\n{}", message, link);
+    if full.chars().count() <= TELEGRAM_MESSAGE_LIMIT {
+        return full;
+    }
+    truncate_text(&message, TELEGRAM_MESSAGE_LIMIT, &format!("{}\n{}", tg_escape("…"), link))
+}
+
+// Обрезает текст по целым строкам, чтобы вместе с хвостом уложиться в limit символов. Блок кода, оборванный
+// на середине, закрывается, иначе Discord покажет остаток сообщения моноширинным
+fn truncate_text(text: &str, limit: usize, tail: &str) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    const FENCE_END: &str = "```\n";
+    let budget = limit.saturating_sub(tail.chars().count() + FENCE_END.len());
+    let mut truncated = String::new();
+    let (mut length, mut in_code) = (0, false);
+    for line in text.lines() {
+        let line_length = line.chars().count() + 1;
+        if length + line_length > budget {
+            break;
+        }
+        truncated.push_str(line);
+        truncated.push('\n');
+        length += line_length;
+        if line.starts_with("```") {
+            in_code = !in_code;
+        }
+    }
+    if in_code {
+        truncated.push_str(FENCE_END);
+    }
+    truncated.push_str(tail);
+    truncated
+}
+
+// Пишет страницу патчноута в output_dir/page. Полные diff локализации кладутся в output_dir, и ссылки
+// со страницы во вложенной папке считаются от него
+fn write_changelog(
+    diff: &MapDiff,
+    stats: &DiffStats,
+    output_dir: &Path,
+    page: &str,
+    title: &str,
+    content: &PageContent,
+) -> Result<(), ChangelogError> {
+    let lang_diffs = content.lang_diffs;
+    let page_path = output_dir.join(page);
+    fs::create_dir_all(page_path.parent().unwrap_or(output_dir))?;
+
+    let base = "../".repeat(page.matches('/').count());
+    let changelog = content.changelog;
+    let (branding, labels) = (&changelog.branding, changelog.labels());
+    let heading = format!("{} {}", html_escape::encode_text(changelog.title_prefix()), title);
+    let mut html_content = page_start(&heading, (!base.is_empty()).then_some(base.as_str()), changelog);
+    html_content.push_str(&format!("    <h1>{}</h1>\n{}", heading, summary_html(stats, lang_diffs, labels)));
+    html_content.push_str(&search_input(labels));
+    // Пустой раздел с деревом и счётчиками +0 ~0 -0 выглядит как ошибка, поэтому вместо него только пометка
+    if !stats.is_empty() {
+        html_content.push_str(&format!(
+            r#"    <h2>{}: {}</h2>
+    <div class="stats">{}: {} → {}</div>
+"#,
+            labels.structure_changes, stats, labels.map_entries, stats.total_old, stats.total_new
+        ));
+    }
+    html_content.push_str(&format!(
+        r#"    <h3>{}: <a href="{}" target="_blank">{}</a></h3>
+    <div class="changes">
+"#,
+        labels.source,
+        html_escape::encode_double_quoted_attribute(&branding.source_url),
+        html_escape::encode_text(&branding.source_name)
+    ));
+    let show_hashes = !changelog.hide_hashes;
+    if stats.is_empty() {
+        html_content.push_str(&format!("<div class=\"no-changes\">{}</div>\n", labels.structure_unchanged));
+    } else {
+        DirNode::build(changes_by_dir(diff)).render_children("", &mut html_content, 0, changelog);
+    }
+    if diff.filtered > 0 {
+        html_content.push_str(&format!(
+            "<div class=\"filtered-note\">{}</div>\n",
+            (labels.filtered_paths)(diff.filtered)
+        ));
+    }
+    if diff.ignored > 0 {
+        html_content.push_str(&format!(
+            "<div class=\"filtered-note\">{}</div>\n",
+            (labels.hidden_by_filters)(diff.ignored)
+        ));
+    }
+
+    html_content.push_str("</div>\n");
+
+    if !diff.renamed.is_empty() {
+        push_renames_section(&mut html_content, diff, show_hashes, labels);
+    }
+
+    if let Some(lang_diffs) = lang_diffs {
+        push_lang_sections(&mut html_content, lang_diffs, content.render, output_dir, labels)?;
+        push_locale_gaps(&mut html_content, lang_diffs, labels);
+    }
+
+    html_content.push_str(SEARCH_SCRIPT);
+    html_content.push_str(&page_end(branding));
+
+    write_atomic(&page_path, html_content.as_bytes())?;
+    Ok(())
+}
+
+// Сводка под заголовком: сколько файлов добавлено, изменено, удалено и перемещено, сколько папок верхнего
+// уровня затронуто и сколько ключей локализации изменилось
+fn summary_html(stats: &DiffStats, lang_diffs: Option<&[LangDiff]>, labels: &Labels) -> String {
+    let count = |class: &str, text: String| format!("<span class=\"{}\">{}</span>", class, text);
+    let mut summary = format!("    <div class=\"summary\">\n      <div>{}: ", labels.files);
+    if stats.is_empty() {
+        summary.push_str(labels.no_structure_changes);
+    } else {
+        let mut parts = vec![
+            count("added", format!("+{} {}", stats.added, labels.added)),
+            count("modified", format!("~{} {}", stats.modified, labels.modified)),
+            count("deleted", format!("-{} {}", stats.deleted, labels.deleted)),
+        ];
+        if stats.renamed > 0 {
+            parts.push(count("renamed", format!("»{} {}", stats.renamed, labels.moved)));
+        }
+        summary.push_str(&parts.join(" "));
+        summary.push_str(&format!("; {}: {}", labels.top_level_dirs, stats.directories));
+    }
+    summary.push_str("</div>\n");
+    if let Some(lang_diffs) = lang_diffs {
+        let lang = LangEntryCounts::sum(lang_diffs);
+        summary.push_str(&format!("      <div>{}: ", labels.lang_keys));
+        if lang.total() == 0 {
+            summary.push_str(labels.no_changes);
+        } else {
+            let mut parts = vec![
+                count("added", format!("+{} {}", lang.added, labels.added)),
+                count("modified", format!("~{} {}", lang.modified, labels.modified)),
+                count("deleted", format!("-{} {}", lang.removed, labels.deleted)),
+            ];
+            if lang.renamed > 0 {
+                parts.push(count("renamed", format!("»{} {}", lang.renamed, labels.renamed)));
+            }
+            summary.push_str(&parts.join(" "));
+        }
+        summary.push_str("</div>\n");
+    }
+    summary.push_str("    </div>\n");
+    summary
+}
+
+// Текст в `коде`, где Markdown не разбирается; обратные кавычки внутри обходятся более длинным ограничителем
+fn md_code(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", fence, text, fence)
+    } else {
+        format!("{}{}{}", fence, text, fence)
+    }
+}
+
+// Блок ```diff, ограничитель длиннее любой последовательности обратных кавычек в тексте
+fn md_diff_block(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}diff\n{}{}\n\n", fence, text, fence)
+}
+
+// Ячейка таблицы GitHub: вертикальная черта закончила бы ячейку даже внутри `кода`
+fn md_cell(text: &str) -> String {
+    md_code(text).replace('|', "\\|")
+}
+
+// Патчноут в Markdown (docs/changelog_<дата_время>.md, имя как у страницы в patches). Те же данные, что и в HTML:
+// сводка, список изменённых файлов по папкам и изменения локализации блоками diff, без HTML-разметки
+fn write_changelog_markdown(
+    diff: &MapDiff,
+    stats: &DiffStats,
+    path: &Path,
+    title: &str,
+    content: &PageContent,
+) -> io::Result<()> {
+    let (lang_diffs, render) = (content.lang_diffs, content.render);
+    let changelog = content.changelog;
+    let show_hashes = !changelog.hide_hashes;
+    let (branding, labels) = (&changelog.branding, changelog.labels());
+    let mut md = format!("# {} {}\n\n", changelog.title_prefix(), title);
+    if stats.is_empty() {
+        md.push_str(&format!("{}\n\n", labels.structure_unchanged));
+    } else {
+        md.push_str(&format!(
+            "**{}:** {}. {}: {} → {}\n\n{}: {}\n\n",
+            labels.structure_changes,
+            stats,
+            labels.map_entries,
+            stats.total_old,
+            stats.total_new,
+            labels.top_level_dirs_touched,
+            stats.directories
+        ));
+    }
+    if let Some(lang_diffs) = lang_diffs {
+        md.push_str(&format!("**{}:** {}\n\n", labels.lang_keys, LangEntryCounts::sum(lang_diffs)));
+    }
+
+    let mut dirs: Vec<_> = changes_by_dir(diff).into_iter().collect();
+    dirs.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+    for (dir, mut files) in dirs {
+        files.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+        md.push_str(&format!("- {}\n", md_code(&format!("{}/", dir))));
+        for (name, change_type, hashes) in files {
+            let (_, symbol) = change_markup(&change_type);
+            md.push_str(&format!("  - {}", md_code(&format!("{} {}", symbol, name))));
+            if show_hashes {
+                md.push_str(&format!(" — {}", md_code(&hashes)));
+            }
+            md.push('\n');
+        }
+    }
+    if diff.filtered > 0 {
+        md.push_str(&format!("\n_{}_\n", (labels.filtered_paths)(diff.filtered)));
+    }
+    if diff.ignored > 0 {
+        md.push_str(&format!("\n_{}_\n", (labels.hidden_by_filters)(diff.ignored)));
+    }
+    md.push('\n');
+
+    if !diff.renamed.is_empty() {
+        md.push_str(&format!("## {}\n\n", labels.renamed_files));
+        let mut renamed: Vec<_> = diff.renamed.iter().collect();
+        renamed.sort_by(|a, b| natural_cmp(&a.new_path, &b.new_path));
+        for renamed in renamed {
+            md.push_str(&format!("- {} → {}", md_code(&renamed.old_path), md_code(&renamed.new_path)));
+            if show_hashes {
+                md.push_str(&format!(" — {}", md_code(&format!("{:x}", renamed.hash))));
+            }
+            md.push('\n');
+        }
+        md.push('\n');
+    }
+
+    if let Some(lang_diffs) = lang_diffs {
+        let mut budget = if render.max_rendered == 0 { usize::MAX } else { render.max_rendered };
+        for lang_diff in lang_diffs.iter().filter(|d| !d.is_empty()) {
+            md.push_str(&format!("## {} {}\n\n", labels.lang_file_changes, lang_section_title(lang_diff, labels)));
+            let changes = lang_diff.changes();
+            let shown = changes.len().min(budget);
+            budget -= shown;
+            for (category, category_changes) in render.categories.group(&changes[..shown]) {
+                let category_changes: Vec<LangChange> = category_changes.into_iter().cloned().collect();
+                md.push_str(&format!("### {} ({})\n\n", labels.category(category), category_changes.len()));
+                md.push_str(&md_diff_block(&format_lang_changes(&category_changes)));
+            }
+            if shown < changes.len() {
+                // Файл с полным diff уже записан рядом со страницей при генерации HTML
+                let file_name = truncated_diff_name(lang_diff);
+                md.push_str(&format!(
+                    "_{}:_ [{}]({})\n\n",
+                    (labels.truncated_lang)(changes.len() - shown),
+                    md_code(&file_name),
+                    file_name
+                ));
+            }
+            if lang_diff.filtered > 0 {
+                md.push_str(&format!("_{}_\n\n", (labels.ignored_keys)(lang_diff.filtered)));
+            }
+            if lang_diff.fallback_lines > 0 {
+                md.push_str(&format!("_{}_\n\n", (labels.fallback_lines)(lang_diff.fallback_lines)));
+            }
+            if !lang_diff.duplicates.is_empty() {
+                md.push_str(&format!(
+                    "_{}:_ {}\n\n",
+                    labels.duplicate_keys,
+                    lang_diff.duplicates.iter().map(|key| md_code(key)).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        if lang_diffs.iter().all(LangDiff::is_empty) {
+            md.push_str(&format!("## {}\n\n{}\n\n", labels.lang_file_changes, labels.no_lang_changes));
+        }
+
+        let gaps = locale_gap_rows(lang_diffs, labels);
+        if !gaps.is_empty() {
+            md.push_str(&format!(
+                "## {}\n\n| {} | {} | {} |\n| --- | --- | --- |\n",
+                labels.missing_translation, labels.key_column, labels.added_in_column, labels.missing_in_column
+            ));
+            for [key, added_in, missing_in] in gaps {
+                md.push_str(&format!("| {} | {} | {} |\n", md_cell(&key), md_cell(&added_in), md_cell(&missing_in)));
+            }
+            md.push('\n');
+        }
+    }
+
+    md.push_str(&format!("{}: [{}]({})\n", labels.source, branding.source_name, branding.source_url));
+    write_atomic(path, md.as_bytes())
+}
+
+/// Отчёт о различиях локализации ОТС и основного сервера (см. lang::compare_branches), по разделу на локаль
+pub fn generate_branch_comparison(
+    lang_diffs: &[LangDiff],
+    output_dir: &Path,
+    render: &LangRenderOptions,
+    changelog: &ChangelogConfig,
+) -> Result<(), ChangelogError> {
+    let labels = changelog.labels();
+    fs::create_dir_all(output_dir)?;
+    copy_branding_assets(output_dir, &changelog.branding)?;
+    let title = labels.branch_comparison;
+    let mut html_content = page_start(title, None, changelog);
+    html_content.push_str(&format!(
+        r#"    <h1>{}</h1>
+    <div class="stats">{}: {}</div>
+"#,
+        title,
+        labels.generated,
+        chrono::Local::now().format(labels.date_time)
+    ));
+
+    for lang_diff in lang_diffs {
+        let changes = lang_diff.changes();
+        let of_kind = |kind: fn(&LangChange) -> bool| changes.iter().filter(|c| kind(c)).cloned().collect::<Vec<_>>();
+        let sections = [
+            (labels.only_ots, of_kind(|c| matches!(c, LangChange::Added { .. }))),
+            (labels.only_live, of_kind(|c| matches!(c, LangChange::Removed { .. }))),
+            (labels.differ, of_kind(|c| matches!(c, LangChange::Modified { .. }))),
+        ];
+        for (heading, changes) in sections {
+            html_content.push_str(&format!(
+                r#"    <h2>{}.lang: {} ({})</h2>
+    <div class="lang-changes">
+"#,
+                html_escape::encode_text(&lang_diff.locale),
+                heading,
+                changes.len()
+            ));
+            if changes.is_empty() {
+                html_content.push_str(&format!("<div class=\"no-changes\">{}</div>\n", labels.none));
+            }
+            push_lang_groups(&mut html_content, &changes, render, labels);
+            html_content.push_str("</div>\n");
+        }
+    }
+
+    html_content.push_str(&page_end(&changelog.branding));
+    fs::write(output_dir.join("index.html"), html_content)?;
+    Ok(())
+}
+
+fn push_renames_section(html_content: &mut String, diff: &MapDiff, show_hashes: bool, labels: &Labels) {
+    let (html_class, symbol) = change_markup(&ChangeType::Renamed);
+    html_content.push_str(&format!(
+        r#"    <h2>{}</h2>
+    <div class="renames">
+"#,
+        labels.renamed_files
+    ));
+    let mut renamed: Vec<_> = diff.renamed.iter().collect();
+    renamed.sort_by(|a, b| natural_cmp(&a.new_path, &b.new_path));
+    for renamed in renamed {
+        let title = if show_hashes { format!(" title=\"{:x}\"", renamed.hash) } else { String::new() };
+        html_content.push_str(&format!(
+            "      <div class=\"file {}\"{}>{} {} → {}</div>\n",
+            html_class,
+            title,
+            symbol,
+            html_escape::encode_text(&renamed.old_path),
+            html_escape::encode_text(&renamed.new_path)
+        ));
+    }
+    html_content.push_str("    </div>\n");
+}
+
+// Значение, разбитое на слова и промежутки между ними: склейка токенов даёт исходную строку
+fn word_tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (i, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if in_space.is_some_and(|was_space| was_space != space) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+// Старое и новое значение одной строкой с выделением удалённых и добавленных слов.
+// None, если общих слов нет: тогда значения показываются целиком
+fn word_diff_html(old: &str, new: &str, render: &LangRenderOptions) -> Option<String> {
+    let old_tokens = word_tokens(old);
+    let new_tokens = word_tokens(new);
+    let ops = diff_lines(&old_tokens, &new_tokens);
+    if !ops.iter().any(|op| matches!(op, LineOp::Equal(token) if !token.trim().is_empty())) {
+        return None;
+    }
+
+    let mut html = String::new();
+    let mut pending: Option<(&str, String)> = None;
+    for op in ops {
+        let (class, token) = match op {
+            LineOp::Equal(token) => ("", token),
+            LineOp::Delete(token) => ("word-removed", token),
+            LineOp::Insert(token) => ("word-added", token),
+        };
+        // Соседние токены одного вида объединяются в один фрагмент
+        match &mut pending {
+            Some((pending_class, text)) if *pending_class == class => text.push_str(token),
+            _ => {
+                if let Some((pending_class, text)) = pending.take() {
+                    push_word_fragment(&mut html, pending_class, &text, render);
+                }
+                pending = Some((class, token.to_string()));
+            }
+        }
+    }
+    if let Some((class, text)) = pending {
+        push_word_fragment(&mut html, class, &text, render);
+    }
+    Some(html)
+}
+
+fn push_word_fragment(html: &mut String, class: &str, text: &str, render: &LangRenderOptions) {
+    if class.is_empty() {
+        html.push_str(&lang_text_html(text, render));
+    } else {
+        html.push_str(&format!(r#"<span class="{}">{}</span>"#, class, lang_text_html(text, render)));
+    }
+}
+
+// Уже раскрытое значение для HTML: переводы строк становятся <br>, цветовые коды - цветом или убираются.
+// Цвет действует до конца переданного куска текста
+fn lang_text_html(text: &str, render: &LangRenderOptions) -> String {
+    let mut html = String::new();
+    for (color, segment) in split_color_codes(text) {
+        let segment = html_escape::encode_text(segment).replace('\n', "<br>");
+        match color {
+            Some(color) if render.render_colors => {
+                html.push_str(&format!(r#"<span class="color-{}">{}</span>"#, color, segment))
+            }
+            _ => html.push_str(&segment),
+        }
+    }
+    html
+}
+
+// Значение из файла для показа; в .diff и .json остаётся исходная экранированная форма
+fn lang_value_html(value: &str, render: &LangRenderOptions) -> String {
+    lang_text_html(&unescape_lang_value(value), render)
+}
+
+fn lang_change_html(change: &LangChange, render: &LangRenderOptions, labels: &Labels) -> String {
+    let text = |key: &str, value: &Option<String>| match value {
+        Some(value) => format!("{} = {}", html_escape::encode_text(key), lang_value_html(value, render)),
+        None => html_escape::encode_text(key).to_string(),
+    };
+    match change {
+        LangChange::Added { key, value } => format!(r#"<div class="diff-line added">{}</div>"#, text(key, value)),
+        LangChange::Removed { key, value } => format!(r#"<div class="diff-line deleted">{}</div>"#, text(key, value)),
+        LangChange::Modified { key, old, new, revert } => {
+            let old = old.as_deref().map(unescape_lang_value);
+            let new = new.as_deref().map(unescape_lang_value);
+            let words = old.as_deref().zip(new.as_deref()).and_then(|(old, new)| word_diff_html(old, new, render));
+            let value = words.unwrap_or_else(|| {
+                format!(
+                    r#"<del class="old-value">{}</del> <ins class="new-value">{}</ins>"#,
+                    lang_text_html(old.as_deref().unwrap_or(""), render),
+                    lang_text_html(new.as_deref().unwrap_or(""), render)
+                )
+            });
+            // Потерянный плейсхолдер - частая причина ошибок в игре, такая строка выделяется предупреждением
+            let (class, warning) = if change.placeholder_mismatch() {
+                (
+                    " placeholder-mismatch",
+                    format!(r#" <span class="placeholder-warning">[!] {}</span>"#, labels.placeholder_mismatch),
+                )
+            } else {
+                ("", String::new())
+            };
+            let badge = if *revert {
+                format!(r#" <span class="revert-badge">{}</span>"#, labels.revert)
+            } else {
+                String::new()
+            };
+            format!(
+                r#"<div class="diff-line modified{}">{}{} = {}{}</div>"#,
+                class,
+                html_escape::encode_text(key),
+                badge,
+                value,
+                warning
+            )
+        }
+        LangChange::Renamed { old_key, new_key, value } => format!(
+            r#"<div class="diff-line renamed">{} → {} = {}</div>"#,
+            html_escape::encode_text(old_key),
+            html_escape::encode_text(new_key),
+            lang_value_html(value, render)
+        ),
+    }
+}
+
+// Группа ключа: два первых сегмента, если после них ещё что-то есть (ui.news.title -> ui.news), иначе первый
+// (item.name -> item); ключ без точек группы не имеет
+fn lang_group(key: &str) -> Option<&str> {
+    let mut dots = key.match_indices('.').map(|(i, _)| i);
+    match (dots.next(), dots.next()) {
+        (Some(_), Some(second)) => Some(&key[..second]),
+        (Some(first), None) => Some(&key[..first]),
+        _ => None,
+    }
+}
+
+// Изменения локализации по группам ключей в порядке первого появления; ключи без группы в конце, в «Прочем»
+fn push_lang_groups(html_content: &mut String, changes: &[LangChange], render: &LangRenderOptions, labels: &Labels) {
+    let mut groups: Vec<(Option<&str>, Vec<&LangChange>)> = Vec::new();
+    for change in changes {
+        let group = lang_group(change.key());
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, group_changes)) => group_changes.push(change),
+            None => groups.push((group, vec![change])),
+        }
+    }
+    groups.sort_by_key(|(name, _)| name.is_none());
+
+    for (name, group_changes) in groups {
+        html_content.push_str(&format!(
+            r#"<details class="directory lang-group" open>
+  <summary class="name">{} <span class="group-count">({})</span></summary>
+"#,
+            html_escape::encode_text(name.unwrap_or(labels.other_group)),
+            group_changes.len()
+        ));
+        for change in group_changes {
+            html_content.push_str(&lang_change_html(change, render, labels));
+        }
+        html_content.push_str("</details>\n");
+    }
+}
+
+// Изменения по категориям (render.categories) с числом изменений, внутри категории - по группам ключей
+fn push_lang_categories(
+    html_content: &mut String,
+    changes: &[LangChange],
+    render: &LangRenderOptions,
+    labels: &Labels,
+) {
+    for (category, category_changes) in render.categories.group(changes) {
+        html_content.push_str(&format!(
+            r#"<details class="lang-category" open>
+  <summary class="category">{} <span class="group-count">({})</span></summary>
+"#,
+            html_escape::encode_text(labels.category(category)),
+            category_changes.len()
+        ));
+        let category_changes: Vec<LangChange> = category_changes.into_iter().cloned().collect();
+        push_lang_groups(html_content, &category_changes, render, labels);
+        html_content.push_str("</details>\n");
+    }
+}
+
+// Имя файла в заголовке раздела; при нескольких ветках в lang.branches подписана и ветка
+fn lang_section_title(lang_diff: &LangDiff, labels: &Labels) -> String {
+    match &lang_diff.branch {
+        Some(branch) => format!("{} ({})", lang_diff.file_name(), labels.branch(&Branch::from_env_dir_name(branch))),
+        None => lang_diff.file_name(),
+    }
+}
+
+// Полный diff рядом со страницей, когда раздел не уместился в render.max_rendered
+fn truncated_diff_name(lang_diff: &LangDiff) -> String {
+    match &lang_diff.branch {
+        Some(branch) => format!("{}_{}_changes.diff", branch, lang_diff.artifact_name()),
+        None => format!("{}_changes.diff", lang_diff.artifact_name()),
+    }
+}
+
+// Раздел на каждую локализацию с изменениями; если изменений нет ни в одной, одна общая заглушка
+// Изменения сверх render.max_rendered на страницу не попадают: для них рядом со страницей пишется полный diff
+fn push_lang_sections(
+    html_content: &mut String,
+    lang_diffs: &[LangDiff],
+    render: &LangRenderOptions,
+    output_dir: &Path,
+    labels: &Labels,
+) -> io::Result<()> {
+    let mut budget = if render.max_rendered == 0 { usize::MAX } else { render.max_rendered };
+    let mut any_changes = false;
+    for lang_diff in lang_diffs.iter().filter(|d| !d.is_empty()) {
+        any_changes = true;
+        // Файлы из дерева ассетов (lang.scan_tree) сворачиваются: их может быть много
+        let collapsible = lang_diff.path.is_some();
+        if collapsible {
+            html_content.push_str("    <details class=\"lang-file\" open>\n    <summary>");
+        }
+        html_content.push_str(&format!(
+            r#"    <h2>{} {}</h2>
+"#,
+            labels.lang_file_changes,
+            html_escape::encode_text(&lang_section_title(lang_diff, labels))
+        ));
+        if collapsible {
+            html_content.push_str("    </summary>\n");
+        }
+        html_content.push_str("    <div class=\"lang-changes\">\n");
+        let changes = lang_diff.changes();
+        let shown = changes.len().min(budget);
+        budget -= shown;
+        push_lang_categories(html_content, &changes[..shown], render, labels);
+        if shown < changes.len() {
+            let file_name = truncated_diff_name(lang_diff);
+            fs::write(output_dir.join(&file_name), format_lang_changes(&changes))?;
+            html_content.push_str(&format!(
+                "<div class=\"truncated-note\">{}: <a href=\"{}\">{}</a></div>\n",
+                (labels.truncated_lang)(changes.len() - shown),
+                html_escape::encode_double_quoted_attribute(&file_name),
+                html_escape::encode_text(&file_name)
+            ));
+        }
+        if lang_diff.filtered > 0 {
+            html_content.push_str(&format!(
+                "<div class=\"filtered-note\">{}</div>\n",
+                (labels.ignored_keys)(lang_diff.filtered)
+            ));
+        }
+        if lang_diff.fallback_lines > 0 {
+            html_content.push_str(&format!(
+                "<div class=\"filtered-note\">{}</div>\n",
+                (labels.fallback_lines)(lang_diff.fallback_lines)
+            ));
+        }
+        if !lang_diff.duplicates.is_empty() {
+            html_content.push_str(&format!(
+                "<div class=\"filtered-note\">{}: {}</div>\n",
+                labels.duplicate_keys,
+                html_escape::encode_text(&lang_diff.duplicates.join(", "))
+            ));
+        }
+        html_content.push_str("</div>\n");
+        if collapsible {
+            html_content.push_str("    </details>\n");
+        }
+    }
+
+    if !any_changes {
+        html_content.push_str(&format!(
+            r#"    <h2>{}</h2>
+    <div class="lang-changes">
+<div class="no-changes">{}</div></div>
+"#,
+            labels.lang_file_changes, labels.no_lang_changes
+        ));
+    }
+    Ok(())
+}
+
+// Таблица ключей без перевода. Локали сравниваются внутри одной папки одной ветки: при lang.scan_tree это файлы
+// <папка>/<локаль>.lang, иначе локали из lang.locales
+fn push_locale_gaps(html_content: &mut String, lang_diffs: &[LangDiff], labels: &Labels) {
+    let rows = locale_gap_rows(lang_diffs, labels);
+    if rows.is_empty() {
+        return;
+    }
+    html_content.push_str(&format!(
+        r#"    <h2>{}</h2>
+    <table class="locale-gaps">
+<tr><th>{}</th><th>{}</th><th>{}</th></tr>
+"#,
+        labels.missing_translation, labels.key_column, labels.added_in_column, labels.missing_in_column
+    ));
+    for [key, added_in, missing_in] in rows {
+        html_content.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            html_escape::encode_text(&key),
+            html_escape::encode_text(&added_in),
+            html_escape::encode_text(&missing_in)
+        ));
+    }
+    html_content.push_str("</table>\n");
+}
+
+// Строки таблицы «Нет перевода»: ключ (с папкой и веткой), где добавлен, где его нет
+fn locale_gap_rows(lang_diffs: &[LangDiff], labels: &Labels) -> Vec<[String; 3]> {
+    let mut folders: BTreeMap<(Option<&str>, &str), HashMap<String, LangDiff>> = BTreeMap::new();
+    for lang_diff in lang_diffs {
+        let (folder, locale) = match &lang_diff.path {
+            Some(path) => match path.rsplit_once('/') {
+                Some((folder, file)) => (folder, file.strip_suffix(".lang").unwrap_or(file)),
+                None => ("", path.strip_suffix(".lang").unwrap_or(path)),
+            },
+            None => ("", lang_diff.locale.as_str()),
+        };
+        folders
+            .entry((lang_diff.branch.as_deref(), folder))
+            .or_default()
+            .insert(locale.to_string(), lang_diff.clone());
+    }
+
+    let mut rows = Vec::new();
+    for ((branch, folder), diffs) in &folders {
+        for gap in cross_check_locales(diffs) {
+            let mut key = if folder.is_empty() { gap.key } else { format!("{}: {}", folder, gap.key) };
+            if let Some(branch) = branch {
+                key = format!("{} ({})", key, labels.branch(&Branch::from_env_dir_name(branch)));
+            }
+            rows.push([key, gap.added_in.join(", "), gap.missing_in.join(", ")]);
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FooterLink;
+    use crate::diff::{diff_map_entries, diff_map_entries_parallel};
+    use crate::labels::RU;
+    use crate::lang::diff_lang_content;
+    use crate::map::{MapEntry, MapHash};
+    use crate::testing::TempDir;
+
+    fn entry(path: &str, byte: u8) -> MapEntry {
+        MapEntry {
+            path: path.to_string(),
+            hash: MapHash::from([byte; 20]),
+        }
+    }
+
+    fn added(key: &str) -> LangChange {
+        LangChange::Added {
+            key: key.to_string(),
+            value: Some("Значение".to_string()),
+        }
+    }
+
+    // Страница сравнения с настройками по умолчанию
+    fn comparison_page(diff: &MapDiff, dir: &Path) -> String {
+        generate_comparison_changelog(diff, dir, "test", &ChangelogConfig::default()).unwrap();
+        fs::read_to_string(dir.join("index.html")).unwrap()
+    }
+
+    // Последняя страница из patches/ и Markdown-версия рядом с ней
+    fn latest_page(dir: &Path) -> String {
+        fs::read_to_string(dir.join(&load_patch_index(dir)[0].file)).unwrap()
+    }
+
+    fn latest_markdown(dir: &Path) -> String {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with("changelog_"))
+            .max()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn natural_order_respects_numbers() {
+        let mut names = vec!["texture_10.png", "texture_2.png", "texture_1.png", "texture_02.png", "a.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a.png", "texture_1.png", "texture_02.png", "texture_2.png", "texture_10.png"]);
+    }
+
+    #[test]
+    fn changelog_output_is_deterministic() {
+        let old = vec![
+            entry("assets/textures/texture_1.png", 1),
+            entry("assets/textures/texture_2.png", 2),
+            entry("assets/sounds/shot.ogg", 3),
+            entry("assets/models/old.obj", 4),
+        ];
+        let new = vec![
+            entry("assets/textures/texture_10.png", 5),
+            entry("assets/textures/texture_1.png", 6),
+            entry("assets/textures/texture_2.png", 8),
+            entry("assets/models/new.obj", 4),
+            entry("assets/models/model_3.obj", 7),
+        ];
+        let root = TempDir::new("changelog");
+        let render = |diff: &MapDiff, name: &str| comparison_page(diff, &root.join(name));
+
+        let first = render(&diff_map_entries(&old, &new), "first");
+        let second = render(&diff_map_entries(&old, &new), "second");
+        let reversed_old: Vec<_> = old.iter().rev().cloned().collect();
+        let reversed_new: Vec<_> = new.iter().rev().cloned().collect();
+        let reversed = render(&diff_map_entries_parallel(&reversed_old, &reversed_new), "reversed");
+
+        assert_eq!(first, second);
+        assert_eq!(first, reversed);
+        assert!(first.find("texture_2.png").unwrap() < first.find("texture_10.png").unwrap());
+    }
+
+    #[test]
+    fn modified_values_highlight_changed_words() {
+        assert_eq!(word_tokens("Урон  по броне"), ["Урон", "  ", "по", " ", "броне"]);
+        assert_eq!(
+            word_diff_html("Увеличивает урон на 10%", "Увеличивает урон на 15%", &LangRenderOptions::default()).unwrap(),
+            r#"Увеличивает урон на <span class="word-removed">10%</span><span class="word-added">15%</span>"#
+        );
+        assert_eq!(word_diff_html("Старое", "Совсем другое", &LangRenderOptions::default()), None);
+
+        let html = lang_change_html(
+            &LangChange::Modified {
+                key: "item.name".to_string(),
+                old: Some("АК".to_string()),
+                new: Some("СВД".to_string()),
+                revert: false,
+            },
+            &LangRenderOptions::default(),
+            &RU,
+        );
+        assert!(html.contains(r#"<del class="old-value">АК</del> <ins class="new-value">СВД</ins>"#));
+        assert!(!html.contains("placeholder-warning"));
+
+        let html = lang_change_html(
+            &LangChange::Modified {
+                key: "item.desc".to_string(),
+                old: Some("Урон: %d".to_string()),
+                new: Some("Урон: много".to_string()),
+                revert: false,
+            },
+            &LangRenderOptions::default(),
+            &RU,
+        );
+        assert!(html.contains(r#"class="diff-line modified placeholder-mismatch""#));
+        assert!(html.contains("placeholder-warning"));
+    }
+
+    #[test]
+    fn lang_section_is_capped_with_full_diff_beside_page() {
+        let dir = TempDir::new("lang_cap");
+        let diffs = [
+            LangDiff::from_changes("ru", vec![added("a.one"), added("a.two")]),
+            LangDiff::from_changes("en", vec![added("b.one"), added("b.two")]),
+        ];
+        let render = LangRenderOptions {
+            max_rendered: 3,
+            ..LangRenderOptions::default()
+        };
+        let mut html = String::new();
+        push_lang_sections(&mut html, &diffs, &render, &dir, &RU).unwrap();
+
+        assert!(html.contains("b.one") && !html.contains("b.two"));
+        assert!(html.contains(r#"…и ещё 1 изменение, полный diff: <a href="en_changes.diff">"#));
+        assert!(!dir.join("ru_changes.diff").exists());
+        assert_eq!(fs::read_to_string(dir.join("en_changes.diff")).unwrap(), format_lang_changes(&diffs[1].changes()));
+    }
+
+    #[test]
+    fn lang_branches_are_labelled_and_not_mixed() {
+        let dir = TempDir::new("lang_branches");
+        let with_branch = |locale: &str, branch: &str, keys: &[&str]| LangDiff {
+            branch: Some(branch.to_string()),
+            ..LangDiff::from_changes(
+                locale,
+                keys.iter().map(|key| added(key)).collect(),
+            )
+        };
+        let diffs = [
+            with_branch("ru", "live", &["item.live"]),
+            with_branch("en", "live", &[]),
+            with_branch("ru", "ots", &[]),
+            with_branch("en", "ots", &["item.ots", "item.ots_extra"]),
+        ];
+        let render = LangRenderOptions {
+            max_rendered: 2,
+            ..LangRenderOptions::default()
+        };
+        let mut html = String::new();
+        push_lang_sections(&mut html, &diffs, &render, &dir, &RU).unwrap();
+        push_locale_gaps(&mut html, &diffs, &RU);
+        let truncated = dir.join("ots_en_changes.diff").exists();
+
+        assert!(html.contains("Изменения в файле локализации ru.lang (основной сервер)"));
+        assert!(html.contains("Изменения в файле локализации en.lang (ОТС)"));
+        assert!(truncated);
+        // Ключи без перевода ищутся внутри ветки: ru ОТС не подменяет ru основного сервера
+        assert!(html.contains("<td>item.live (основной сервер)</td><td>ru</td><td>en</td>"));
+        assert!(html.contains("<td>item.ots (ОТС)</td><td>en</td><td>ru</td>"));
+    }
+
+    #[test]
+    fn markdown_changelog_matches_html_content() {
+        let dir = TempDir::new("markdown");
+        let diff = diff_map_entries(
+            &[entry("assets/models/old.obj", 1), entry("assets/textures/texture_2.png", 2)],
+            &[
+                entry("assets/textures/texture_10.png", 3),
+                entry("assets/textures/texture_2.png", 4),
+                entry("assets/models/old.obj", 1),
+            ],
+        );
+        let lang_diffs = [LangDiff::from_changes(
+            "ru",
+            vec![
+                LangChange::Added {
+                    key: "item.wpn.vsk.name".to_string(),
+                    value: Some("ВСК-94 ```".to_string()),
+                },
+                LangChange::Removed {
+                    key: "misc.old".to_string(),
+                    value: Some("Старое".to_string()),
+                },
+            ],
+        )];
+        let path = dir.join("changelog.md");
+        let stats = diff.stats();
+        let content = PageContent {
+            lang_diffs: Some(&lang_diffs),
+            render: &LangRenderOptions::default(),
+            changelog: &ChangelogConfig {
+                hide_hashes: true,
+                ..ChangelogConfig::default()
+            },
+        };
+        write_changelog_markdown(&diff, &stats, &path, "01.02.2026", &content).unwrap();
+        let md = fs::read_to_string(&path).unwrap();
+
+        assert!(md.starts_with("# Патчноут 01.02.2026\n\n**Изменения файловой структуры:** "));
+        assert!(!md.contains('<'));
+        assert!(md.contains("- `assets/textures/`\n  - `~ texture_2.png`\n  - `+ texture_10.png`\n"));
+        assert!(!md.contains("old.obj"));
+        assert!(md.contains("### Оружие (1)\n\n````diff\n+item.wpn.vsk.name = ВСК-94 ```\n````\n"));
+        assert!(md.contains("### Прочее (1)\n\n```diff\n-misc.old = Старое\n```\n"));
+        assert_eq!(md_code("a`b"), "``a`b``");
+        assert_eq!(md_cell("a|b"), "`a\\|b`");
+    }
+
+    #[test]
+    fn changes_json_round_trips() {
+        let dir = TempDir::new("changes_json");
+        let diff = diff_map_entries(
+            &[entry("assets/a.png", 1), entry("assets/b.png", 2)],
+            &[entry("assets/b.png", 3), entry("assets/c.png", 4)],
+        );
+        let lang_diffs = [LangDiff::from_changes(
+            "ru",
+            vec![LangChange::Modified {
+                key: "item.name".to_string(),
+                old: Some("АК".to_string()),
+                new: Some("СВД".to_string()),
+                revert: true,
+            }],
+        )];
+        let changelog = ChangelogConfig::default();
+        let render = LangRenderOptions::default();
+        generate_changelog(Some(&diff), &dir, &Branch::Live, &lang_diffs, &render, &changelog).unwrap();
+        let json = fs::read_to_string(dir.join("changes.json")).unwrap();
+
+        let changes: ChangesJson = serde_json::from_str(&json).unwrap();
+        assert_eq!(changes.schema_version, CHANGES_JSON_SCHEMA_VERSION);
+        assert_eq!(changes.branch, "live");
+        assert_eq!((changes.summary.added, changes.summary.modified, changes.summary.lang_changes), (1, 1, 1));
+        let paths: Vec<_> = changes.files.iter().map(|f| (f.kind, f.path.as_str())).collect();
+        assert_eq!(
+            paths,
+            [
+                (FileChangeKind::Deleted, "assets/a.png"),
+                (FileChangeKind::Modified, "assets/b.png"),
+                (FileChangeKind::Added, "assets/c.png"),
+            ]
+        );
+        assert_eq!(changes.files[1].old_hash, Some("02".repeat(20)));
+        assert_eq!(changes.files[1].new_hash, Some("03".repeat(20)));
+        assert_eq!(changes.lang[0].file, "ru.lang");
+        assert!(changes.lang[0].changes[0].revert);
+        let expected = ChangesJson {
+            generated: changes.generated.clone(),
+            ..ChangesJson::new(&diff, &diff.stats(), &Branch::Live, &lang_diffs)
+        };
+        assert_eq!(changes, expected);
+    }
+
+    // Минимальная проверка XML: теги закрываются в обратном порядке, вне тегов нет сырых < и &
+    fn assert_well_formed_xml(xml: &str) {
+        let mut stack = Vec::new();
+        let mut rest = xml.strip_prefix(r#"<?xml version="1.0" encoding="utf-8"?>"#).expect("нет XML-декларации");
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            assert!(text.split('&').skip(1).all(|s| ["amp;", "lt;", "gt;", "quot;"].iter().any(|e| s.starts_with(e))));
+            let end = rest[start..].find('>').expect("незакрытый тег") + start;
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "< внутри тега: {}", tag);
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(stack.pop(), Some(name.to_string()), "лишний </{}>", name);
+            } else if !tag.ends_with('/') {
+                stack.push(tag.split_whitespace().next().unwrap().to_string());
+            }
+            rest = &rest[end + 1..];
+        }
+        assert!(rest.trim().is_empty() && stack.is_empty(), "незакрытые теги: {:?}", stack);
+    }
+
+    #[test]
+    fn atom_feed_keeps_stable_ids_and_recent_entries() {
+        let dir = TempDir::new("feed");
+        let changelog = ChangelogConfig {
+            feed_entries: 2,
+            ..ChangelogConfig::default()
+        };
+        let entry = |stamp: &str, summary: &str| FeedEntry {
+            id: format!("{}/{}", feed_id(&Branch::Live), stamp),
+            title: "Патчноут 01.02.2026".to_string(),
+            link: site_link(&changelog.site_url, "index.html"),
+            published: "2026-02-01T10:00:00+03:00".to_string(),
+            summary: summary.to_string(),
+        };
+        update_feed(&dir, &Branch::Live, entry("20260201_100000", "первый"), &changelog).unwrap();
+        update_feed(&dir, &Branch::Live, entry("20260202_100000", "второй <b> & ещё"), &changelog).unwrap();
+        update_feed(&dir, &Branch::Live, entry("20260202_100000", "второй <b> & ещё"), &changelog).unwrap();
+        update_feed(&dir, &Branch::Live, entry("20260203_100000", "третий"), &changelog).unwrap();
+        let xml = fs::read_to_string(dir.join("feed.xml")).unwrap();
+
+        assert_well_formed_xml(&xml);
+        for required in ["<feed xmlns=\"http://www.w3.org/2005/Atom\">", "<author><name>", "<updated>", "rel=\"self\""] {
+            assert!(xml.contains(required), "нет {}", required);
+        }
+        assert_eq!(xml.matches("<entry>").count(), 2);
+        assert_eq!(xml.matches("20260202_100000</id>").count(), 1);
+        assert!(xml.find("20260203_100000").unwrap() < xml.find("20260202_100000").unwrap());
+        assert!(!xml.contains("первый"));
+        assert!(xml.contains("<summary>второй &lt;b&gt; &amp; ещё</summary>"));
+        assert!(xml.contains(r#"href="https://builderssc.github.io/Krevetka/index.html""#));
+
+        let summary = feed_summary(
+            &DiffStats::default(),
+            &[diff_lang_content("ru", "a = 1\n", "a = 2\nb = 3\nc = 4\nd = 5\n")],
+            &RU,
+        );
+        assert_eq!(summary, "Файлы: +0 ~0 -0. Локализация: 4 изменения (~a, +b, +c, …)");
+    }
+
+    #[test]
+    fn dated_pages_are_kept_and_listed_in_index() {
+        let dir = TempDir::new("dated_pages");
+        fs::write(dir.join("index.html"), "<html>прежний патчноут</html>").unwrap();
+        let changelog = ChangelogConfig::default();
+        let first = diff_map_entries(&[entry("a.png", 1)], &[entry("a.png", 2), entry("b.png", 3)]);
+        let second = diff_map_entries(&[entry("a.png", 2)], &[]);
+        for diff in [&first, &second] {
+            let render = LangRenderOptions::default();
+            generate_changelog(Some(diff), &dir, &Branch::Live, &[], &render, &changelog).unwrap();
+        }
+        let pages = load_patch_index(&dir);
+        let index = fs::read_to_string(dir.join("index.html")).unwrap();
+        let contents: Vec<String> = pages.iter().map(|p| fs::read_to_string(dir.join(&p.file)).unwrap()).collect();
+        let feed = fs::read_to_string(dir.join("feed.xml")).unwrap();
+
+        assert_eq!(pages.len(), 3);
+        assert_ne!(pages[0].file, pages[1].file);
+        assert!(pages.iter().all(|p| p.file.starts_with("patches/") && p.file.ends_with(".html")));
+        assert_eq!(pages[2].summary, None);
+        assert_eq!(contents[2], "<html>прежний патчноут</html>");
+        assert!(contents[0].contains(r#"<base href="../">"#));
+        assert!(index.contains("+0 ~0 -1") && index.contains("+1 ~1 -0"));
+        assert!(index.find(&pages[0].file).unwrap() < index.find(&pages[1].file).unwrap());
+        assert!(feed.contains(&format!("Krevetka/{}", pages[0].file)));
+    }
+
+    #[test]
+    fn summary_header_counts_files_directories_and_lang_keys() {
+        let diff = diff_map_entries(
+            &[entry("sounds/a.ogg", 1), entry("textures/b.png", 2), entry("root.txt", 3)],
+            &[entry("sounds/a.ogg", 4), entry("models/c.obj", 5), entry("root.txt", 3)],
+        );
+        let stats = diff.stats();
+        assert_eq!((stats.added, stats.modified, stats.deleted, stats.directories), (1, 1, 1, 3));
+        let lang = [diff_lang_content("ru", "a = 1\nb = 2\n", "a = 3\nc = 4\n")];
+        let summary = summary_html(&stats, Some(&lang), &RU);
+        assert!(summary.contains(r#"<span class="added">+1 добавлено</span>"#));
+        assert!(summary.contains("папок верхнего уровня: 3"));
+        assert!(summary.contains(r#"<span class="deleted">-1 удалено</span>"#));
+
+        let dir = TempDir::new("summary");
+        let empty = diff_map_entries(&[entry("root.txt", 3)], &[entry("root.txt", 3)]);
+        let content = PageContent {
+            lang_diffs: None,
+            render: &LangRenderOptions::default(),
+            changelog: &ChangelogConfig::default(),
+        };
+        write_changelog(&empty, &empty.stats(), &dir, "index.html", "тест", &content).unwrap();
+        let html = fs::read_to_string(dir.join("index.html")).unwrap();
+        assert!(html.contains("изменений файловой структуры нет"));
+        assert!(html.contains(r#"<div class="no-changes">Файловая структура не изменилась</div>"#));
+    }
+
+    #[test]
+    fn file_hashes_are_shown_unless_hidden() {
+        let diff = diff_map_entries(&[entry("a/old.png", 0xa1), entry("a/gone.png", 0x0b)], &[
+            entry("a/old.png", 0xc3),
+            entry("a/new.png", 0x0d),
+        ]);
+        let dir = TempDir::new("hashes");
+        let mut changelog = ChangelogConfig::default();
+        let mut pages = Vec::new();
+        for hide_hashes in [false, true] {
+            changelog.hide_hashes = hide_hashes;
+            generate_comparison_changelog(&diff, &dir, "test", &changelog).unwrap();
+            pages.push(fs::read_to_string(dir.join("index.html")).unwrap());
+        }
+        let content = PageContent {
+            lang_diffs: None,
+            render: &LangRenderOptions::default(),
+            changelog: &ChangelogConfig::default(),
+        };
+        write_changelog_markdown(&diff, &diff.stats(), &dir.join("changelog.md"), "test", &content).unwrap();
+        let md = fs::read_to_string(dir.join("changelog.md")).unwrap();
+
+        let modified = format!(r#"title="old: {} → new: {}""#, "a1".repeat(20), "c3".repeat(20));
+        assert!(pages[0].contains(&modified));
+        assert!(pages[0].contains(&format!(r#"<div class="file added" title="{}">"#, "0d".repeat(20))));
+        assert!(pages[0].contains(&format!(r#"<div class="file deleted" title="{}">"#, "0b".repeat(20))));
+        assert!(!pages[1].contains("title=\""));
+        assert!(md.contains(&format!("  - `~ old.png` — `{}`\n", &modified[7..modified.len() - 1])));
+    }
+
+    #[test]
+    fn ignored_paths_are_hidden_from_every_output() {
+        let diff = diff_map_entries(
+            &[entry("shadercache/a.bin", 1), entry("shadercache/sub/b.bin", 2), entry("textures/gen.png", 3)],
+            &[
+                entry("shadercache/a.bin", 4),
+                entry("shadercache/sub/c.bin", 5),
+                entry("textures/gen.png", 6),
+                entry("textures/gen.png.meta", 7),
+                entry("textures/stone.png", 8),
+            ],
+        );
+        let ignored = diff.without_ignored(&["shadercache/*".to_string(), "textures/gen.png".to_string()]);
+        assert_eq!(ignored.ignored, 4);
+        assert_eq!(ignored.total(), 2);
+        assert!(ignored.added.iter().any(|e| e.path == "textures/gen.png.meta"));
+
+        let dir = TempDir::new("ignore");
+        let changelog = ChangelogConfig {
+            ignore: vec!["shadercache/*".to_string(), "textures/gen.png".to_string()],
+            ..ChangelogConfig::default()
+        };
+        generate_changelog(Some(&diff), &dir, &Branch::Live, &[], &LangRenderOptions::default(), &changelog).unwrap();
+        let html = latest_page(&dir);
+        let md = latest_markdown(&dir);
+        let json: ChangesJson = serde_json::from_str(&fs::read_to_string(dir.join("changes.json")).unwrap()).unwrap();
+
+        for output in [&html, &md] {
+            assert!(!output.contains("shadercache") && !output.contains("gen.png ") && !output.contains("a.bin"));
+            assert!(output.contains("gen.png.meta") && output.contains("stone.png"));
+            assert!(output.contains("скрыто 4 изменения по фильтрам"));
+        }
+        assert_eq!(json.files.len(), 2);
+        assert_eq!((json.summary.added, json.summary.ignored), (2, 4));
+    }
+
+    #[test]
+    fn single_child_directory_chains_are_collapsed() {
+        let diff = diff_map_entries(&[], &[
+            entry("a/b/c/d/x.png", 1),
+            entry("a/b/y.png", 2),
+            entry("a/b/e/f/z.png", 3),
+            entry("top/only/one/w.png", 4),
+        ]);
+        let dir = TempDir::new("collapse");
+        let html = comparison_page(&diff, &dir);
+
+        let labels: Vec<&str> = html
+            .split(r#"<summary class="name">"#)
+            .skip(1)
+            .map(|rest| &rest[..rest.find(r#" <span class="badges">"#).unwrap()])
+            .collect();
+        // a/b содержит файл y.png, поэтому не сливается со своими подпапками
+        assert_eq!(labels, ["a/b", "c/d", "e/f", "top/only/one"]);
+        assert_eq!(html.matches("<details class=\"directory\"").count(), html.matches("</details>").count());
+        assert!(html.contains(r#"<div class="path">a/b/c/d</div>"#));
+    }
+
+    #[test]
+    fn directory_badges_count_changes_recursively() {
+        let diff = diff_map_entries(
+            &[entry("items/old.png", 1), entry("items/weapon/ak.png", 2), entry("items/weapon/gone.png", 3)],
+            &[
+                entry("items/old.png", 4),
+                entry("items/weapon/ak.png", 5),
+                entry("items/weapon/new.png", 6),
+                entry("items/armor/new.png", 7),
+            ],
+        );
+        let tree = DirNode::build(changes_by_dir(&diff));
+        let items = &tree.children[0].1;
+        assert_eq!((items.added, items.modified, items.deleted), (2, 2, 1));
+        let weapon = &items.children.iter().find(|(name, _)| name == "weapon").unwrap().1;
+        assert_eq!((weapon.added, weapon.modified, weapon.deleted), (1, 1, 1));
+
+        let mut html = String::new();
+        tree.render_children("", &mut html, 0, &ChangelogConfig::default());
+        assert!(html.contains(concat!(
+            r#"items <span class="badges"><span class="added">+2</span> <span class="modified">~2</span> "#,
+            r#"<span class="deleted">-1</span></span>"#
+        )));
+        assert!(html.contains(r#"armor <span class="badges"><span class="added">+1</span></span>"#));
+    }
+
+    #[test]
+    fn directories_below_max_depth_are_summarized() {
+        let diff = diff_map_entries(
+            &[entry("items/weapon/rifles/ak.png", 1), entry("items/weapon/gone.png", 2)],
+            &[
+                entry("items/old.png", 3),
+                entry("items/weapon/rifles/ak.png", 4),
+                entry("items/weapon/rifles/new.png", 5),
+                entry("items/armor/new.png", 6),
+            ],
+        );
+        let tree = DirNode::build(changes_by_dir(&diff));
+        let changelog = ChangelogConfig {
+            max_depth: 1,
+            ..ChangelogConfig::default()
+        };
+        let mut html = String::new();
+        tree.render_children("", &mut html, 0, &changelog);
+
+        // Верхний уровень раскрыт, его файлы и счётчики на месте; подпапки свёрнуты в строки
+        assert_eq!(html.matches("<details").count(), 1);
+        assert!(html.contains(concat!(
+            r#"items <span class="badges"><span class="added">+3</span> <span class="modified">~1</span> "#,
+            r#"<span class="deleted">-1</span></span>"#
+        )));
+        assert!(html.contains("+ old.png"));
+        assert!(html.contains(concat!(
+            r#"<div class="file deeper" title="items/weapon">weapon/ <span class="badges">"#,
+            r#"<span class="added">+1</span> <span class="modified">~1</span> "#,
+            r#"<span class="deleted">-1</span></span></div>"#
+        )));
+        assert!(html.contains(r#"title="items/armor">armor/ <span class="badges"><span class="added">+1</span>"#));
+        assert!(!html.contains("rifles") && !html.contains("ak.png"));
+
+        // 0 - без ограничения
+        let mut full = String::new();
+        tree.render_children("", &mut full, 0, &ChangelogConfig::default());
+        assert!(full.contains("<summary class=\"name\">rifles ") && !full.contains("deeper"));
+    }
+
+    #[test]
+    fn tree_names_are_escaped() {
+        let diff = diff_map_entries(&[entry("x&y/old.png", 1)], &[
+            entry("x&y/a<b>&c.png", 2),
+            entry("d<i>r/a<b>&c.png", 1),
+        ]);
+        let dir = TempDir::new("escape");
+        let html = comparison_page(&diff, &dir);
+
+        assert!(html.contains("+ a&lt;b&gt;&amp;c.png"));
+        assert!(html.contains(r#"<summary class="name">x&amp;y <span"#));
+        assert!(html.contains(r#"<div class="path">x&amp;y</div>"#));
+        assert!(html.contains("x&amp;y/old.png → d&lt;i&gt;r/a&lt;b&gt;&amp;c.png"));
+        assert!(!html.contains("<b>") && !html.contains("<i>"));
+    }
+
+    #[test]
+    fn text_changelog_fits_discord_limit() {
+        let new: Vec<MapEntry> = (0..200).map(|i| entry(&format!("items/weapon/rifles/gun_{}.png", i), 1)).collect();
+        let diff = diff_map_entries(&[entry("sounds/deep/er/a.ogg", 2)], &new);
+        let lang_diffs = [diff_lang_content("ru", "a = 1\n", "a = 2\nb = 3\nc = 4\n")];
+        let changelog = ChangelogConfig {
+            text_limit: 400,
+            text_depth: 1,
+            text_lang_changes: 2,
+            ..ChangelogConfig::default()
+        };
+        let url = "https://example.org/patches/1.html";
+        let stats = diff.stats();
+        let text = generate_changelog_text(&diff, &stats, "01.02.2026", &lang_diffs, &changelog, url);
+
+        assert!(text.starts_with("**Патчноут 01.02.2026**\nФайлы: +200 ~0 -1"));
+        // Глубина 1: файлы внутри items/weapon/rifles видны только по счётчику, и всё умещается целиком
+        assert!(text.contains("```\nitems/weapon/rifles/ +200\nsounds/deep/er/ -1\n```\n"));
+        assert!(!text.contains("gun_") && !text.contains("Полная версия"));
+        assert!(text.ends_with("```diff\n~a = 1\n~a => 2\n+b = 3\n```\n…и ещё 1\n"));
+
+        let changelog = ChangelogConfig {
+            text_depth: 3,
+            ..changelog
+        };
+        let text = generate_changelog_text(&diff, &stats, "01.02.2026", &lang_diffs, &changelog, url);
+        assert!(text.chars().count() <= 400);
+        assert!(text.contains("  + gun_0.png\n"));
+        assert!(text.ends_with(&format!("```\n…\nПолная версия: {}", url)));
+        assert_eq!(text.matches("```").count() % 2, 0);
+    }
+
+    #[test]
+    fn telegram_message_escapes_every_special_character() {
+        let special = r"_*[]()~`>#+-=|{}.!\";
+        assert_eq!(tg_escape(special), r"\_\*\[\]\(\)\~\`\>\#\+\-\=\|\{\}\.\!\\");
+        assert_eq!(tg_escape("Патчноут 01.02"), r"Патчноут 01\.02");
+        assert_eq!(tg_escape_code(special), r"_*[]()~\`>#+-=|{}.!\\");
+
+        let path = format!("items/{}.png", special);
+        let diff = diff_map_entries(&[entry("old (1).png", 1)], &[entry(&path, 2)]);
+        let lang_diffs = [diff_lang_content("ru", "", &format!("key.a_b = {}\n", special))];
+        let url = "https://example.org/patches/(1).html";
+        let changelog = ChangelogConfig::default();
+        let message = format_telegram_message(&diff, &diff.stats(), "01.02.2026", &lang_diffs, &changelog, url);
+
+        assert!(message.starts_with("*Патчноут 01\\.02\\.2026*\n*Файлы: \\+1 \\~0 \\-1*\n"));
+        assert!(message.contains(r"• `items/_*[]()~\`>#+-=|{}.!\\.png`"));
+        assert!(message.contains("• `old (1).png`"));
+        assert!(message.contains("*Добавлено \\(1\\)*"));
+        assert!(message.contains(r"```diff"));
+        assert!(message.contains(r"+key.a_b = _*[]()~\`>#+-=|{}.!\\"));
+        assert!(message.ends_with(r"[Полная версия](https://example.org/patches/(1\).html)"));
+        let new: Vec<MapEntry> = (0..500).map(|i| entry(&format!("items/weapon/gun_{}.png", i), 3)).collect();
+        let big = diff_map_entries(&[], &new);
+        let message = format_telegram_message(&big, &big.stats(), "01.02.2026", &[], &changelog, url);
+        assert!(message.chars().count() <= TELEGRAM_MESSAGE_LIMIT);
+        assert!(message.ends_with(".png`\n…\n[Полная версия](https://example.org/patches/(1\\).html)"));
+    }
+
+    #[test]
+    fn bbcode_changelog_matches_golden_file() {
+        let diff = diff_map_entries(
+            &[
+                entry("assets/stalker/items/weapon/ak.png", 1),
+                entry("assets/stalker/items/weapon/old.png", 2),
+                entry("assets/stalker/items/armor/vest.png", 3),
+                entry("sounds/[beta].ogg", 4),
+                entry("readme.txt", 5),
+            ],
+            &[
+                entry("assets/stalker/items/weapon/ak.png", 6),
+                entry("assets/stalker/items/weapon/new.png", 7),
+                entry("assets/stalker/items/armor/vest.png", 8),
+                entry("assets/stalker/items/armor/heavy/exo/helmet.png", 9),
+                entry("sounds/beta.ogg", 4),
+                entry("readme.txt", 10),
+            ],
+        );
+        let lang_diffs = [diff_lang_content("ru", "item.ak.name = АК\n", "item.ak.name = АК-74\nui.new = Новое\n")];
+        let changelog = ChangelogConfig {
+            bbcode_depth: 2,
+            ..ChangelogConfig::default()
+        };
+        let url = "https://builderssc.github.io/Krevetka/patches/2026-02-01_1200.html";
+        let bbcode = generate_changelog_bbcode(&diff, &diff.stats(), "01.02.2026", &lang_diffs, &changelog, url);
+        let golden = include_str!("../tests/golden/changelog.bbcode").replace("\r\n", "\n");
+        assert_eq!(bbcode, golden);
+    }
+
+    #[test]
+    fn lang_only_and_map_only_changelogs_skip_the_other_section() {
+        let dir = TempDir::new("only");
+        let changelog = ChangelogConfig::default();
+        let render = LangRenderOptions::default();
+        let lang_diffs = [diff_lang_content("ru", "a = 1\n", "a = 2\n")];
+        generate_changelog(None, &dir, &Branch::Live, &lang_diffs, &render, &changelog).unwrap();
+        let lang_only = latest_page(&dir);
+
+        // Устаревший diff прошлой публикации рядом со страницей не должен попасть в патчноут
+        fs::write(dir.join("ru_changes.diff"), "+old.key = Старое\n").unwrap();
+        let diff = diff_map_entries(&[], &[entry("items/new.png", 1)]);
+        let unchanged = [LangDiff {
+            locale: "ru".to_string(),
+            ..LangDiff::default()
+        }];
+        generate_changelog(Some(&diff), &dir, &Branch::Live, &unchanged, &render, &changelog).unwrap();
+        let map_only = latest_page(&dir);
+
+        assert!(!lang_only.contains("Изменения файловой структуры") && !lang_only.contains("Записей в карте"));
+        assert!(lang_only.contains(r#"<div class="no-changes">Файловая структура не изменилась</div>"#));
+        assert!(lang_only.contains("<h2>Изменения в файле локализации ru.lang</h2>"));
+        assert!(map_only.contains("Изменения файловой структуры: +1 ~0 -0"));
+        assert!(map_only.contains("Изменений в локализации не обнаружено") && !map_only.contains("old.key"));
+    }
+
+    #[test]
+    fn changelog_page_has_offline_search() {
+        let diff = diff_map_entries(&[], &[entry("items/ak.png", 1)]);
+        let dir = TempDir::new("search");
+        let html = comparison_page(&diff, &dir);
+
+        // Без JS поле скрыто, а все разделы остаются раскрытыми
+        assert!(html.contains(r#"<input type="search" id="search" class="search""#));
+        assert!(html.contains(r#"placeholder="Поиск по файлам и ключам" hidden>"#));
+        assert!(html.contains(r#"<details class="directory" open>"#));
+        let script = &html[html.find("<script>").unwrap()..html.find("</script>").unwrap()];
+        assert!(script.contains("input.hidden = false"));
+        assert!(!script.contains("src=") && !script.contains("http"));
+        assert!(html.find(r#"id="search""#).unwrap() < html.find("<script>").unwrap());
+    }
+
+    #[test]
+    fn branding_is_configurable_and_assets_are_copied() {
+        let dir = TempDir::new("branding");
+        let assets = dir.join("assets");
+        fs::create_dir_all(&assets).unwrap();
+        fs::write(assets.join("logo.png"), [1, 2, 3]).unwrap();
+        let changelog = ChangelogConfig {
+            branding: BrandingConfig {
+                title_prefix: "Обновление".to_string(),
+                source_name: "Мой <бот>".to_string(),
+                source_url: "https://example.org/bot".to_string(),
+                logo: "logo.png".to_string(),
+                logo_url: "https://example.org".to_string(),
+                footer_links: vec![FooterLink {
+                    name: "Discord".to_string(),
+                    url: "https://discord.gg/example".to_string(),
+                }],
+                pattern: String::new(),
+                assets_dir: assets.to_string_lossy().into_owned(),
+            },
+            ..ChangelogConfig::default()
+        };
+        let output = dir.join("docs");
+        let diff = diff_map_entries(&[], &[entry("items/ak.png", 1)]);
+        generate_changelog(Some(&diff), &output, &Branch::Ots, &[], &LangRenderOptions::default(), &changelog).unwrap();
+        let page = latest_page(&output);
+        let index = fs::read_to_string(output.join("index.html")).unwrap();
+        let logo = fs::read(output.join("logo.png")).unwrap();
+
+        assert!(page.contains("<h1>Обновление ОТС "));
+        assert!(page.contains(r#"Источник: <a href="https://example.org/bot" target="_blank">Мой &lt;бот&gt;</a>"#));
+        assert!(page.contains(r#"<img src="logo.png" alt="Мой &lt;бот&gt; Logo">"#));
+        assert!(page.contains(r#"<a href="https://discord.gg/example" target="_blank">Discord</a>"#));
+        assert!(!page.contains("background-image") && !page.contains("icon.png"));
+        assert!(index.contains(">Обновление ОТС "));
+        assert_eq!(logo, [1, 2, 3]);
+
+        // Значения по умолчанию повторяют прежнюю вёрстку
+        let default_end = page_end(&BrandingConfig::default());
+        assert!(default_end.contains(r#"<a href="https://github.com/BuildersSC/Krevetka" target="_blank">"#));
+        assert!(default_end.contains(r#"<img src="icon.png" alt="Krevetka Logo">"#));
+        let default_start = page_start("x", None, &ChangelogConfig::default());
+        assert!(default_start.contains("background-image: url('pattern_anti_spectrum.png');"));
+    }
+
+    #[test]
+    fn labels_and_dates_follow_changelog_language() {
+        let dir = TempDir::new("language");
+        let changelog = ChangelogConfig {
+            language: "en".to_string(),
+            ..ChangelogConfig::default()
+        };
+        let diff = diff_map_entries(&[entry("items/old.png", 1)], &[entry("items/ak.png", 2)]);
+        let lang_diffs = [diff_lang_content("ru", "a = 1\n", "a = 2\n")];
+        let render = LangRenderOptions::default();
+        generate_changelog(Some(&diff), &dir, &Branch::Live, &lang_diffs, &render, &changelog).unwrap();
+        let page = latest_page(&dir);
+        let markdown = latest_markdown(&dir);
+        let text = fs::read_to_string(dir.join("changelog.txt")).unwrap();
+        let feed = fs::read_to_string(dir.join("feed.xml")).unwrap();
+
+        let date = chrono::Local::now().format("%b %-d, %Y").to_string();
+        assert!(page.contains(r#"<html lang="en">"#));
+        assert!(page.contains(&format!("<h1>Patch notes {}</h1>", date)));
+        assert!(page.contains(r#"<span class="added">+1 added</span>"#));
+        assert!(page.contains("<h2>Changes in localization file ru.lang</h2>"));
+        assert!(page.contains(r#"placeholder="Search files and keys""#));
+        assert!(markdown.starts_with(&format!("# Patch notes {}\n\n**File structure changes:** ", date)));
+        assert!(text.contains("Files: +1 ~0 -1, top-level folders: 1\nLocalization keys: +0 ~1 -0\n"));
+        assert!(feed.contains("<title>Krevetka: STALCRAFT patch notes</title>"));
+        assert!(feed.contains("Localization: 1 change (~a)"));
+        // Русских подписей не остаётся ни в одном файле; комментарии в скрипте поиска - не подписи
+        let page = page[..page.find("<script>").unwrap()].to_string();
+        assert!(page.contains("Other <span class=\"group-count\">(1)</span>"));
+        for output in [&page, &markdown, &text, &feed] {
+            assert!(!output.chars().any(|c| matches!(c, 'а'..='я' | 'А'..='Я')), "{}", output);
+        }
+    }
+
+    #[test]
+    fn lang_values_are_unescaped_for_display() {
+        let change = LangChange::Added {
+            key: "item.desc".to_string(),
+            value: Some(r"&cОпасно!&r\nВторая <строка>".to_string()),
+        };
+        let stripped = lang_change_html(&change, &LangRenderOptions::default(), &RU);
+        assert!(stripped.contains("item.desc = Опасно!<br>Вторая &lt;строка&gt;"));
+
+        let render = LangRenderOptions {
+            render_colors: true,
+            ..LangRenderOptions::default()
+        };
+        let colored = lang_change_html(&change, &render, &RU);
+        assert!(colored.contains(r#"<span class="color-c">Опасно!</span><br>Вторая"#));
+    }
+
+    #[test]
+    fn lang_changes_are_grouped_by_namespace() {
+        assert_eq!(lang_group("ui.news.title"), Some("ui.news"));
+        assert_eq!(lang_group("item.name"), Some("item"));
+        assert_eq!(lang_group("title"), None);
+
+        let changes: Vec<_> = ["standalone", "ui.news.title", "item.name", "ui.news.text", "item.desc"]
+            .iter()
+            .map(|key| LangChange::Added {
+                key: key.to_string(),
+                value: None,
+            })
+            .collect();
+        let mut html = String::new();
+        push_lang_groups(&mut html, &changes, &LangRenderOptions::default(), &RU);
+        let position = |text: &str| html.find(text).unwrap();
+
+        assert!(html.contains("ui.news <span class=\"group-count\">(2)</span>"));
+        assert!(html.contains("item <span class=\"group-count\">(2)</span>"));
+        assert!(position("ui.news.text") < position("item <span"));
+        assert!(position("item.desc") < position("Прочее"));
+        assert!(position("Прочее") < position(">standalone<"));
+    }
+
+    #[test]
+    fn lang_history_since_publish_keeps_every_unpublished_diff() {
+        let dir = TempDir::new("lang_history");
+        let write = |name: &str, content: &str| fs::write(dir.join(name), content).unwrap();
+        write("lang_ru_20260101_100000_000.diff", "+old.key = Опубликовано\n");
+        write("lang_ru_20260101_120000_000.diff", "+first.key = Первое\n");
+        write("lang_en_20260101_123000_000.diff", "-gone.key = Gone\n");
+        write("lang_ru_20260101_130000_000.diff", "~first.key = Первое\n~first.key => Второе\n");
+        write(LAST_PUBLISH_FILE, "20260101_110000_000");
+
+        let diffs = lang_changes_since_publish(&dir).unwrap();
+        mark_published(&dir).unwrap();
+        let after_publish = lang_changes_since_publish(&dir).unwrap();
+
+        assert_eq!(diffs.len(), 2);
+        assert_eq!((diffs[0].locale.as_str(), diffs[1].locale.as_str()), ("ru", "en"));
+        // Ключ добавлен и затем изменён: в патчноуте одно добавление с последним значением
+        assert_eq!(
+            diffs[0].changes(),
+            [LangChange::Added {
+                key: "first.key".to_string(),
+                value: Some("Второе".to_string()),
+            }]
+        );
+        assert!(after_publish.is_empty());
+    }
+
+    #[test]
+    fn lang_history_since_publish_merges_changes_of_one_key() {
+        let dir = TempDir::new("lang_history_merge");
+        let write = |name: &str, content: &str| fs::write(dir.join(name), content).unwrap();
+        write("lang_ru_20260101_120000_000.diff", "+temp.key = Временный\n~title = Старое\n~title => Среднее\n");
+        write("lang_ru_20260101_130000_000.diff", "-temp.key = Временный\n~title = Среднее\n~title => Новое\n");
+
+        let diffs = lang_changes_since_publish(&dir).unwrap();
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(
+            diffs[0].changes(),
+            [LangChange::Modified {
+                key: "title".to_string(),
+                old: Some("Старое".to_string()),
+                new: Some("Новое".to_string()),
+                revert: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn lang_history_notes_reach_the_page() {
+        let dir = TempDir::new("lang_history_notes");
+        let history = dir.join("history");
+        fs::create_dir_all(&history).unwrap();
+        let header = |fallback: usize| LangDiffHeader {
+            fallback_lines: fallback,
+            ..LangDiffHeader::default()
+        };
+        let first = header(2).render() + "+first.key = Первое\n# Скрыто фильтром lang.ignore_keys: 1\n";
+        let second = header(1).render() + "+second.key = Второе\n# Скрыто фильтром lang.ignore_keys: 2\n";
+        fs::write(history.join("lang_ru_20260101_120000_000.diff"), first).unwrap();
+        fs::write(history.join("lang_ru_20260101_130000_000.diff"), second).unwrap();
+
+        let lang_diffs = lang_changes_since_publish(&history).unwrap();
+        let output = dir.join("docs");
+        let render = LangRenderOptions::default();
+        generate_changelog(None, &output, &Branch::Live, &lang_diffs, &render, &ChangelogConfig::default()).unwrap();
+        let html = latest_page(&output);
+
+        assert_eq!((lang_diffs[0].fallback_lines, lang_diffs[0].filtered), (3, 3));
+        assert!(html.contains("строк, прочитанных как CP1251: 3"));
+        assert!(html.contains("и ещё 3 изменения в ключах из lang.ignore_keys"));
+    }
+
+    #[test]
+    fn map_only_cycle_does_not_republish_old_lang_changes() {
+        let dir = TempDir::new("stale_lang");
+        let history = dir.join("history");
+        fs::create_dir_all(&history).unwrap();
+        fs::write(history.join("lang_ru_20260101_100000_000.diff"), "+old.key = Вчерашнее\n").unwrap();
+        fs::write(history.join(LAST_PUBLISH_FILE), "20260101_110000_000").unwrap();
+        // Следующий diff записан пустым: ни он, ни опубликованный вчера не должны попасть в новый патчноут
+        fs::write(history.join(format!("lang_ru_{}.diff", history_timestamp())), "").unwrap();
+
+        let lang_diffs = lang_changes_since_publish(&history).unwrap();
+        let diff = diff_map_entries(&[], &[entry("items/new.png", 1)]);
+        let output = dir.join("docs");
+        let render = LangRenderOptions::default();
+        generate_changelog(Some(&diff), &output, &Branch::Live, &lang_diffs, &render, &ChangelogConfig::default())
+            .unwrap();
+        let html = latest_page(&output);
+
+        assert!(lang_diffs.is_empty());
+        assert!(!html.contains("old.key"));
+        assert!(html.contains("Изменений в локализации не обнаружено"));
+    }
 }
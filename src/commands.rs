@@ -0,0 +1,198 @@
+use crate::changelog::{generate_branch_comparison, generate_comparison_changelog, LangRenderOptions};
+use crate::config::load_config_or_default;
+use crate::error::AppError;
+use crate::lang::{
+    compare_branches, compare_game_branches, format_lang_changes, lang_changes_json, lang_diff_path, preview_lang_diff,
+    read_lang_diff, LangDiff, LangError,
+};
+use crate::map::{
+    export_map_csv, export_map_json, get_game_path, get_stalcraft_map_path, read_map_entries, resolve_environment_root,
+    Branch, Environment, MapReadOptions,
+};
+use crate::monitor::diff_filtered;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// krevetka export [файл.json|файл.csv]
+pub fn export_map(args: &[String]) -> Result<(), AppError> {
+    let out_path = args.first().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("stalcraft_map.json"));
+    let read_options = MapReadOptions::from_config(&load_config_or_default()?.map);
+    let entries = read_map_entries(&get_stalcraft_map_path()?, &read_options)?;
+    match out_path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => export_map_csv(&entries, &out_path)?,
+        _ => export_map_json(&entries, &out_path)?,
+    }
+    println!("Выгружено {} записей в {}", entries.len(), out_path.display());
+    Ok(())
+}
+
+// krevetka compare old.map new.map [--out папка] [--format json]
+pub fn compare_maps(args: &[String]) -> Result<(), AppError> {
+    let mut paths = Vec::new();
+    let mut out_dir = PathBuf::from("changes").join("compare");
+    let mut json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--out" => {
+                out_dir = iter
+                    .next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| AppError::Usage("После --out нужно указать папку".to_string()))?
+            }
+            "--format" => match iter.next().map(String::as_str) {
+                Some("json") => json = true,
+                Some("html") => json = false,
+                other => return Err(AppError::Usage(format!("Неизвестный формат: {}", other.unwrap_or("")))),
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [old_map, new_map] = paths.as_slice() else {
+        return Err(AppError::Usage(
+            "Использование: krevetka compare old.map new.map [--out папка] [--format json]".to_string(),
+        ));
+    };
+
+    let config = load_config_or_default()?;
+    let map_config = config.map;
+    let read_options = MapReadOptions::from_config(&map_config);
+    let old_entries = read_map_entries(old_map, &read_options)
+        .inspect_err(|_| eprintln!("Не удалось прочитать {}", old_map.display()))?;
+    let new_entries = read_map_entries(new_map, &read_options)
+        .inspect_err(|_| eprintln!("Не удалось прочитать {}", new_map.display()))?;
+    let diff = diff_filtered(old_entries, new_entries, &map_config, false);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        let title = format!("{} → {}", old_map.display(), new_map.display());
+        let stats = generate_comparison_changelog(&diff, &out_dir, &title, &config.changelog)?;
+        println!("Изменения: {}. Патчноут сохранён в {}", stats, out_dir.join("index.html").display());
+    }
+    Ok(())
+}
+
+// krevetka compare-lang [live.lang ots.lang] [--out папка]
+// Без путей сравниваются локализации из lang.locales в папке игры
+pub fn compare_lang(args: &[String]) -> Result<(), AppError> {
+    let mut paths = Vec::new();
+    let mut out_dir = PathBuf::from("changes").join("ots_compare");
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--out" => {
+                out_dir = iter
+                    .next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| AppError::Usage("После --out нужно указать папку".to_string()))?
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let config = load_config_or_default()?;
+    let lang_config = config.lang;
+    let diffs = match paths.as_slice() {
+        [] => compare_game_branches(&get_game_path()?, &lang_config.locales)?,
+        [live, ots] => vec![compare_branches(live, ots)?],
+        _ => {
+            return Err(AppError::Usage(
+                "Использование: krevetka compare-lang [live.lang ots.lang] [--out папка]".to_string(),
+            ))
+        }
+    };
+    for diff in &diffs {
+        println!(
+            "{}: только на ОТС {}, только на основном сервере {}, отличаются {}",
+            diff.locale,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.modified.len()
+        );
+    }
+    let render = LangRenderOptions::from_config(&lang_config);
+    generate_branch_comparison(&diffs, &out_dir, &render, &config.changelog)?;
+    println!("Отчёт сохранён в {}", out_dir.join("index.html").display());
+    Ok(())
+}
+
+// krevetka lang-diff [--filter шаблон]... [--format text|json] [--locale код] [--fresh]
+// Без --fresh берётся последний сохранённый diff, с --fresh файл игры сравнивается с копией окружения.
+// ExitCode::FAILURE, если под фильтр не подошло ни одного изменения
+pub fn lang_diff(args: &[String]) -> Result<ExitCode, AppError> {
+    let config = load_config_or_default()?;
+    let mut patterns = Vec::new();
+    let mut json = false;
+    let mut fresh = false;
+    let mut locale = config.lang.locales.first().cloned().unwrap_or_else(|| "ru".to_string());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--filter" => patterns.push(
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| AppError::Usage("После --filter нужно указать шаблон ключа".to_string()))?,
+            ),
+            "--format" => match iter.next().map(String::as_str) {
+                Some("json") => json = true,
+                Some("text") => json = false,
+                other => return Err(AppError::Usage(format!("Неизвестный формат: {}", other.unwrap_or("")))),
+            },
+            "--locale" => {
+                locale = iter
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| AppError::Usage("После --locale нужно указать код локали".to_string()))?
+            }
+            "--fresh" => fresh = true,
+            _ => {
+                return Err(AppError::Usage(
+                    "Использование: krevetka lang-diff [--filter шаблон]... [--format text|json] [--locale код] [--fresh]"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+
+    let mut diff = if fresh {
+        let env = Environment::new(&resolve_environment_root(None, &config.environment), Branch::Live);
+        preview_lang_diff(&get_game_path()?, &env, &locale, &config.lang)?.unwrap_or_default()
+    } else {
+        let path = lang_diff_path(&Branch::Live, &locale);
+        match read_lang_diff(&path) {
+            Ok((_, changes)) => LangDiff::from_changes(&locale, changes),
+            Err(LangError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => LangDiff::default(),
+            Err(e) => return Err(AppError::Lang(e)),
+        }
+    };
+    if !patterns.is_empty() {
+        diff.retain_keys(&patterns);
+    }
+    if diff.is_empty() {
+        if patterns.is_empty() {
+            eprintln!("Нет изменений локализации {}", locale);
+        } else {
+            eprintln!("Нет изменений локализации {}, подходящих под фильтр", locale);
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+    if json {
+        println!("{}", lang_changes_json(&diff)?);
+    } else {
+        print!("{}", format_lang_changes(&diff.changes()));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lang_diff_reports_empty_result_as_exit_code() {
+        let args = ["--locale".to_string(), "zz_missing".to_string()];
+        assert_eq!(lang_diff(&args).unwrap(), ExitCode::FAILURE);
+        assert!(matches!(lang_diff(&["--bogus".to_string()]), Err(AppError::Usage(_))));
+        assert!(matches!(compare_maps(&["one.map".to_string()]), Err(AppError::Usage(_))));
+    }
+}
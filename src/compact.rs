@@ -0,0 +1,101 @@
+use crate::map::{MapEntry, MapHash};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Запись карты с общей папкой: почти все пути начинаются с одних и тех же "assets/stalker/..."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactMapEntry {
+    // Папка вместе с завершающим '/', пустая для файлов в корне
+    dir: Arc<str>,
+    file: Box<str>,
+    pub hash: MapHash,
+}
+
+impl CompactMapEntry {
+    pub fn path(&self) -> String {
+        let mut path = String::with_capacity(self.dir.len() + self.file.len());
+        path.push_str(&self.dir);
+        path.push_str(&self.file);
+        path
+    }
+
+    pub fn to_entry(&self) -> MapEntry {
+        MapEntry {
+            path: self.path(),
+            hash: self.hash,
+        }
+    }
+}
+
+/// Карта, в которой каждая папка хранится один раз
+#[derive(Debug, Clone, Default)]
+pub struct CompactMap {
+    entries: Vec<CompactMapEntry>,
+}
+
+impl CompactMap {
+    pub fn from_entries(entries: &[MapEntry]) -> Self {
+        let mut dirs: HashSet<Arc<str>> = HashSet::new();
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let split = entry.path.rfind('/').map_or(0, |idx| idx + 1);
+                let (dir, file) = entry.path.split_at(split);
+                let dir = match dirs.get(dir) {
+                    Some(dir) => dir.clone(),
+                    None => {
+                        let dir: Arc<str> = Arc::from(dir);
+                        dirs.insert(dir.clone());
+                        dir
+                    }
+                };
+                CompactMapEntry {
+                    dir,
+                    file: file.into(),
+                    hash: entry.hash,
+                }
+            })
+            .collect();
+        CompactMap { entries }
+    }
+
+    /// Порядок записей совпадает с исходным, поэтому diff по восстановленным записям не меняется
+    pub fn to_entries(&self) -> Vec<MapEntry> {
+        self.entries.iter().map(CompactMapEntry::to_entry).collect()
+    }
+
+    /// Как to_entries, но сжатые записи освобождаются по мере разворачивания
+    pub fn into_entries(self) -> Vec<MapEntry> {
+        self.entries.into_iter().map(|entry| entry.to_entry()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_paths() {
+        let entries: Vec<_> = ["assets/stalker/a.png", "assets/stalker/b.png", "root.txt", "/lead", "dir/"]
+            .iter()
+            .enumerate()
+            .map(|(i, path)| MapEntry {
+                path: path.to_string(),
+                hash: MapHash::from([i as u8; 20]),
+            })
+            .collect();
+        let compact = CompactMap::from_entries(&entries);
+        assert_eq!(compact.len(), entries.len());
+        assert_eq!(compact.to_entries(), entries);
+        assert!(Arc::ptr_eq(&compact.entries[0].dir, &compact.entries[1].dir));
+        assert_eq!(compact.into_entries(), entries);
+    }
+}
\ No newline at end of file
@@ -1,18 +1,459 @@
+use crate::labels::{find_labels, labels, Labels, LANGUAGES};
 use serde::Deserialize;
 use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct Config {
     pub github: GithubConfig,
+    #[serde(default)]
+    pub game: GameConfig,
+    #[serde(default)]
+    pub monitor: MonitorConfig,
+    #[serde(default)]
+    pub map: MapConfig,
+    #[serde(default)]
+    pub environment: EnvironmentConfig,
+    #[serde(default)]
+    pub lang: LangConfig,
+    #[serde(default)]
+    pub changelog: ChangelogConfig,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 pub struct GithubConfig {
     pub token: String,
 }
 
-pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+#[derive(Deserialize, Default)]
+pub struct GameConfig {
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct EnvironmentConfig {
+    // Папка с копиями файлов игры; относительный путь считается от папки с программой
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+pub struct LangConfig {
+    // Коды локализаций игры (имена файлов <код>.lang), изменения которых попадают в патчноут
+    #[serde(default = "default_locales")]
+    pub locales: Vec<String>,
+    // Ветки, локализация которых отслеживается в одном цикле (live, ots, имя runtime); у каждой свои
+    // копии в environment и свои артефакты в changes/<ветка>
+    #[serde(default = "default_lang_branches")]
+    pub branches: Vec<String>,
+    // Формат файла changes/<ветка>/<код>_changes.diff: по ключам или стандартный unified diff
+    #[serde(default)]
+    pub diff_format: LangDiffFormat,
+    // Ключи, изменения которых не попадают в патчноут: точные имена или шаблоны с * и ? (ui.news.*)
+    #[serde(default)]
+    pub ignore_keys: Vec<String>,
+    // Сколько diff хранить в changes/<ветка>/history (0 - все)
+    #[serde(default = "default_lang_history_retention")]
+    pub history_retention: usize,
+    // Сколько принятых версий каждого файла хранить в environment/lang/snapshots (0 - не сохранять)
+    #[serde(default = "default_snapshot_retention")]
+    pub snapshot_retention: usize,
+    // При отслеживании ОТС сравнивать его локализацию с основным сервером и писать отчёт в docs/ots_compare
+    #[serde(default)]
+    pub compare_ots: bool,
+    // Отслеживать все файлы */lang/*.lang в modassets/assets, а не только stalker/lang/<код>.lang
+    #[serde(default)]
+    pub scan_tree: bool,
+    // Не считать изменением разницу в пробелах и табуляциях внутри значения
+    #[serde(default)]
+    pub collapse_whitespace: bool,
+    // Отбрасывать комментарии в конце значения (`ключ = текст  # заметка`, `// заметка`) вне кавычек
+    #[serde(default)]
+    pub strip_inline_comments: bool,
+    // Показывать цветовые коды &a / §a в патчноуте цветом, а не убирать их из текста
+    #[serde(default)]
+    pub render_colors: bool,
+    // Сколько изменений локализации показывать в патчноуте, остальные - в приложенном diff (0 - все)
+    #[serde(default = "default_lang_max_rendered")]
+    pub max_rendered: usize,
+    // Сколько изменений локализации с последней публикации нужно, чтобы опубликовать патчноут
+    #[serde(default = "default_lang_min_changes")]
+    pub min_changes: usize,
+    // Ключи, изменения которых пишутся в историю, но сами публикацию не вызывают
+    #[serde(default)]
+    pub quiet_keys: Vec<String>,
+    // Свои категории изменений в патчноуте; проверяются раньше встроенных (Оружие, Броня, Артефакты...)
+    #[serde(default)]
+    pub categories: Vec<LangCategoryRule>,
+}
+
+/// Категория изменений локализации: имя раздела в патчноуте и шаблоны ключей с * и ?
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LangCategoryRule {
+    pub name: String,
+    pub keys: Vec<String>,
+}
+
+impl Default for LangConfig {
+    fn default() -> Self {
+        LangConfig {
+            locales: default_locales(),
+            branches: default_lang_branches(),
+            diff_format: LangDiffFormat::default(),
+            ignore_keys: Vec::new(),
+            history_retention: default_lang_history_retention(),
+            snapshot_retention: default_snapshot_retention(),
+            compare_ots: false,
+            scan_tree: false,
+            collapse_whitespace: false,
+            strip_inline_comments: false,
+            render_colors: false,
+            max_rendered: default_lang_max_rendered(),
+            min_changes: default_lang_min_changes(),
+            quiet_keys: Vec::new(),
+            categories: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LangDiffFormat {
+    #[default]
+    Keys,
+    // Построчный diff с заголовками ---/+++ и контекстом, который понимают patch, git apply и редакторы
+    Unified,
+}
+
+#[derive(Deserialize)]
+pub struct MonitorConfig {
+    // Следить ли за картой тестового сервера (ОТС)
+    #[serde(default)]
+    pub ots: bool,
+    // Следить за всеми найденными папками runtime со своей картой (ивенты, экспериментальные сборки)
+    #[serde(default)]
+    pub all_runtimes: bool,
+    // Как узнавать об изменениях: уведомления файловой системы или опрос раз в секунду
+    #[serde(default)]
+    pub watch: WatchMode,
+    // Сколько ждать тишины после последнего события: лаунчер пишет файл частями
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        MonitorConfig {
+            ots: false,
+            all_runtimes: false,
+            watch: WatchMode::default(),
+            debounce_ms: default_debounce_ms(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    #[default]
+    Notify,
+    // Для сетевых дисков, где уведомления приходят ненадёжно
+    Poll,
+}
+
+#[derive(Deserialize)]
+pub struct MapConfig {
+    // Файлы карты крупнее этого размера (в байтах) разбираются и сравниваются параллельно
+    #[serde(default = "default_parallel_threshold")]
+    pub parallel_threshold: u64,
+    // Сколько прошлых версий карты хранить в environment/snapshots (0 - не сохранять)
+    #[serde(default = "default_snapshot_retention")]
+    pub snapshot_retention: usize,
+    // Префиксы путей (допускаются * и ?), которые попадают в патчноут; пустой список - все пути
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // Сохранять полный список путей карты в environment/<ветка>/listings при каждом изменении
+    #[serde(default)]
+    pub export_listing: bool,
+    // Сколько списков хранить (0 - все)
+    #[serde(default = "default_listing_retention")]
+    pub listing_retention: usize,
+    // Папка с распакованными ассетами (modassets) для сверки изменённых файлов по SHA-1
+    #[serde(default)]
+    pub assets_root: Option<PathBuf>,
+    // Сколько изменённых файлов сверять за одно изменение карты
+    #[serde(default = "default_verify_sample")]
+    pub verify_sample: usize,
+    // Максимальная длина пути записи в байтах; формат допускает до 65535
+    #[serde(default = "default_max_path_len")]
+    pub max_path_len: usize,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            parallel_threshold: default_parallel_threshold(),
+            snapshot_retention: default_snapshot_retention(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            export_listing: false,
+            listing_retention: default_listing_retention(),
+            assets_root: None,
+            verify_sample: default_verify_sample(),
+            max_path_len: default_max_path_len(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangelogConfig {
+    // Адрес опубликованного сайта: ссылки ленты feed.xml ведут на страницы патчноутов внутри него
+    #[serde(default = "default_site_url")]
+    pub site_url: String,
+    // Сколько последних патчноутов держать в ленте feed.xml
+    #[serde(default = "default_feed_entries")]
+    pub feed_entries: usize,
+    // Не показывать хэши файлов из карты в подсказках и в Markdown
+    #[serde(default)]
+    pub hide_hashes: bool,
+    // Сколько уровней папок раскрывать на странице патчноута; глубже - одна строка со счётчиками, 0 - все
+    #[serde(default)]
+    pub max_depth: usize,
+    // Шаблоны путей (* и ?, сравнивается весь путь), изменения в которых не показываются в патчноуте
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    // Текстовый патчноут changelog.txt для Discord: предел длины в символах (2000, с Nitro 4000),
+    // глубина дерева папок и сколько изменений локализации показать
+    #[serde(default = "default_text_limit")]
+    pub text_limit: usize,
+    #[serde(default = "default_text_depth")]
+    pub text_depth: usize,
+    #[serde(default = "default_text_lang_changes")]
+    pub text_lang_changes: usize,
+    // Сколько спойлеров можно вложить друг в друга в changelog.bbcode; более глубокие папки идут списком
+    #[serde(default = "default_bbcode_depth")]
+    pub bbcode_depth: usize,
+    // Язык подписей и дат в патчноуте: ru, en (см. labels::LANGUAGES)
+    #[serde(default = "default_language")]
+    pub language: String,
+    #[serde(default)]
+    pub branding: BrandingConfig,
+}
+
+impl Default for ChangelogConfig {
+    fn default() -> Self {
+        ChangelogConfig {
+            site_url: default_site_url(),
+            feed_entries: default_feed_entries(),
+            hide_hashes: false,
+            max_depth: 0,
+            ignore: Vec::new(),
+            text_limit: default_text_limit(),
+            text_depth: default_text_depth(),
+            text_lang_changes: default_text_lang_changes(),
+            bbcode_depth: default_bbcode_depth(),
+            language: default_language(),
+            branding: BrandingConfig::default(),
+        }
+    }
+}
+
+impl ChangelogConfig {
+    /// Подписи и форматы дат на языке changelog.language
+    pub fn labels(&self) -> &'static Labels {
+        labels(&self.language)
+    }
+
+    /// Начало заголовка патчноута: branding.title_prefix, а если он не задан - подпись языка
+    pub fn title_prefix(&self) -> &str {
+        if self.branding.title_prefix.is_empty() {
+            self.labels().patch
+        } else {
+            &self.branding.title_prefix
+        }
+    }
+}
+
+fn default_text_limit() -> usize {
+    2000
+}
+
+fn default_text_depth() -> usize {
+    2
+}
+
+fn default_text_lang_changes() -> usize {
+    10
+}
+
+fn default_bbcode_depth() -> usize {
+    3
+}
+
+fn default_language() -> String {
+    "ru".to_string()
+}
+
+/// Оформление страниц патчноута; значения по умолчанию повторяют прежнюю вёрстку
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BrandingConfig {
+    // Начало заголовка патчноута: "<title_prefix> 01.02.2026"; пустая строка - подпись из changelog.language
+    #[serde(default)]
+    pub title_prefix: String,
+    // Подпись и ссылка в строке "Источник" под сводкой
+    #[serde(default = "default_source_name")]
+    pub source_name: String,
+    #[serde(default = "default_source_url")]
+    pub source_url: String,
+    // Картинка в подвале и ссылка с неё; пустая строка - без картинки
+    #[serde(default = "default_logo")]
+    pub logo: String,
+    #[serde(default = "default_logo_url")]
+    pub logo_url: String,
+    // Текстовые ссылки в подвале рядом с картинкой
+    #[serde(default)]
+    pub footer_links: Vec<FooterLink>,
+    // Фоновый узор страницы; пустая строка - без узора
+    #[serde(default = "default_pattern")]
+    pub pattern: String,
+    // Откуда копировать logo и pattern в папку со страницами
+    #[serde(default = "default_assets_dir")]
+    pub assets_dir: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FooterLink {
+    pub name: String,
+    pub url: String,
+}
+
+impl Default for BrandingConfig {
+    fn default() -> Self {
+        BrandingConfig {
+            title_prefix: String::new(),
+            source_name: default_source_name(),
+            source_url: default_source_url(),
+            logo: default_logo(),
+            logo_url: default_logo_url(),
+            footer_links: Vec::new(),
+            pattern: default_pattern(),
+            assets_dir: default_assets_dir(),
+        }
+    }
+}
+
+fn default_source_name() -> String {
+    "Krevetka".to_string()
+}
+
+fn default_source_url() -> String {
+    "https://github.com/Art3mLapa".to_string()
+}
+
+fn default_logo() -> String {
+    "icon.png".to_string()
+}
+
+fn default_logo_url() -> String {
+    "https://github.com/BuildersSC/Krevetka".to_string()
+}
+
+fn default_pattern() -> String {
+    "pattern_anti_spectrum.png".to_string()
+}
+
+fn default_assets_dir() -> String {
+    "assets".to_string()
+}
+
+fn default_site_url() -> String {
+    "https://builderssc.github.io/Krevetka/".to_string()
+}
+
+fn default_feed_entries() -> usize {
+    20
+}
+
+fn default_locales() -> Vec<String> {
+    vec!["ru".to_string()]
+}
+
+fn default_lang_branches() -> Vec<String> {
+    vec!["live".to_string()]
+}
+
+fn default_debounce_ms() -> u64 {
+    1000
+}
+
+fn default_parallel_threshold() -> u64 {
+    4 * 1024 * 1024
+}
+
+fn default_snapshot_retention() -> usize {
+    10
+}
+
+fn default_listing_retention() -> usize {
+    30
+}
+
+fn default_lang_history_retention() -> usize {
+    100
+}
+
+fn default_lang_max_rendered() -> usize {
+    2000
+}
+
+fn default_lang_min_changes() -> usize {
+    1
+}
+
+fn default_verify_sample() -> usize {
+    100
+}
+
+fn default_max_path_len() -> usize {
+    1024
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Не удалось прочитать config.toml: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Ошибка в config.toml: {0}")]
+    ParseError(#[from] toml::de::Error),
+    #[error("Неизвестный changelog.language \"{language}\", поддерживаются: {supported}")]
+    UnknownLanguage { language: String, supported: String },
+}
+
+impl ConfigError {
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ConfigError::IoError(e) if e.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+pub fn load_config() -> Result<Config, ConfigError> {
     let config_content = fs::read_to_string("config.toml")?;
     let config: Config = toml::from_str(&config_content)?;
+    if find_labels(&config.changelog.language).is_none() {
+        let supported: Vec<_> = LANGUAGES.iter().map(|(code, _)| *code).collect();
+        return Err(ConfigError::UnknownLanguage {
+            language: config.changelog.language,
+            supported: supported.join(", "),
+        });
+    }
     Ok(config)
+}
+
+/// Без config.toml берутся значения по умолчанию; битый или нечитаемый конфиг - ошибка, а не молчаливый откат
+pub fn load_config_or_default() -> Result<Config, ConfigError> {
+    match load_config() {
+        Err(e) if e.is_not_found() => Ok(Config::default()),
+        result => result,
+    }
 }
\ No newline at end of file
@@ -0,0 +1,93 @@
+use crate::labels::RU;
+use crate::lang::{LangChange, LangDiff};
+use std::env;
+use std::io::{self, IsTerminal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Red,
+    Yellow,
+    Cyan,
+}
+
+impl Color {
+    fn ansi_code(self) -> &'static str {
+        match self {
+            Color::Green => "32",
+            Color::Red => "31",
+            Color::Yellow => "33",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+/// Цвет включается только в терминале и без NO_COLOR. Старая консоль Windows escape-последовательности
+/// не понимает, поэтому там цвет включается лишь в Windows Terminal, ConEmu, ANSICON и терминалах с TERM
+pub fn colors_enabled() -> bool {
+    if !io::stdout().is_terminal() || env::var_os("NO_COLOR").is_some() {
+        return false;
+    }
+    if env::var("TERM").is_ok_and(|term| term == "dumb") {
+        return false;
+    }
+    !cfg!(windows)
+        || env::var_os("WT_SESSION").is_some()
+        || env::var_os("ANSICON").is_some()
+        || env::var("ConEmuANSI").is_ok_and(|value| value == "ON")
+        || env::var_os("TERM").is_some()
+}
+
+pub fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Краткая сводка изменений локализации для консоли: первые limit строк и число оставшихся
+pub fn lang_summary(diff: &LangDiff, limit: usize, colors: bool) -> String {
+    let changes = diff.changes();
+    let mut summary = format!("{}: {}\n", diff.file_name(), (RU.changes_count)(changes.len()));
+    for change in changes.iter().take(limit) {
+        let (line, color) = match change {
+            LangChange::Added { key, value } => (format!("+ {} = {}", key, value.as_deref().unwrap_or("")), Color::Green),
+            LangChange::Removed { key, value } => (format!("- {} = {}", key, value.as_deref().unwrap_or("")), Color::Red),
+            LangChange::Modified { key, old, new, revert } => (
+                format!(
+                    "~ {}: {} -> {}{}",
+                    key,
+                    old.as_deref().unwrap_or(""),
+                    new.as_deref().unwrap_or(""),
+                    if *revert { " (откат)" } else { "" }
+                ),
+                Color::Yellow,
+            ),
+            LangChange::Renamed { old_key, new_key, .. } => (format!("> {} -> {}", old_key, new_key), Color::Cyan),
+        };
+        summary.push_str("  ");
+        summary.push_str(&paint(&line, color, colors));
+        summary.push('\n');
+    }
+    if changes.len() > limit {
+        summary.push_str(&format!("  ...и ещё {}\n", changes.len() - limit));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::diff_lang_content;
+
+    #[test]
+    fn lang_summary_is_limited_and_colored_on_request() {
+        let diff = diff_lang_content("ru", "a = 1\nb = 2\n", "a = 3\nc = 4\nd = 5\n");
+        assert_eq!(
+            lang_summary(&diff, 2, false),
+            "ru.lang: 4 изменения\n  ~ a: 1 -> 3\n  - b = 2\n  ...и ещё 2\n"
+        );
+        assert!(lang_summary(&diff, 1, true).contains("\x1b[33m~ a: 1 -> 3\x1b[0m"));
+    }
+}
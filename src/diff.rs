@@ -0,0 +1,410 @@
+use crate::glob::glob_match;
+use crate::index::MapIndex;
+use crate::map::{MapEntry, MapHash};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModifiedEntry {
+    pub path: String,
+    pub old_hash: MapHash,
+    pub new_hash: MapHash,
+    // Распакованный файл всё ещё совпадает со старым хэшем: хэш в карте сменился, а содержимое нет
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub content_unchanged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenamedEntry {
+    pub old_path: String,
+    pub new_path: String,
+    pub hash: MapHash,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MapDiff {
+    pub added: Vec<MapEntry>,
+    pub modified: Vec<ModifiedEntry>,
+    pub deleted: Vec<MapEntry>,
+    pub renamed: Vec<RenamedEntry>,
+    // Изменения в путях, отброшенных фильтрами map.include / map.exclude
+    pub filtered: usize,
+    // Изменения, скрытые из патчноута шаблонами changelog.ignore (см. without_ignored)
+    pub ignored: usize,
+    // Количество записей в старой и новой карте
+    pub total_old: usize,
+    pub total_new: usize,
+}
+
+/// Сводка по одному изменению карты для консоли, патчноута и уведомлений
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DiffStats {
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub renamed: usize,
+    pub total_old: usize,
+    pub total_new: usize,
+    /// Затронутые папки верхнего уровня (первая часть пути); файлы в корне карты не считаются
+    pub directories: usize,
+}
+
+impl DiffStats {
+    /// Изменений файловой структуры нет: ни добавленных, ни изменённых, ни удалённых, ни перемещённых файлов
+    pub fn is_empty(&self) -> bool {
+        self.added + self.modified + self.deleted + self.renamed == 0
+    }
+}
+
+impl fmt::Display for DiffStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ~{} -{}", self.added, self.modified, self.deleted)?;
+        if self.renamed > 0 {
+            write!(f, " »{}", self.renamed)?;
+        }
+        Ok(())
+    }
+}
+
+impl MapDiff {
+    pub fn added_count(&self) -> usize {
+        self.added.len()
+    }
+
+    pub fn modified_count(&self) -> usize {
+        self.modified.len()
+    }
+
+    pub fn deleted_count(&self) -> usize {
+        self.deleted.len()
+    }
+
+    pub fn renamed_count(&self) -> usize {
+        self.renamed.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.added_count() + self.modified_count() + self.deleted_count() + self.renamed_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Копия без изменений в путях, подходящих под шаблоны (`*` и `?`, сравнивается весь путь).
+    /// Перемещение скрывается, если под шаблон подходит старый или новый путь
+    pub fn without_ignored(&self, patterns: &[String]) -> MapDiff {
+        let ignored = |path: &str| patterns.iter().any(|pattern| glob_match(pattern, path));
+        let diff = MapDiff {
+            added: self.added.iter().filter(|e| !ignored(&e.path)).cloned().collect(),
+            modified: self.modified.iter().filter(|e| !ignored(&e.path)).cloned().collect(),
+            deleted: self.deleted.iter().filter(|e| !ignored(&e.path)).cloned().collect(),
+            renamed: self
+                .renamed
+                .iter()
+                .filter(|e| !ignored(&e.old_path) && !ignored(&e.new_path))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        };
+        MapDiff {
+            ignored: self.ignored + self.total() - diff.total(),
+            ..diff
+        }
+    }
+
+    pub fn stats(&self) -> DiffStats {
+        let paths = self
+            .added
+            .iter()
+            .chain(&self.deleted)
+            .map(|e| e.path.as_str())
+            .chain(self.modified.iter().map(|e| e.path.as_str()))
+            .chain(self.renamed.iter().flat_map(|e| [e.old_path.as_str(), e.new_path.as_str()]));
+        let directories: std::collections::HashSet<&str> =
+            paths.filter_map(|path| path.split_once('/').map(|(dir, _)| dir)).collect();
+        DiffStats {
+            added: self.added_count(),
+            modified: self.modified_count(),
+            deleted: self.deleted_count(),
+            renamed: self.renamed_count(),
+            total_old: self.total_old,
+            total_new: self.total_new,
+            directories: directories.len(),
+        }
+    }
+}
+
+fn modified_entry(old_index: &MapIndex, entry: &MapEntry) -> Option<ModifiedEntry> {
+    match old_index.get(&entry.path) {
+        Some(old_entry) if old_entry.hash != entry.hash => Some(ModifiedEntry {
+            path: entry.path.clone(),
+            old_hash: old_entry.hash,
+            new_hash: entry.hash,
+            content_unchanged: false,
+        }),
+        _ => None,
+    }
+}
+
+// Удалённый и добавленный файл с одинаковым хешем считаются переименованием.
+// Пары составляются только если хеш встречается ровно один раз среди удалённых и среди добавленных
+fn detect_renames(diff: &mut MapDiff) {
+    fn count_hashes(entries: &[MapEntry]) -> HashMap<MapHash, usize> {
+        let mut counts = HashMap::new();
+        for entry in entries {
+            *counts.entry(entry.hash).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    let added_counts = count_hashes(&diff.added);
+    let deleted_counts = count_hashes(&diff.deleted);
+    let is_unique = |hash: &MapHash| added_counts.get(hash) == Some(&1) && deleted_counts.get(hash) == Some(&1);
+
+    let mut renamed_from: HashMap<MapHash, String> = diff
+        .deleted
+        .iter()
+        .filter(|e| is_unique(&e.hash))
+        .map(|e| (e.hash, e.path.clone()))
+        .collect();
+    if renamed_from.is_empty() {
+        return;
+    }
+
+    diff.deleted.retain(|e| !is_unique(&e.hash));
+    let mut added = Vec::with_capacity(diff.added.len());
+    for entry in std::mem::take(&mut diff.added) {
+        match renamed_from.remove(&entry.hash) {
+            Some(old_path) => diff.renamed.push(RenamedEntry {
+                old_path,
+                new_path: entry.path,
+                hash: entry.hash,
+            }),
+            None => added.push(entry),
+        }
+    }
+    diff.added = added;
+}
+
+pub fn diff_map_entries(old: &[MapEntry], new: &[MapEntry]) -> MapDiff {
+    diff_indexes(&MapIndex::new(old.to_vec()), &MapIndex::new(new.to_vec()))
+}
+
+/// Оба индекса отсортированы по пути, поэтому достаточно одного совместного прохода
+pub fn diff_indexes(old: &MapIndex, new: &MapIndex) -> MapDiff {
+    let mut diff = MapDiff {
+        total_old: old.len(),
+        total_new: new.len(),
+        ..MapDiff::default()
+    };
+    let mut old_iter = old.entries().iter().peekable();
+    let mut new_iter = new.entries().iter().peekable();
+    loop {
+        match (old_iter.peek(), new_iter.peek()) {
+            (Some(old_entry), Some(new_entry)) => match old_entry.path.cmp(&new_entry.path) {
+                Ordering::Less => diff.deleted.extend(old_iter.next().cloned()),
+                Ordering::Greater => diff.added.extend(new_iter.next().cloned()),
+                Ordering::Equal => {
+                    if old_entry.hash != new_entry.hash {
+                        diff.modified.push(ModifiedEntry {
+                            path: new_entry.path.clone(),
+                            old_hash: old_entry.hash,
+                            new_hash: new_entry.hash,
+                            content_unchanged: false,
+                        });
+                    }
+                    old_iter.next();
+                    new_iter.next();
+                }
+            },
+            (Some(_), None) => diff.deleted.extend(old_iter.next().cloned()),
+            (None, Some(_)) => diff.added.extend(new_iter.next().cloned()),
+            (None, None) => break,
+        }
+    }
+    detect_renames(&mut diff);
+    diff
+}
+
+/// Тот же результат, что и у diff_map_entries, но индексы и проходы считаются параллельно
+pub fn diff_map_entries_parallel(old: &[MapEntry], new: &[MapEntry]) -> MapDiff {
+    let (old_index, new_index) = rayon::join(|| MapIndex::new(old.to_vec()), || MapIndex::new(new.to_vec()));
+    diff_indexes_parallel(&old_index, &new_index)
+}
+
+/// Как diff_map_entries и diff_map_entries_parallel, но записи переходят в индексы без копирования
+pub fn diff_owned_entries(old: Vec<MapEntry>, new: Vec<MapEntry>, parallel: bool) -> MapDiff {
+    if parallel {
+        let (old_index, new_index) = rayon::join(|| MapIndex::new(old), || MapIndex::new(new));
+        diff_indexes_parallel(&old_index, &new_index)
+    } else {
+        diff_indexes(&MapIndex::new(old), &MapIndex::new(new))
+    }
+}
+
+/// Параллельный вариант diff_indexes
+pub fn diff_indexes_parallel(old_index: &MapIndex, new_index: &MapIndex) -> MapDiff {
+    let (added, (modified, deleted)) = rayon::join(
+        || {
+            new_index
+                .entries()
+                .par_iter()
+                .filter(|e| !old_index.contains(&e.path))
+                .cloned()
+                .collect()
+        },
+        || {
+            rayon::join(
+                || {
+                    new_index
+                        .entries()
+                        .par_iter()
+                        .filter_map(|e| modified_entry(old_index, e))
+                        .collect()
+                },
+                || {
+                    old_index
+                        .entries()
+                        .par_iter()
+                        .filter(|e| !new_index.contains(&e.path))
+                        .cloned()
+                        .collect()
+                },
+            )
+        },
+    );
+    let mut diff = MapDiff {
+        added,
+        modified,
+        deleted,
+        renamed: Vec::new(),
+        filtered: 0,
+        ignored: 0,
+        total_old: old_index.len(),
+        total_new: new_index.len(),
+    };
+    detect_renames(&mut diff);
+    diff
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, byte: u8) -> MapEntry {
+        MapEntry {
+            path: path.to_string(),
+            hash: MapHash::from([byte; 20]),
+        }
+    }
+
+    fn paths(entries: &[MapEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[test]
+    fn empty_old_map_adds_everything() {
+        let diff = diff_map_entries(&[], &[entry("b.png", 1), entry("a.png", 2)]);
+        assert_eq!(paths(&diff.added), ["a.png", "b.png"]);
+        assert!(diff.modified.is_empty() && diff.deleted.is_empty() && diff.renamed.is_empty());
+        assert_eq!((diff.total_old, diff.total_new), (0, 2));
+    }
+
+    #[test]
+    fn empty_new_map_deletes_everything() {
+        let diff = diff_map_entries(&[entry("b.png", 1), entry("a.png", 2)], &[]);
+        assert_eq!(paths(&diff.deleted), ["a.png", "b.png"]);
+        assert!(diff.added.is_empty() && diff.modified.is_empty() && diff.renamed.is_empty());
+        assert_eq!((diff.total_old, diff.total_new), (2, 0));
+        assert!(diff_map_entries(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn identical_maps_have_no_changes() {
+        let entries = [entry("a.png", 1), entry("dir/b.png", 2), entry("dir/c.png", 2)];
+        let diff = diff_map_entries(&entries, &entries);
+        assert!(diff.is_empty());
+        assert_eq!((diff.total_old, diff.total_new), (3, 3));
+        assert_eq!(diff.stats().directories, 0);
+    }
+
+    #[test]
+    fn renames_need_a_unique_hash_on_both_sides() {
+        let diff = diff_map_entries(
+            &[entry("old/one.png", 1), entry("old/dup_a.png", 2), entry("old/dup_b.png", 2), entry("old/x.png", 3)],
+            &[entry("new/one.png", 1), entry("new/dup.png", 2), entry("new/x_a.png", 3), entry("new/x_b.png", 3)],
+        );
+        assert_eq!(
+            diff.renamed,
+            [RenamedEntry {
+                old_path: "old/one.png".to_string(),
+                new_path: "new/one.png".to_string(),
+                hash: MapHash::from([1; 20]),
+            }]
+        );
+        // Хэш 2 дважды среди удалённых, хэш 3 дважды среди добавленных: пару не выбрать
+        assert_eq!(paths(&diff.deleted), ["old/dup_a.png", "old/dup_b.png", "old/x.png"]);
+        assert_eq!(paths(&diff.added), ["new/dup.png", "new/x_a.png", "new/x_b.png"]);
+    }
+
+    #[test]
+    fn without_ignored_counts_hidden_changes() {
+        let diff = diff_map_entries(
+            &[entry("cache/a.bin", 1), entry("cache/gone.bin", 2), entry("moved/from.png", 3), entry("keep.png", 4)],
+            &[entry("cache/a.bin", 5), entry("cache/new.bin", 6), entry("cache/to.png", 3), entry("keep.png", 7)],
+        );
+        assert_eq!(diff.total(), 5);
+
+        let ignored = diff.without_ignored(&["cache/*".to_string()]);
+        // Перемещение в cache/ скрывается целиком, хотя старый путь под шаблон не подходит
+        assert_eq!(ignored.ignored, 4);
+        assert!(ignored.renamed.is_empty());
+        assert_eq!(ignored.modified.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), ["keep.png"]);
+        assert_eq!((ignored.total_old, ignored.total_new), (diff.total_old, diff.total_new));
+
+        // Повторная фильтрация добавляет к уже скрытым
+        let twice = ignored.without_ignored(&["keep.png".to_string()]);
+        assert_eq!((twice.ignored, twice.total()), (5, 0));
+        assert_eq!(diff.without_ignored(&[]), diff);
+    }
+
+    #[test]
+    fn parallel_diff_matches_sequential() {
+        // Изменения, удаления, добавления, перемещения и повторяющиеся хэши; вход не отсортирован
+        let old: Vec<MapEntry> = (0..5000u32)
+            .rev()
+            .map(|i| entry(&format!("dir_{}/file_{}.png", i % 17, i), (i % 251) as u8))
+            .collect();
+        let mut new: Vec<MapEntry> = old
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 50 != 7)
+            .map(|(i, e)| match i % 40 {
+                3 => entry(&e.path, e.hash.as_bytes()[0].wrapping_add(1)),
+                9 => entry(&format!("moved/{}", e.path), e.hash.as_bytes()[0]),
+                _ => e.clone(),
+            })
+            .collect();
+        let mut unique = [0xee; 20];
+        unique[19] = 0;
+        new.extend((0..300).map(|i| entry(&format!("new/file_{}.png", i), i as u8)));
+        new.push(MapEntry {
+            path: "unique/renamed.png".to_string(),
+            hash: MapHash::from(unique),
+        });
+        let mut old = old;
+        old.push(MapEntry {
+            path: "unique/original.png".to_string(),
+            hash: MapHash::from(unique),
+        });
+
+        let sequential = diff_map_entries(&old, &new);
+        assert_eq!(diff_map_entries_parallel(&old, &new), sequential);
+        assert!(!sequential.added.is_empty() && !sequential.modified.is_empty() && !sequential.deleted.is_empty());
+        assert!(sequential.renamed.iter().any(|e| e.old_path == "unique/original.png"));
+        assert_eq!(diff_map_entries_parallel(&[], &[]), diff_map_entries(&[], &[]));
+    }
+}
@@ -0,0 +1,29 @@
+use crate::changelog::ChangelogError;
+use crate::config::ConfigError;
+use crate::github::PublishError;
+use crate::lang::LangError;
+use crate::map::MapError;
+use thiserror::Error;
+
+/// Общая ошибка приложения: по варианту основной цикл решает, повторить действие или пересоздать копию окружения
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error(transparent)]
+    Map(#[from] MapError),
+    #[error(transparent)]
+    Lang(#[from] LangError),
+    #[error(transparent)]
+    Changelog(#[from] ChangelogError),
+    #[error(transparent)]
+    Publish(#[from] PublishError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("{0}")]
+    Usage(String),
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Map(MapError::JsonError(e))
+    }
+}
\ No newline at end of file
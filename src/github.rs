@@ -1,8 +1,9 @@
 use std::env;
 use std::process::Command;
 use thiserror::Error;
-use crate::config::{load_config, Config};
+use crate::config::{load_config, Config, ConfigError};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum PublishError {
     #[error("Ошибка ввода/вывода: {0}")]
@@ -10,7 +11,7 @@ pub enum PublishError {
     #[error("Ошибка выполнения BunJS: {0}")]
     ExecutionError(String),
     #[error("Ошибка загрузки конфигурации: {0}")]
-    ConfigError(#[from] Box<dyn std::error::Error>),
+    ConfigError(#[from] ConfigError),
 }
 
 pub fn publish_html() -> Result<(), PublishError> {
@@ -0,0 +1,35 @@
+/// Простые шаблоны: `*` - любая последовательность символов, `?` - ровно один символ
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Звёздочка забирает ещё один символ, и сравнение повторяется
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Шаблон сравнивается с началом строки: `assets/stalker/items/` совпадает со всем содержимым папки
+pub fn glob_match_prefix(pattern: &str, text: &str) -> bool {
+    glob_match(&format!("{}*", pattern), text)
+}
@@ -0,0 +1,105 @@
+use crate::map::MapEntry;
+
+/// Стабильная сортировка по пути: записи с одинаковым путём сохраняют исходный порядок
+pub fn sort_entries(entries: &mut [MapEntry]) {
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+}
+
+/// Бинарный поиск; entries должны быть отсортированы по пути (см. sort_entries)
+pub fn find_entry<'a>(entries: &'a [MapEntry], path: &str) -> Option<&'a MapEntry> {
+    entries
+        .binary_search_by(|entry| entry.path.as_str().cmp(path))
+        .ok()
+        .map(|i| &entries[i])
+}
+
+/// Отсортированные по пути записи без повторов. При повторяющихся путях остаётся последняя запись файла
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapIndex {
+    entries: Vec<MapEntry>,
+}
+
+impl MapIndex {
+    pub fn new(mut entries: Vec<MapEntry>) -> Self {
+        entries.reverse();
+        sort_entries(&mut entries);
+        entries.dedup_by(|a, b| a.path == b.path);
+        MapIndex { entries }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&MapEntry> {
+        find_entry(&self.entries, path)
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.get(path).is_some()
+    }
+
+    /// Все записи, путь которых начинается с prefix, например "assets/textures/"
+    pub fn prefix(&self, prefix: &str) -> &[MapEntry] {
+        let start = self.entries.partition_point(|entry| entry.path.as_str() < prefix);
+        let len = self.entries[start..].partition_point(|entry| entry.path.starts_with(prefix));
+        &self.entries[start..start + len]
+    }
+
+    pub fn entries(&self) -> &[MapEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl From<Vec<MapEntry>> for MapIndex {
+    fn from(entries: Vec<MapEntry>) -> Self {
+        MapIndex::new(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapHash;
+
+    fn entry(path: &str, byte: u8) -> MapEntry {
+        MapEntry {
+            path: path.to_string(),
+            hash: MapHash::from([byte; 20]),
+        }
+    }
+
+    #[test]
+    fn keeps_last_duplicate() {
+        let index = MapIndex::new(vec![entry("b", 1), entry("a", 2), entry("b", 3)]);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get("b").map(|e| e.hash), Some(MapHash::from([3; 20])));
+    }
+
+    #[test]
+    fn finds_entries_by_prefix() {
+        let index = MapIndex::new(vec![
+            entry("assets/textures/b.png", 1),
+            entry("assets/sounds/a.ogg", 2),
+            entry("assets/textures/a.png", 3),
+            entry("assets/texturesx.png", 4),
+        ]);
+        let paths: Vec<_> = index.prefix("assets/textures/").iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["assets/textures/a.png", "assets/textures/b.png"]);
+        assert!(index.prefix("missing/").is_empty());
+        assert!(index.contains("assets/sounds/a.ogg"));
+        assert!(!index.contains("assets/sounds"));
+    }
+
+    #[test]
+    fn find_entry_on_sorted_slice() {
+        let mut entries = vec![entry("c", 1), entry("a", 2), entry("b", 3)];
+        sort_entries(&mut entries);
+        assert_eq!(find_entry(&entries, "b").map(|e| e.hash), Some(MapHash::from([3; 20])));
+        assert!(find_entry(&entries, "d").is_none());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,279 @@
+use crate::map::Branch;
+
+/// Подписи и форматы дат выходных файлов патчноута (HTML, Markdown, текст, Telegram, BBCode, лента)
+/// на одном языке. Новый язык - ещё одна таблица в LANGUAGES, генераторы патчноута менять не нужно
+pub struct Labels {
+    // Атрибут lang страницы
+    pub html_lang: &'static str,
+    // Форматы chrono: дата в заголовке патчноута и время в списке патчноутов
+    pub date: &'static str,
+    pub date_time: &'static str,
+    pub live_branch: &'static str,
+    pub ots_branch: &'static str,
+    pub patch: &'static str,
+    pub patches: &'static str,
+    pub total: &'static str,
+    pub page_description: &'static str,
+    pub search_placeholder: &'static str,
+    pub feed_title: &'static str,
+    pub source: &'static str,
+    pub full_version: &'static str,
+    pub full_version_on_site: &'static str,
+    // Сводка
+    pub files: &'static str,
+    pub lang_keys: &'static str,
+    pub lang: &'static str,
+    pub top_level_dirs: &'static str,
+    pub top_level_dirs_touched: &'static str,
+    pub structure_changes: &'static str,
+    pub structure_unchanged: &'static str,
+    pub no_structure_changes: &'static str,
+    pub no_changes: &'static str,
+    pub map_entries: &'static str,
+    // Счётчики в сводке: "+3 добавлено"
+    pub added: &'static str,
+    pub modified: &'static str,
+    pub deleted: &'static str,
+    pub moved: &'static str,
+    pub renamed: &'static str,
+    // Заголовки списков файлов в Telegram
+    pub added_heading: &'static str,
+    pub modified_heading: &'static str,
+    pub deleted_heading: &'static str,
+    pub moved_heading: &'static str,
+    // Разделы
+    pub renamed_files: &'static str,
+    pub lang_file_changes: &'static str,
+    pub no_lang_changes: &'static str,
+    pub other_group: &'static str,
+    // Встроенные категории ключей (lang::BUILTIN_LANG_CATEGORIES и «Прочее») -> подпись; категории из
+    // lang.categories и не перечисленные здесь выводятся как есть
+    pub categories: &'static [(&'static str, &'static str)],
+    pub missing_translation: &'static str,
+    pub key_column: &'static str,
+    pub added_in_column: &'static str,
+    pub missing_in_column: &'static str,
+    pub placeholder_mismatch: &'static str,
+    pub revert: &'static str,
+    pub duplicate_keys: &'static str,
+    // Отчёт о различиях локализации ОТС и основного сервера
+    pub branch_comparison: &'static str,
+    pub generated: &'static str,
+    pub only_ots: &'static str,
+    pub only_live: &'static str,
+    pub differ: &'static str,
+    pub none: &'static str,
+    // Пометки с числом: склонение остаётся за таблицей языка
+    pub changes_count: fn(usize) -> String,
+    pub more_changes: fn(usize) -> String,
+    pub filtered_paths: fn(usize) -> String,
+    pub hidden_by_filters: fn(usize) -> String,
+    pub truncated_lang: fn(usize) -> String,
+    pub ignored_keys: fn(usize) -> String,
+    pub fallback_lines: fn(usize) -> String,
+}
+
+impl Labels {
+    /// Подпись ветки в заголовках; у веток runtime - имя папки
+    pub fn branch<'a>(&self, branch: &'a Branch) -> &'a str {
+        match branch {
+            Branch::Live => self.live_branch,
+            Branch::Ots => self.ots_branch,
+            Branch::Runtime(name) => name,
+        }
+    }
+
+    /// Подпись категории ключей локализации
+    pub fn category<'a>(&self, name: &'a str) -> &'a str {
+        self.categories.iter().find(|(builtin, _)| *builtin == name).map_or(name, |(_, label)| label)
+    }
+}
+
+pub const RU: Labels = Labels {
+    html_lang: "ru",
+    date: "%d.%m.%Y",
+    date_time: "%d.%m.%Y %H:%M",
+    live_branch: "основной сервер",
+    ots_branch: "ОТС",
+    patch: "Патчноут",
+    patches: "Патчноуты",
+    total: "Всего",
+    page_description: "Изменения в файлах ассетов игры",
+    search_placeholder: "Поиск по файлам и ключам",
+    feed_title: "Krevetka: патчноуты STALCRAFT",
+    source: "Источник",
+    full_version: "Полная версия",
+    full_version_on_site: "Полная версия на сайте",
+    files: "Файлы",
+    lang_keys: "Ключи локализации",
+    lang: "Локализация",
+    top_level_dirs: "папок верхнего уровня",
+    top_level_dirs_touched: "Папок верхнего уровня затронуто",
+    structure_changes: "Изменения файловой структуры",
+    structure_unchanged: "Файловая структура не изменилась",
+    no_structure_changes: "изменений файловой структуры нет",
+    no_changes: "изменений нет",
+    map_entries: "Записей в карте",
+    added: "добавлено",
+    modified: "изменено",
+    deleted: "удалено",
+    moved: "перемещено",
+    renamed: "переименовано",
+    added_heading: "Добавлено",
+    modified_heading: "Изменено",
+    deleted_heading: "Удалено",
+    moved_heading: "Перемещено",
+    renamed_files: "Перемещённые файлы",
+    lang_file_changes: "Изменения в файле локализации",
+    no_lang_changes: "Изменений в локализации не обнаружено",
+    other_group: "Прочее",
+    categories: &[],
+    missing_translation: "Нет перевода",
+    key_column: "Ключ",
+    added_in_column: "Добавлен",
+    missing_in_column: "Нет в",
+    placeholder_mismatch: "плейсхолдеры не совпадают",
+    revert: "откат",
+    duplicate_keys: "повторяющиеся ключи, учтено последнее значение",
+    branch_comparison: "Локализация ОТС и основного сервера",
+    generated: "Сформировано",
+    only_ots: "Только на ОТС",
+    only_live: "Только на основном сервере",
+    differ: "Отличаются (основной сервер → ОТС)",
+    none: "Нет",
+    changes_count: |n| format!("{} {}", n, changes_ru(n)),
+    more_changes: |n| format!("…и ещё {}", n),
+    filtered_paths: |n| format!("и ещё {} {} в отфильтрованных путях", n, changes_ru(n)),
+    hidden_by_filters: |n| format!("скрыто {} {} по фильтрам", n, changes_ru(n)),
+    truncated_lang: |n| format!("…и ещё {} {}, полный diff", n, changes_ru(n)),
+    ignored_keys: |n| format!("и ещё {} {} в ключах из lang.ignore_keys", n, changes_ru(n)),
+    fallback_lines: |n| format!("строк, прочитанных как CP1251: {}", n),
+};
+
+// "изменение" в форме, согласованной с числом: 1 изменение, 2 изменения, 5 изменений, 21 изменение
+fn changes_ru(n: usize) -> &'static str {
+    match (n % 10, n % 100) {
+        (1, rem) if rem != 11 => "изменение",
+        (2..=4, rem) if !(12..=14).contains(&rem) => "изменения",
+        _ => "изменений",
+    }
+}
+
+// Окончание множественного числа в английских подписях
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+pub const EN: Labels = Labels {
+    html_lang: "en",
+    date: "%b %-d, %Y",
+    date_time: "%b %-d, %Y %H:%M",
+    live_branch: "live server",
+    ots_branch: "test server",
+    patch: "Patch notes",
+    patches: "Patch notes",
+    total: "Total",
+    page_description: "Changes in the game asset files",
+    search_placeholder: "Search files and keys",
+    feed_title: "Krevetka: STALCRAFT patch notes",
+    source: "Source",
+    full_version: "Full version",
+    full_version_on_site: "Full version on the site",
+    files: "Files",
+    lang_keys: "Localization keys",
+    lang: "Localization",
+    top_level_dirs: "top-level folders",
+    top_level_dirs_touched: "Top-level folders touched",
+    structure_changes: "File structure changes",
+    structure_unchanged: "File structure unchanged",
+    no_structure_changes: "no file structure changes",
+    no_changes: "no changes",
+    map_entries: "Map entries",
+    added: "added",
+    modified: "modified",
+    deleted: "deleted",
+    moved: "moved",
+    renamed: "renamed",
+    added_heading: "Added",
+    modified_heading: "Modified",
+    deleted_heading: "Deleted",
+    moved_heading: "Moved",
+    renamed_files: "Moved files",
+    lang_file_changes: "Changes in localization file",
+    no_lang_changes: "No localization changes found",
+    other_group: "Other",
+    categories: &[
+        ("Оружие", "Weapons"),
+        ("Броня", "Armor"),
+        ("Артефакты", "Artifacts"),
+        ("Квесты", "Quests"),
+        ("Интерфейс", "Interface"),
+        ("Прочее", "Other"),
+    ],
+    missing_translation: "Missing translations",
+    key_column: "Key",
+    added_in_column: "Added in",
+    missing_in_column: "Missing in",
+    placeholder_mismatch: "placeholders differ",
+    revert: "revert",
+    duplicate_keys: "duplicate keys, last value used",
+    branch_comparison: "Localization: test server vs live server",
+    generated: "Generated",
+    only_ots: "Only on the test server",
+    only_live: "Only on the live server",
+    differ: "Different (live server → test server)",
+    none: "None",
+    changes_count: |n| format!("{} change{}", n, plural(n)),
+    more_changes: |n| format!("…and {} more", n),
+    filtered_paths: |n| format!("and {} more change{} in filtered paths", n, plural(n)),
+    hidden_by_filters: |n| format!("{} change{} hidden by filters", n, plural(n)),
+    truncated_lang: |n| format!("…and {} more change{}, full diff", n, plural(n)),
+    ignored_keys: |n| format!("and {} more change{} in keys from lang.ignore_keys", n, plural(n)),
+    fallback_lines: |n| format!("lines read as CP1251: {}", n),
+};
+
+/// Языки для changelog.language
+pub const LANGUAGES: &[(&str, &Labels)] = &[("ru", &RU), ("en", &EN)];
+
+/// Таблица языка; неизвестный язык - русская
+pub fn labels(language: &str) -> &'static Labels {
+    find_labels(language).unwrap_or(&RU)
+}
+
+/// Таблица языка из LANGUAGES; None, если такого языка нет
+pub fn find_labels(language: &str) -> Option<&'static Labels> {
+    LANGUAGES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(language))
+        .map(|&(_, labels)| labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_fall_back_to_russian() {
+        assert_eq!(labels("en").patch, "Patch notes");
+        assert_eq!(labels("EN").html_lang, "en");
+        assert_eq!(labels("de").html_lang, "ru");
+        assert!(find_labels("de").is_none());
+        assert_eq!((EN.changes_count)(1), "1 change");
+        assert_eq!((EN.changes_count)(2), "2 changes");
+        assert_eq!((RU.changes_count)(1), "1 изменение");
+        assert_eq!((RU.changes_count)(3), "3 изменения");
+        assert_eq!((RU.changes_count)(5), "5 изменений");
+        assert_eq!((RU.changes_count)(12), "12 изменений");
+        assert_eq!((RU.changes_count)(21), "21 изменение");
+        assert_eq!((RU.changes_count)(104), "104 изменения");
+        assert_eq!(RU.branch(&Branch::Ots), "ОТС");
+        assert_eq!(EN.branch(&Branch::Runtime("stalcraft_dev".to_string())), "stalcraft_dev");
+        assert_eq!(EN.category("Оружие"), "Weapons");
+        assert_eq!(EN.category("Транспорт"), "Транспорт");
+        assert_eq!(RU.category("Оружие"), "Оружие");
+    }
+}
@@ -1,88 +1,3312 @@
-use crate::map::MapError;
+use crate::config::{LangCategoryRule, LangConfig, LangDiffFormat};
+use crate::glob::glob_match;
+use crate::map::{retry_io, Branch, Environment};
+use crate::unified::unified_diff;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use xxhash_rust::xxh3::xxh3_64;
 
-pub fn process_lang_file(game_path: &Path) -> Result<(), MapError> {
-    let lang_path = game_path
-        .join("runtime")
-        .join("stalcraft")
-        .join("modassets")
-        .join("assets")
-        .join("stalker")
-        .join("lang")
-        .join("ru.lang");
+// Пауза между чтениями файла игры: лаунчер во время обновления может ещё дописывать его
+const STABLE_READ_DELAY: Duration = Duration::from_millis(200);
+const STABLE_READ_ATTEMPTS: usize = 5;
 
-    if !lang_path.exists() {
-        println!("Файл локализации не найден: {}", lang_path.display());
-        return Ok(());
+#[derive(Error, Debug)]
+pub enum LangError {
+    #[error("Ошибка ввода/вывода при обработке локализации: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Ошибка сериализации изменений локализации: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Файл локализации {0} продолжает меняться, обработка отложена до следующего цикла")]
+    Unstable(PathBuf),
+    #[error("Снимок локализации {0} с временем {1} не найден")]
+    SnapshotNotFound(String, String),
+}
+
+/// Одно изменение строки локализации
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LangChange {
+    Added { key: String, value: Option<String> },
+    // revert - новое значение уже встречалось у ключа раньше (см. LangValueHistory)
+    Modified { key: String, old: Option<String>, new: Option<String>, revert: bool },
+    Removed { key: String, value: Option<String> },
+    Renamed { old_key: String, new_key: String, value: String },
+}
+
+impl LangChange {
+    pub fn key(&self) -> &str {
+        match self {
+            LangChange::Added { key, .. } | LangChange::Modified { key, .. } | LangChange::Removed { key, .. } => key,
+            LangChange::Renamed { new_key, .. } => new_key,
+        }
+    }
+
+    /// У изменённого значения другой набор плейсхолдеров: в игре строка, скорее всего, отобразится с ошибкой
+    pub fn placeholder_mismatch(&self) -> bool {
+        match self {
+            LangChange::Modified {
+                old: Some(old),
+                new: Some(new),
+                ..
+            } => extract_placeholders(old) != extract_placeholders(new),
+            _ => false,
+        }
     }
+}
 
-    let env_dir = std::path::PathBuf::from("environment").join("lang");
-    fs::create_dir_all(&env_dir)?;
-    let env_lang = env_dir.join("ru.lang");
+// Пометка в lang_changes.diff после нового значения, если плейсхолдеры не совпали
+const PLACEHOLDER_MISMATCH_MARK: &str = " [!] placeholder mismatch";
+// Пометка после ключа изменённой строки, если значение вернулось к одному из прежних
+const REVERT_MARK: &str = " (revert)";
 
-    if !env_lang.exists() {
-        fs::copy(&lang_path, &env_lang)?;
-        println!("Создана первичная копия файла локализации");
-        return Ok(());
+/// Плейсхолдеры значения в порядке сортировки (мультимножество): `%s`, `%d`, `%1$s`, `%.2f`, `{0}`, `{name}`
+/// и цветовые коды `&a`, `§c`. Экранированные `%%`, `{{` и `}}` плейсхолдерами не считаются
+pub fn extract_placeholders(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut placeholders = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '%' if chars.get(i + 1) == Some(&'%') => i += 2,
+            '%' => {
+                // Флаги, ширина, точность и номер аргумента, затем буква преобразования
+                let mut end = i + 1;
+                while end < chars.len() && (chars[end].is_ascii_digit() || "$.-+# ".contains(chars[end])) {
+                    end += 1;
+                }
+                match chars.get(end) {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        placeholders.push(chars[i..=end].iter().collect());
+                        i = end + 1;
+                    }
+                    _ => i += 1,
+                }
+            }
+            '{' if chars.get(i + 1) == Some(&'{') => i += 2,
+            '}' if chars.get(i + 1) == Some(&'}') => i += 2,
+            '{' => match chars[i + 1..].iter().position(|&c| c == '}') {
+                Some(len) if chars[i + 1..i + 1 + len].iter().all(|c| c.is_alphanumeric() || *c == '_') => {
+                    placeholders.push(chars[i..i + len + 2].iter().collect());
+                    i += len + 2;
+                }
+                _ => i += 1,
+            },
+            '&' | '§' if chars.get(i + 1).is_some_and(|&c| is_color_code(c)) => {
+                placeholders.push(format!("{}{}", chars[i], chars[i + 1].to_ascii_lowercase()));
+                i += 2;
+            }
+            _ => i += 1,
+        }
     }
+    placeholders.sort();
+    placeholders
+}
 
-    let game_content = fs::read_to_string(&lang_path)?;
-    let env_content = fs::read_to_string(&env_lang)?;
+// Символ после & или §: цвет 0-9a-f, форматирование k-o или сброс r
+fn is_color_code(c: char) -> bool {
+    c.is_ascii_hexdigit() || "klmnorKLMNOR".contains(c)
+}
 
-    if game_content == env_content {
-        return Ok(());
+/// Раскрывает экранирование в значении для показа: `\n`, `\t`, `\\` и `\uXXXX` (в том числе `\u00a7` для
+/// цветовых кодов). Неизвестные и неполные последовательности остаются как есть
+pub fn unescape_lang_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('\\') {
+        result.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let mut chars = rest.chars();
+        chars.next();
+        let (unescaped, len) = match chars.next() {
+            Some('n') => (Some('\n'), 2),
+            Some('t') => (Some('\t'), 2),
+            Some('\\') => (Some('\\'), 2),
+            Some('u') => (
+                rest.get(2..6).and_then(|hex| u32::from_str_radix(hex, 16).ok()).and_then(char::from_u32),
+                6,
+            ),
+            _ => (None, 1),
+        };
+        match unescaped {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    Cow::Owned(result)
+}
+
+/// Делит значение по цветовым кодам `&a` / `§a` на куски текста с действующим цветом (0-9a-f).
+/// Сами коды в текст не попадают; коды форматирования k-o цвет не меняют, r сбрасывает его
+pub fn split_color_codes(value: &str) -> Vec<(Option<char>, &str)> {
+    let mut segments = Vec::new();
+    let mut color = None;
+    let mut start = 0;
+    let mut chars = value.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let Some(&(code_at, code)) = chars.peek() else {
+            break;
+        };
+        if !matches!(c, '&' | '§') || !is_color_code(code) {
+            continue;
+        }
+        if start < i {
+            segments.push((color, &value[start..i]));
+        }
+        let code = code.to_ascii_lowercase();
+        if code.is_ascii_hexdigit() {
+            color = Some(code);
+        } else if code == 'r' {
+            color = None;
+        }
+        chars.next();
+        start = code_at + code.len_utf8();
+    }
+    if start < value.len() {
+        segments.push((color, &value[start..]));
+    }
+    segments
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LangEntry {
+    pub key: String,
+    pub value: Option<String>,
+    // Место изменения в выводе: по строке ключа в новом файле, удалённые - рядом с соседями из старого
+    #[serde(skip)]
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModifiedLangEntry {
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub revert: bool,
+    #[serde(skip)]
+    pub position: usize,
+}
+
+/// Вид изменения в changes/<ветка>/<локаль>_changes.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LangChangeKind {
+    Added,
+    Modified,
+    Removed,
+    Renamed,
+}
+
+/// Запись changes/<ветка>/<локаль>_changes.json. Файл читают внешние боты, поэтому имена и смысл полей не меняются:
+/// new - значение после изменения (null у removed), old - до него (null у added), у renamed old_key - прежний ключ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangChangeRecord {
+    pub kind: LangChangeKind,
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_key: Option<String>,
+    // Только у modified: значение вернулось к одному из прежних
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub revert: bool,
+}
+
+impl From<LangChange> for LangChangeRecord {
+    fn from(change: LangChange) -> Self {
+        let record = |kind, key, old, new| LangChangeRecord {
+            kind,
+            key,
+            old,
+            new,
+            old_key: None,
+            revert: false,
+        };
+        match change {
+            LangChange::Added { key, value } => record(LangChangeKind::Added, key, None, value),
+            LangChange::Modified { key, old, new, revert } => LangChangeRecord {
+                revert,
+                ..record(LangChangeKind::Modified, key, old, new)
+            },
+            LangChange::Removed { key, value } => record(LangChangeKind::Removed, key, value, None),
+            LangChange::Renamed { old_key, new_key, value } => LangChangeRecord {
+                kind: LangChangeKind::Renamed,
+                key: new_key,
+                old: Some(value.clone()),
+                new: Some(value),
+                old_key: Some(old_key),
+                revert: false,
+            },
+        }
+    }
+}
+
+/// Ключ переименован, а значение осталось прежним
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenamedLangEntry {
+    pub old_key: String,
+    pub new_key: String,
+    pub value: String,
+    #[serde(skip)]
+    pub position: usize,
+}
+
+/// Изменения одной локализации между копией окружения и файлом игры; списки идут в порядке строк файла
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LangDiff {
+    pub locale: String,
+    pub added: Vec<LangEntry>,
+    pub modified: Vec<ModifiedLangEntry>,
+    pub removed: Vec<LangEntry>,
+    pub renamed: Vec<RenamedLangEntry>,
+    // Строки файла игры, которые не были корректным UTF-8 и прочитаны как CP1251
+    #[serde(skip_serializing_if = "is_zero")]
+    pub fallback_lines: usize,
+    // Изменения в ключах, отброшенных фильтром lang.ignore_keys
+    #[serde(skip_serializing_if = "is_zero")]
+    pub filtered: usize,
+    // Путь файла относительно modassets/assets при сканировании всего дерева (lang.scan_tree)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // Ключи, встречающиеся в файле игры несколько раз; в сравнении участвует последнее значение
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicates: Vec<String>,
+    // Ветка (имя папки: live, ots), когда в одном цикле отслеживается несколько веток (lang.branches)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Содержимое lang файла после декодирования
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodedLang {
+    pub content: String,
+    // Сколько строк не было корректным UTF-8 и декодировано как CP1251
+    pub fallback_lines: usize,
+}
+
+/// Декодирует lang файл построчно: UTF-8, а строки с некорректными байтами - как CP1251.
+/// Байты, которых нет и в CP1251, заменяются на U+FFFD, поэтому декодирование не завершается ошибкой
+pub fn decode_lang_bytes(bytes: &[u8]) -> DecodedLang {
+    if let Ok(content) = std::str::from_utf8(bytes) {
+        return DecodedLang {
+            content: content.to_string(),
+            fallback_lines: 0,
+        };
+    }
+
+    let mut decoded = DecodedLang::default();
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        match std::str::from_utf8(line) {
+            Ok(line) => decoded.content.push_str(line),
+            Err(_) => {
+                let (line, _) = encoding_rs::WINDOWS_1251.decode_without_bom_handling(line);
+                // Неопределённый в CP1251 байт 0x98 декодируется в управляющий символ, а не в U+FFFD
+                decoded.content.extend(line.chars().map(|c| match c {
+                    '\t' | '\r' | '\n' => c,
+                    c if c.is_control() => char::REPLACEMENT_CHARACTER,
+                    c => c,
+                }));
+                decoded.fallback_lines += 1;
+            }
+        }
+    }
+    decoded
+}
+
+// Повторяет чтение, пока два чтения подряд не совпадут по размеру и хэшу. None, если файл так и не устоялся
+fn read_until_stable(
+    mut read: impl FnMut() -> io::Result<Vec<u8>>,
+    delay: Duration,
+    attempts: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut previous = read()?;
+    for _ in 0..attempts {
+        thread::sleep(delay);
+        let current = read()?;
+        if current.len() == previous.len() && xxh3_64(&current) == xxh3_64(&previous) {
+            return Ok(Some(current));
+        }
+        previous = current;
+    }
+    Ok(None)
+}
+
+fn read_stable_bytes(path: &Path) -> Result<Vec<u8>, LangError> {
+    read_until_stable(|| retry_io(|| fs::read(path)), STABLE_READ_DELAY, STABLE_READ_ATTEMPTS)?
+        .ok_or_else(|| LangError::Unstable(path.to_path_buf()))
+}
+
+// Файл игры читается только после проверки, что его не дописывают: обрезанный файл дал бы ложное
+// удаление всех ключей. Возвращает и исходные байты, чтобы копия окружения совпала с прочитанным
+fn read_game_lang_file(path: &Path) -> Result<(Vec<u8>, DecodedLang), LangError> {
+    let bytes = read_stable_bytes(path)?;
+    let decoded = decode_lang(path, &bytes);
+    Ok((bytes, decoded))
+}
+
+// Копия окружения из файла игры, прочитанного после проверки стабильности
+fn copy_game_lang_file(from: &Path, to: &Path) -> Result<(), LangError> {
+    let bytes = read_stable_bytes(from)?;
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(write_atomic(to, &bytes)?)
+}
+
+fn read_lang_file(path: &Path) -> Result<DecodedLang, LangError> {
+    Ok(decode_lang(path, &fs::read(path)?))
+}
+
+fn decode_lang(path: &Path, bytes: &[u8]) -> DecodedLang {
+    let decoded = decode_lang_bytes(bytes);
+    if decoded.fallback_lines > 0 {
+        eprintln!(
+            "Предупреждение: в {} строк не в UTF-8 ({}), прочитаны как CP1251",
+            path.display(),
+            decoded.fallback_lines
+        );
+    }
+    decoded
+}
+
+impl LangDiff {
+    /// Имя файла для заголовков: путь в дереве ассетов или <локаль>.lang
+    pub fn file_name(&self) -> String {
+        match &self.path {
+            Some(path) => path.clone(),
+            None => format!("{}.lang", self.locale),
+        }
+    }
+
+    /// Имя артефактов <имя>_changes.diff: локаль или путь в дереве через точки
+    pub fn artifact_name(&self) -> String {
+        match &self.path {
+            Some(path) => lang_tree_artifact_name(path),
+            None => self.locale.clone(),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.added.len() + self.modified.len() + self.removed.len() + self.renamed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Все изменения одним списком в порядке строк файла, как в lang_changes.diff
+    pub fn changes(&self) -> Vec<LangChange> {
+        let added = self.added.iter().map(|e| {
+            let change = LangChange::Added {
+                key: e.key.clone(),
+                value: e.value.clone(),
+            };
+            (e.position, change)
+        });
+        let modified = self.modified.iter().map(|e| {
+            let change = LangChange::Modified {
+                key: e.key.clone(),
+                old: e.old.clone(),
+                new: e.new.clone(),
+                revert: e.revert,
+            };
+            (e.position, change)
+        });
+        let removed = self.removed.iter().map(|e| {
+            let change = LangChange::Removed {
+                key: e.key.clone(),
+                value: e.value.clone(),
+            };
+            (e.position, change)
+        });
+        let renamed = self.renamed.iter().map(|e| {
+            let change = LangChange::Renamed {
+                old_key: e.old_key.clone(),
+                new_key: e.new_key.clone(),
+                value: e.value.clone(),
+            };
+            (e.position, change)
+        });
+        let mut changes: Vec<_> = added.chain(modified).chain(removed).chain(renamed).collect();
+        changes.sort_by_key(|(position, _)| *position);
+        changes.into_iter().map(|(_, change)| change).collect()
+    }
+
+    /// Убирает изменения в ключах, подходящих под один из шаблонов, и добавляет их число к filtered
+    pub fn remove_ignored(&mut self, ignore_keys: &[String]) {
+        if ignore_keys.is_empty() {
+            return;
+        }
+        self.filtered += self.retain_by_patterns(ignore_keys, false);
+    }
+
+    /// Оставляет только изменения в ключах, подходящих под один из шаблонов (`krevetka lang-diff --filter`);
+    /// переименование остаётся, если под шаблон подходит старый или новый ключ
+    pub fn retain_keys(&mut self, patterns: &[String]) {
+        self.retain_by_patterns(patterns, true);
+    }
+
+    // Оставляет изменения, у которых совпадение хотя бы одного ключа с шаблонами равно keep_matching.
+    // Возвращает число убранных изменений
+    fn retain_by_patterns(&mut self, patterns: &[String], keep_matching: bool) -> usize {
+        let matches = |key: &str| is_ignored_key(key, patterns);
+        let before = self.total();
+        self.added.retain(|e| matches(&e.key) == keep_matching);
+        self.modified.retain(|e| matches(&e.key) == keep_matching);
+        self.removed.retain(|e| matches(&e.key) == keep_matching);
+        self.renamed.retain(|e| (matches(&e.old_key) || matches(&e.new_key)) == keep_matching);
+        before - self.total()
+    }
+
+    /// Сколько изменений учитывается для порога lang.min_changes: ключи из quiet_keys не считаются
+    pub fn publishable_count(&self, quiet_keys: &[String]) -> usize {
+        self.changes().iter().filter(|change| !is_ignored_key(change.key(), quiet_keys)).count()
+    }
+
+    /// Хэш набора изменений без учёта их порядка: одинаковые изменения, записанные в другом порядке или
+    /// другим форматом lang.diff_format, дают один хэш
+    pub fn semantic_hash(&self) -> u64 {
+        let mut records: Vec<String> = self
+            .changes()
+            .into_iter()
+            .map(|change| {
+                let record = LangChangeRecord::from(change);
+                let field = |value: &Option<String>| value.as_deref().map_or("\u{0}".to_string(), str::to_string);
+                format!(
+                    "{:?}\u{1f}{}\u{1f}{}\u{1f}{}\u{1f}{}\u{1f}{}",
+                    record.kind,
+                    record.key,
+                    field(&record.old),
+                    field(&record.new),
+                    field(&record.old_key),
+                    record.revert
+                )
+            })
+            .collect();
+        records.sort_unstable();
+        let branch = self.branch.as_deref().unwrap_or_default();
+        xxh3_64(format!("{}\u{1e}{}\u{1e}{}", branch, self.file_name(), records.join("\u{1e}")).as_bytes())
+    }
+
+    /// Порядок изменений сохраняется: позиции назначаются по месту в списке
+    pub fn from_changes(locale: &str, changes: Vec<LangChange>) -> Self {
+        let mut diff = LangDiff {
+            locale: locale.to_string(),
+            ..LangDiff::default()
+        };
+        for (position, change) in changes.into_iter().enumerate() {
+            match change {
+                LangChange::Added { key, value } => diff.added.push(LangEntry { key, value, position }),
+                LangChange::Modified { key, old, new, revert } => diff.modified.push(ModifiedLangEntry {
+                    key,
+                    old,
+                    new,
+                    revert,
+                    position,
+                }),
+                LangChange::Removed { key, value } => diff.removed.push(LangEntry { key, value, position }),
+                LangChange::Renamed { old_key, new_key, value } => diff.renamed.push(RenamedLangEntry {
+                    old_key,
+                    new_key,
+                    value,
+                    position,
+                }),
+            }
+        }
+        diff
+    }
+
+    /// Удалённый и добавленный ключ с одинаковым непустым значением считаются переименованием.
+    /// Пары составляются только если значение встречается ровно один раз среди удалённых и среди добавленных
+    pub fn detect_renames(&mut self) {
+        fn count_values(entries: &[LangEntry]) -> HashMap<&str, usize> {
+            let mut counts = HashMap::new();
+            for value in entries.iter().filter_map(|e| e.value.as_deref()).filter(|v| !v.is_empty()) {
+                *counts.entry(value).or_insert(0) += 1;
+            }
+            counts
+        }
+
+        let unique_values: HashSet<String> = {
+            let added_counts = count_values(&self.added);
+            let removed_counts = count_values(&self.removed);
+            added_counts
+                .iter()
+                .filter(|&(value, &count)| count == 1 && removed_counts.get(value) == Some(&1))
+                .map(|(value, _)| value.to_string())
+                .collect()
+        };
+        if unique_values.is_empty() {
+            return;
+        }
+        let is_unique = |entry: &LangEntry| entry.value.as_ref().is_some_and(|v| unique_values.contains(v));
+
+        let mut renamed_from: HashMap<String, String> = self
+            .removed
+            .iter()
+            .filter(|e| is_unique(e))
+            .filter_map(|e| Some((e.value.clone()?, e.key.clone())))
+            .collect();
+        self.removed.retain(|e| !is_unique(e));
+        let mut added = Vec::with_capacity(self.added.len());
+        for entry in std::mem::take(&mut self.added) {
+            match entry.value.as_ref().and_then(|value| renamed_from.remove(value)) {
+                Some(old_key) => self.renamed.push(RenamedLangEntry {
+                    old_key,
+                    new_key: entry.key,
+                    value: entry.value.unwrap_or_default(),
+                    position: entry.position,
+                }),
+                None => added.push(entry),
+            }
+        }
+        self.added = added;
+        self.renamed.sort_by_key(|e| e.position);
+    }
+}
+
+/// Убирает BOM в начале файла и приводит переводы строк (`\r\n`, `\r`) к `\n`: после патчей игры
+/// в ru.lang встречаются оба варианта, и без нормализации BOM прилипает к первому ключу
+pub fn normalize_lang_content(content: &str) -> String {
+    normalized_lang_content(content).into_owned()
+}
+
+// normalize_lang_content без копирования файла, в котором нет BOM и \r
+fn normalized_lang_content(content: &str) -> Cow<'_, str> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    if content.contains('\r') {
+        Cow::Owned(content.replace("\r\n", "\n").replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(content)
     }
+}
 
-    let game_lines: std::collections::HashMap<_, _> = game_content
+/// Ключ подходит хотя бы под один шаблон; шаблон без * и ? - точное имя ключа
+pub fn is_ignored_key(key: &str, ignore_keys: &[String]) -> bool {
+    ignore_keys.iter().any(|pattern| glob_match(pattern, key))
+}
+
+// Для unified diff отфильтрованные ключи убираются из самих строк файла
+fn remove_ignored_lines(content: &str, ignore_keys: &[String]) -> String {
+    content
         .lines()
-        .filter(|l| !l.trim().is_empty())
-        .map(|l| {
-            let parts: Vec<_> = l.splitn(2, '=').collect();
-            (parts[0].trim(), parts.get(1).map(|s| s.trim()))
+        .filter(|line| {
+            let key = line.split_once('=').map_or(*line, |(key, _)| key).trim();
+            key.is_empty() || !is_ignored_key(key, ignore_keys)
+        })
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Как приводить значения к общему виду перед сравнением
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LangParseOptions {
+    // Считать любые последовательности пробелов и табуляций внутри значения одним пробелом
+    pub collapse_whitespace: bool,
+    // Отбрасывать комментарий в конце значения (strip_inline_comment)
+    pub strip_comments: bool,
+}
+
+impl LangParseOptions {
+    pub fn from_config(config: &LangConfig) -> Self {
+        LangParseOptions {
+            collapse_whitespace: config.collapse_whitespace,
+            strip_comments: config.strip_inline_comments,
+        }
+    }
+}
+
+/// Значение без комментария в конце: `#` или `//`, перед которым пробел, а после - пробел или конец строки.
+/// Внутри кавычек (с учётом `\"`) комментариев не бывает, а `#5`, `C#` и `http://` остаются частью текста.
+/// Значение, которое начинается с `#`, комментарием не считается
+pub fn strip_inline_comment(value: &str) -> &str {
+    let value = value.trim_start();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut previous_space = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && previous_space {
+            let rest = &value[i..];
+            let comment = rest.strip_prefix('#').or_else(|| rest.strip_prefix("//"));
+            if comment.is_some_and(|comment| comment.is_empty() || comment.starts_with(char::is_whitespace)) {
+                return value[..i].trim_end();
+            }
+        }
+        previous_space = c.is_whitespace();
+    }
+    value
+}
+
+/// Значение в том виде, в котором оно сравнивается: без пробелов по краям и, если включено, без комментария
+/// в конце и с одиночными пробелами внутри. Одинаково применяется к обеим сторонам сравнения
+pub fn canonical_value<'a>(value: &'a str, options: &LangParseOptions) -> Cow<'a, str> {
+    let value = if options.strip_comments { strip_inline_comment(value) } else { value };
+    let value = value.trim();
+    // Значение без табуляций и двойных пробелов не копируется
+    let mut previous_space = false;
+    let irregular = value.chars().any(|c| {
+        let irregular = c.is_whitespace() && (c != ' ' || previous_space);
+        previous_space = c.is_whitespace();
+        irregular
+    });
+    if options.collapse_whitespace && irregular {
+        Cow::Owned(value.split_whitespace().collect::<Vec<_>>().join(" "))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+// Строка файла: номер строки, ключ и значение как в файле
+#[derive(Debug, Clone, Copy)]
+struct LangLine<'a> {
+    line: usize,
+    key: &'a str,
+    value: Option<&'a str>,
+}
+
+fn lang_lines(content: &str) -> impl Iterator<Item = LangLine<'_>> {
+    content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()).map(|(line, l)| {
+        let mut parts = l.splitn(2, '=');
+        LangLine {
+            line,
+            key: parts.next().unwrap_or_default().trim(),
+            value: parts.next(),
+        }
+    })
+}
+
+// Разобранный файл без копирования строк и без HashMap: на файлах в несколько мегабайт хэширование
+// каждого ключа было основной ценой сравнения, а отсортированные списки сравниваются слиянием
+struct ParsedLang<'a> {
+    // По одной строке на ключ, отсортированы по ключу. Повторяющийся ключ, как и в игре, берётся из последнего
+    // вхождения, и его строка задаёт позицию ключа
+    entries: Vec<LangLine<'a>>,
+    // Индексы entries в порядке строк файла
+    order: Vec<usize>,
+    // Повторяющиеся ключи в порядке второго вхождения
+    duplicates: Vec<&'a str>,
+}
+
+impl<'a> ParsedLang<'a> {
+    fn parse(content: &'a str) -> Self {
+        let mut entries: Vec<LangLine> = lang_lines(content).collect();
+        // Сортировка устойчивая: вхождения одного ключа остаются в порядке файла
+        entries.sort_by(|a, b| a.key.cmp(b.key));
+        let mut duplicates = Vec::new();
+        // dedup_by оставляет первый элемент группы, поэтому в него переносится более позднее вхождение
+        entries.dedup_by(|later, kept| {
+            if later.key != kept.key {
+                return false;
+            }
+            if duplicates.last().is_none_or(|&(_, key)| key != kept.key) {
+                duplicates.push((later.line, later.key));
+            }
+            std::mem::swap(later, kept);
+            true
+        });
+        duplicates.sort_unstable();
+
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        order.sort_unstable_by_key(|&i| entries[i].line);
+        ParsedLang {
+            entries,
+            order,
+            duplicates: duplicates.into_iter().map(|(_, key)| key).collect(),
+        }
+    }
+}
+
+/// Категория ключей, которые не подошли ни под один шаблон
+pub const OTHER_LANG_CATEGORY: &str = "Прочее";
+
+// Встроенная таблица категорий; порядок задаёт и приоритет шаблонов, и порядок разделов в патчноуте
+const BUILTIN_LANG_CATEGORIES: &[(&str, &[&str])] = &[
+    ("Оружие", &["item.wpn.*", "item.weapon.*", "weapon.*", "item.ammo.*"]),
+    ("Броня", &["item.armor.*", "item.arm.*", "armor.*"]),
+    ("Артефакты", &["item.art.*", "item.artefact.*", "item.artifact.*", "artefact.*", "artifact.*"]),
+    ("Квесты", &["quest.*", "quests.*", "dialog.*", "npc.dialog.*"]),
+    ("Интерфейс", &["ui.*", "gui.*", "menu.*", "hud.*", "options.*", "key.*"]),
+];
+
+/// Категории изменений локализации для патчноута: правила из lang.categories, затем встроенные.
+/// Ключ относится к первой категории с подходящим шаблоном, остальные - к «Прочему»
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangCategories {
+    rules: Vec<LangCategoryRule>,
+}
+
+impl Default for LangCategories {
+    fn default() -> Self {
+        LangCategories::with_overrides(&[])
+    }
+}
+
+impl LangCategories {
+    pub fn with_overrides(overrides: &[LangCategoryRule]) -> Self {
+        let builtin = BUILTIN_LANG_CATEGORIES.iter().map(|(name, keys)| LangCategoryRule {
+            name: name.to_string(),
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+        });
+        LangCategories {
+            rules: overrides.iter().cloned().chain(builtin).collect(),
+        }
+    }
+
+    pub fn category(&self, key: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|rule| rule.keys.iter().any(|pattern| glob_match(pattern, key)))
+            .map_or(OTHER_LANG_CATEGORY, |rule| rule.name.as_str())
+    }
+
+    /// Изменения по категориям в порядке таблицы, «Прочее» последним; пустые категории пропускаются.
+    /// Внутри категории изменения идут в исходном порядке
+    pub fn group<'a>(&self, changes: &'a [LangChange]) -> Vec<(&str, Vec<&'a LangChange>)> {
+        let mut groups: Vec<(&str, Vec<&LangChange>)> = Vec::new();
+        for rule in &self.rules {
+            if !groups.iter().any(|(name, _)| *name == rule.name) {
+                groups.push((&rule.name, Vec::new()));
+            }
+        }
+        groups.push((OTHER_LANG_CATEGORY, Vec::new()));
+        for change in changes {
+            let category = self.category(change.key());
+            if let Some((_, group)) = groups.iter_mut().find(|(name, _)| *name == category) {
+                group.push(change);
+            }
+        }
+        groups.retain(|(_, changes)| !changes.is_empty());
+        groups
+    }
+}
+
+/// Ключи, которые встречаются в файле больше одного раза, в порядке второго вхождения
+pub fn duplicate_lang_keys(content: &str) -> Vec<String> {
+    ParsedLang::parse(content).duplicates.into_iter().map(String::from).collect()
+}
+
+fn warn_duplicate_keys(path: &Path, duplicates: &[String]) {
+    if !duplicates.is_empty() {
+        eprintln!(
+            "Предупреждение: в {} повторяются ключи, учтено последнее значение: {}",
+            path.display(),
+            duplicates.join(", ")
+        );
+    }
+}
+
+/// Изменения между двумя версиями файла локализации.
+/// Ключ с новым значением - всегда одно изменение в modified, а не пара удаление + добавление
+pub fn diff_lang_content(locale: &str, old_content: &str, new_content: &str) -> LangDiff {
+    diff_lang_content_with(locale, old_content, new_content, &LangParseOptions::default())
+}
+
+/// diff_lang_content с настройками приведения значений
+pub fn diff_lang_content_with(locale: &str, old_content: &str, new_content: &str, options: &LangParseOptions) -> LangDiff {
+    let mut diff = diff_lang_keys(locale, old_content, new_content, options);
+    diff.detect_renames();
+    diff
+}
+
+// Сравнение по ключам без поиска переименований. Ключи обоих файлов сопоставляются слиянием отсортированных
+// списков, а строки копируются только для изменений. Позиции в выводе: ключи нового файла по порядку строк,
+// а удалённый ключ ставится сразу после ближайшего предшествующего ему в старом файле ключа, который остался
+fn diff_lang_keys(locale: &str, old_content: &str, new_content: &str, options: &LangParseOptions) -> LangDiff {
+    let old_content = normalized_lang_content(old_content);
+    let new_content = normalized_lang_content(new_content);
+    let old = ParsedLang::parse(&old_content);
+    let new = ParsedLang::parse(&new_content);
+
+    // Для каждого ключа нового файла - индекс того же ключа в старом, и наоборот
+    let mut old_match = vec![None; old.entries.len()];
+    let mut new_match = vec![None; new.entries.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.entries.len() && j < new.entries.len() {
+        match old.entries[i].key.cmp(new.entries[j].key) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                old_match[i] = Some(j);
+                new_match[j] = Some(i);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    // Удалённые ключи с номером строки в новом файле того ключа, после которого они выводятся
+    let mut removed = Vec::new();
+    let mut anchor = None;
+    for &i in &old.order {
+        match old_match[i] {
+            Some(j) => anchor = Some(new.entries[j].line),
+            None => removed.push((anchor, i)),
+        }
+    }
+    removed.sort_by_key(|&(anchor, _)| anchor);
+
+    fn value<'a>(line: &LangLine<'a>, options: &LangParseOptions) -> Option<Cow<'a, str>> {
+        line.value.map(|value| canonical_value(value, options))
+    }
+    let owned = |value: Option<Cow<str>>| value.map(Cow::into_owned);
+    let mut diff = LangDiff {
+        locale: locale.to_string(),
+        duplicates: new.duplicates.iter().map(|key| key.to_string()).collect(),
+        ..LangDiff::default()
+    };
+    let mut removed = removed.into_iter().peekable();
+    let mut new_order = new.order.iter();
+    let mut anchor = None;
+    let mut position = 0;
+    loop {
+        while let Some((_, i)) = removed.next_if(|&(removed_anchor, _)| removed_anchor == anchor) {
+            let entry = &old.entries[i];
+            diff.removed.push(LangEntry {
+                key: entry.key.to_string(),
+                value: owned(value(entry, options)),
+                position,
+            });
+            position += 1;
+        }
+        let Some(&j) = new_order.next() else {
+            break;
+        };
+        let entry = &new.entries[j];
+        anchor = Some(entry.line);
+        match new_match[j] {
+            None => diff.added.push(LangEntry {
+                key: entry.key.to_string(),
+                value: owned(value(entry, options)),
+                position,
+            }),
+            Some(i) => {
+                let (old_value, new_value) = (value(&old.entries[i], options), value(entry, options));
+                if old_value != new_value {
+                    diff.modified.push(ModifiedLangEntry {
+                        key: entry.key.to_string(),
+                        old: owned(old_value),
+                        new: owned(new_value),
+                        revert: false,
+                        position,
+                    });
+                }
+            }
+        }
+        position += 1;
+    }
+    diff
+}
+
+/// Сравнивает локализацию ОТС с основным сервером: added - строки только на ОТС (там видно ещё не
+/// анонсированный контент), removed - только на основном сервере, modified - значения отличаются.
+/// Переименования не ищутся, чтобы каждый ключ только на ОТС попал в отчёт
+pub fn compare_branches(live_path: &Path, ots_path: &Path) -> Result<LangDiff, LangError> {
+    let locale = live_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let live = read_lang_file(live_path)?;
+    let ots = read_lang_file(ots_path)?;
+    let mut diff = diff_lang_keys(locale, &live.content, &ots.content, &LangParseOptions::default());
+    diff.fallback_lines = ots.fallback_lines;
+    Ok(diff)
+}
+
+/// compare_branches для каждой локали, файлы которой есть в обеих ветках игры
+pub fn compare_game_branches(game_path: &Path, locales: &[String]) -> Result<Vec<LangDiff>, LangError> {
+    // Локали сравниваются параллельно, порядок результата - порядок locales
+    let diffs: Result<Vec<Option<LangDiff>>, LangError> = locales
+        .par_iter()
+        .map(|locale| {
+            let live_path = lang_file_path(game_path, &Branch::Live, locale);
+            let ots_path = lang_file_path(game_path, &Branch::Ots, locale);
+            if live_path.exists() && ots_path.exists() {
+                compare_branches(&live_path, &ots_path).map(Some)
+            } else {
+                Ok(None)
+            }
         })
         .collect();
+    Ok(diffs?.into_iter().flatten().collect())
+}
 
-    let env_lines: std::collections::HashMap<_, _> = env_content
-        .lines()
-        .filter(|l| !l.trim().is_empty())
-        .map(|l| {
-            let parts: Vec<_> = l.splitn(2, '=').collect();
-            (parts[0].trim(), parts.get(1).map(|s| s.trim()))
+/// Ключ, добавленный в одних локалях и не появившийся в других за то же время
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocaleGap {
+    pub key: String,
+    pub added_in: Vec<String>,
+    pub missing_in: Vec<String>,
+}
+
+/// Ищет ключи, добавленные в одной локали, но не добавленные в остальные (вероятно, ещё не переведённые).
+/// Проверяются только локали из diffs: неустановленные локально передавать не нужно, они пропускаются.
+/// Ключ считается присутствующим в локали, если он там добавлен, изменён или получен переименованием
+pub fn cross_check_locales(diffs: &HashMap<String, LangDiff>) -> Vec<LocaleGap> {
+    let mut locales: Vec<&String> = diffs.keys().collect();
+    locales.sort();
+    let present: HashMap<&str, HashSet<&str>> = diffs
+        .iter()
+        .map(|(locale, diff)| {
+            let keys = diff
+                .added
+                .iter()
+                .map(|e| e.key.as_str())
+                .chain(diff.modified.iter().map(|e| e.key.as_str()))
+                .chain(diff.renamed.iter().map(|e| e.new_key.as_str()))
+                .collect();
+            (locale.as_str(), keys)
+        })
+        .collect();
+
+    let mut added: HashMap<&str, Vec<String>> = HashMap::new();
+    for locale in &locales {
+        for entry in &diffs[*locale].added {
+            added.entry(entry.key.as_str()).or_default().push(locale.to_string());
+        }
+    }
+    let mut gaps: Vec<LocaleGap> = added
+        .into_iter()
+        .filter_map(|(key, added_in)| {
+            let missing_in: Vec<String> =
+                locales.iter().filter(|locale| !present[locale.as_str()].contains(key)).map(|l| l.to_string()).collect();
+            (!missing_in.is_empty()).then(|| LocaleGap {
+                key: key.to_string(),
+                added_in,
+                missing_in,
+            })
         })
         .collect();
+    gaps.sort_by(|a, b| a.key.cmp(&b.key));
+    gaps
+}
 
-    let mut diff_content = String::new();
-    for (key, new_value) in &game_lines {
-        match env_lines.get(key) {
-            Some(old_value) if old_value != new_value => {
-                diff_content.push_str(&format!("~{} = {}\n", key, new_value.unwrap_or("")));
+/// Формат lang_changes.diff: `+ключ = значение`, `-ключ = значение`, а изменённый ключ - двумя строками подряд:
+/// `~ключ = старое` и `~ключ => новое`, переименованный - `>старый.ключ -> новый.ключ = значение`.
+/// Возврат к прежнему значению помечается в первой строке: `~ключ (revert) = старое`
+pub fn format_lang_changes(changes: &[LangChange]) -> String {
+    let mut content = String::new();
+    for change in changes {
+        match change {
+            LangChange::Added { key, value } => {
+                content.push_str(&format!("+{} = {}\n", key, value.as_deref().unwrap_or("")));
             }
-            None => {
-                diff_content.push_str(&format!("+{} = {}\n", key, new_value.unwrap_or("")));
+            LangChange::Modified { key, old, new, revert } => {
+                let revert = if *revert { REVERT_MARK } else { "" };
+                content.push_str(&format!("~{}{} = {}\n", key, revert, old.as_deref().unwrap_or("")));
+                let mark = if change.placeholder_mismatch() { PLACEHOLDER_MISMATCH_MARK } else { "" };
+                content.push_str(&format!("~{} => {}{}\n", key, new.as_deref().unwrap_or(""), mark));
+            }
+            LangChange::Removed { key, value: Some(value) } => content.push_str(&format!("-{} = {}\n", key, value)),
+            LangChange::Removed { key, value: None } => content.push_str(&format!("-{}\n", key)),
+            LangChange::Renamed { old_key, new_key, value } => {
+                content.push_str(&format!(">{} -> {} = {}\n", old_key, new_key, value));
+            }
+        }
+    }
+    content
+}
+
+/// Разбирает lang_changes.diff обратно в список изменений; нераспознанные строки пропускаются
+pub fn parse_lang_diff(content: &str) -> Vec<LangChange> {
+    let split = |line: &str| -> (String, Option<String>) {
+        match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), Some(value.trim().to_string())),
+            None => (line.trim().to_string(), None),
+        }
+    };
+
+    let mut changes: Vec<LangChange> = Vec::new();
+    for line in content.lines() {
+        let Some(marker) = line.chars().next() else {
+            continue;
+        };
+        let rest = &line[marker.len_utf8()..];
+        match marker {
+            '+' => {
+                let (key, value) = split(rest);
+                changes.push(LangChange::Added { key, value });
             }
+            '-' => {
+                let (key, value) = split(rest);
+                changes.push(LangChange::Removed { key, value });
+            }
+            '>' => {
+                let (keys, value) = split(rest);
+                if let (Some((old_key, new_key)), Some(value)) = (keys.split_once("->"), value) {
+                    changes.push(LangChange::Renamed {
+                        old_key: old_key.trim().to_string(),
+                        new_key: new_key.trim().to_string(),
+                        value,
+                    });
+                }
+            }
+            // В ключе '=' не бывает, поэтому первый '=' с '>' после него отличает строку с новым значением
+            '~' => match rest.find('=').filter(|&i| rest[i + 1..].starts_with('>')) {
+                Some(i) => {
+                    let (key, revert) = strip_revert_mark(rest[..i].trim());
+                    let value = rest[i + 2..].trim();
+                    let new_value = Some(value.strip_suffix(PLACEHOLDER_MISMATCH_MARK).unwrap_or(value).trim().to_string());
+                    match changes.last_mut() {
+                        Some(LangChange::Modified { key: last_key, new, .. }) if last_key == key => *new = new_value,
+                        _ => changes.push(LangChange::Modified {
+                            key: key.to_string(),
+                            old: None,
+                            new: new_value,
+                            revert,
+                        }),
+                    }
+                }
+                _ => {
+                    let (key, old) = split(rest);
+                    let (key, revert) = strip_revert_mark(&key);
+                    changes.push(LangChange::Modified {
+                        key: key.to_string(),
+                        old,
+                        new: None,
+                        revert,
+                    });
+                }
+            },
             _ => {}
         }
     }
-    for key in env_lines.keys() {
-        if !game_lines.contains_key(key) {
-            if let Some(old_value) = env_lines.get(key).and_then(|v| *v) {
-                diff_content.push_str(&format!("-{} = {}\n", key, old_value));
-            } else {
-                diff_content.push_str(&format!("-{}\n", key));
+    changes
+}
+
+// Ключ без пометки REVERT_MARK и признак того, что она была
+fn strip_revert_mark(key: &str) -> (&str, bool) {
+    match key.strip_suffix(REVERT_MARK) {
+        Some(key) => (key.trim_end(), true),
+        None => (key, false),
+    }
+}
+
+/// Разбирает lang-diff в формате unified diff. Строки контекста и заголовки пропускаются, а ключ,
+/// который в одном фрагменте удалён и добавлен, считается изменённым. Переименования определяются так же,
+/// как в diff_lang_content
+pub fn parse_unified_lang_diff(content: &str) -> Vec<LangChange> {
+    let split = |line: &str| -> (String, Option<String>) {
+        match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), Some(value.trim().to_string())),
+            None => (line.trim().to_string(), None),
+        }
+    };
+
+    // Ключ -> (номер строки в diff, значение)
+    let mut removed = HashMap::new();
+    let mut added = HashMap::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.starts_with("---") || line.starts_with("+++") {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix('-') {
+            let (key, value) = split(rest);
+            if !key.is_empty() {
+                removed.insert(key, (line_number, value));
+            }
+        } else if let Some(rest) = line.strip_prefix('+') {
+            let (key, value) = split(rest);
+            if !key.is_empty() {
+                added.insert(key, (line_number, value));
             }
         }
     }
 
-    if !diff_content.is_empty() {
-        let diff_path = std::path::PathBuf::from("changes").join("lang_changes.diff");
-        if let Some(parent) = diff_path.parent() {
-            fs::create_dir_all(parent)?;
+    let mut changes = Vec::new();
+    for (key, (line_number, old)) in removed {
+        match added.remove(&key) {
+            Some((added_line, new)) => {
+                changes.push((added_line, LangChange::Modified { key, old, new, revert: false }))
+            }
+            None => changes.push((line_number, LangChange::Removed { key, value: old })),
         }
-        fs::write(&diff_path, diff_content)?;
-        fs::copy(&lang_path, &env_lang)?;
-        println!("Обнаружены и сохранены изменения в файле локализации");
     }
+    changes.extend(added.into_iter().map(|(key, (line_number, value))| (line_number, LangChange::Added { key, value })));
+    changes.sort_by_key(|(line_number, _)| *line_number);
+    let mut diff = LangDiff::from_changes("", changes.into_iter().map(|(_, change)| change).collect());
+    diff.detect_renames();
+    diff.changes()
+}
+
+pub fn lang_file_path(game_path: &Path, branch: &Branch, locale: &str) -> std::path::PathBuf {
+    lang_assets_root(game_path, branch)
+        .join("stalker")
+        .join("lang")
+        .join(format!("{}.lang", locale))
+}
+
+/// Артефакты каждой ветки лежат в своей папке changes/<ветка>, чтобы ветки не смешивались
+pub fn lang_diff_path(branch: &Branch, locale: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from("changes")
+        .join(branch.env_dir_name())
+        .join(format!("{}_changes.diff", locale))
+}
+
+// Метка времени в именах файлов истории; строки сравниваются как время
+const HISTORY_TIMESTAMP: &str = "%Y%m%d_%H%M%S_%3f";
+const HISTORY_TIMESTAMP_LEN: usize = 19;
+
+/// Текущее время в формате имён файлов истории
+pub fn history_timestamp() -> String {
+    chrono::Local::now().format(HISTORY_TIMESTAMP).to_string()
+}
+
+pub fn lang_history_dir(branch: &Branch) -> std::path::PathBuf {
+    match lang_diff_path(branch, "").parent() {
+        Some(parent) => parent.join("history"),
+        None => std::path::PathBuf::from("history"),
+    }
+}
 
+/// Переносит историю основного сервера из прежнего места changes/history в changes/live/history,
+/// чтобы накопленные с последней публикации изменения не потерялись. Возвращает true, если перенос был
+pub fn migrate_legacy_lang_history() -> Result<bool, LangError> {
+    let legacy = std::path::PathBuf::from("changes").join("history");
+    let target = lang_history_dir(&Branch::Live);
+    if !legacy.is_dir() || target.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&legacy, &target)?;
+    Ok(true)
+}
+
+// Файл в папке истории с хэшем последнего обработанного набора изменений локализации
+const LAST_LANG_HASH_FILE: &str = "last_lang_hash";
+
+/// Хэш изменений нескольких файлов, не зависящий от порядка файлов и изменений в них
+pub fn lang_diffs_hash(diffs: &[LangDiff]) -> u64 {
+    let mut hashes: Vec<u64> = diffs.iter().filter(|diff| !diff.is_empty()).map(LangDiff::semantic_hash).collect();
+    hashes.sort_unstable();
+    let bytes: Vec<u8> = hashes.iter().flat_map(|hash| hash.to_le_bytes()).collect();
+    xxh3_64(&bytes)
+}
+
+/// Хэш, сохранённый save_last_lang_hash; None, если его нет или файл повреждён
+pub fn load_last_lang_hash(history_dir: &Path) -> Option<u64> {
+    let content = fs::read_to_string(history_dir.join(LAST_LANG_HASH_FILE)).ok()?;
+    u64::from_str_radix(content.trim(), 16).ok()
+}
+
+/// Запоминает хэш обработанных изменений, чтобы после перезапуска они не опубликовались повторно
+pub fn save_last_lang_hash(history_dir: &Path, hash: u64) -> Result<(), LangError> {
+    fs::create_dir_all(history_dir)?;
+    write_atomic(&history_dir.join(LAST_LANG_HASH_FILE), format!("{:016x}", hash).as_bytes())?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Файл истории lang_<локаль>_<время>.diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangHistoryEntry {
+    pub path: std::path::PathBuf,
+    pub locale: String,
+    pub timestamp: String,
+}
+
+/// Файлы истории в порядке записи
+pub fn list_lang_history(dir: &Path) -> Result<Vec<LangHistoryEntry>, LangError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_prefix("lang_").and_then(|n| n.strip_suffix(".diff")) else {
+            continue;
+        };
+        if stem.len() <= HISTORY_TIMESTAMP_LEN || !stem.is_char_boundary(stem.len() - HISTORY_TIMESTAMP_LEN) {
+            continue;
+        }
+        let (locale, timestamp) = stem.split_at(stem.len() - HISTORY_TIMESTAMP_LEN);
+        let Some(locale) = locale.strip_suffix('_') else {
+            continue;
+        };
+        entries.push(LangHistoryEntry {
+            locale: locale.to_string(),
+            timestamp: timestamp.to_string(),
+            path,
+        });
+    }
+    entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.locale.cmp(&b.locale)));
+    Ok(entries)
+}
+
+/// Сохраняет diff в папку истории, чтобы следующий diff его не затёр
+pub fn archive_lang_diff(dir: &Path, locale: &str, content: &str) -> Result<std::path::PathBuf, LangError> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("lang_{}_{}.diff", locale, history_timestamp()));
+    write_atomic(&path, content.as_bytes())?;
+    Ok(path)
+}
+
+/// Удаляет самые старые файлы истории сверх keep; 0 - хранить все
+pub fn prune_lang_history(dir: &Path, keep: usize) -> Result<Vec<std::path::PathBuf>, LangError> {
+    if keep == 0 {
+        return Ok(Vec::new());
+    }
+    let history = list_lang_history(dir)?;
+    let excess = history.len().saturating_sub(keep);
+    let removed: Vec<_> = history.into_iter().take(excess).map(|entry| entry.path).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+/// Сохранённая версия файла локализации: <имя>_<время>_<хэш>.lang в environment/lang/snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangSnapshot {
+    pub path: PathBuf,
+    pub name: String,
+    pub timestamp: String,
+    // Первые 8 символов xxh3 содержимого
+    pub short_hash: String,
+}
+
+const SNAPSHOT_INDEX: &str = "index.tsv";
+const SNAPSHOT_SHORT_HASH_LEN: usize = 8;
+
+/// Копирует принятую версию файла name в папку снимков и дописывает строку в index.tsv:
+/// время, имя, полный хэш, число ключей и имя снимка. Журнал не сокращается при удалении старых снимков,
+/// поэтому по нему видно, какая версия действовала в любой момент
+pub fn archive_lang_snapshot(dir: &Path, name: &str, bytes: &[u8]) -> Result<PathBuf, LangError> {
+    fs::create_dir_all(dir)?;
+    let timestamp = history_timestamp();
+    let hash = content_hash(bytes);
+    let file_name = format!("{}_{}_{}.lang", name, timestamp, &hash[..SNAPSHOT_SHORT_HASH_LEN]);
+    let path = dir.join(&file_name);
+    write_atomic(&path, bytes)?;
+
+    let content = normalized_lang_content(&decode_lang_bytes(bytes).content).into_owned();
+    let entries = ParsedLang::parse(&content).entries.len();
+    let index = dir.join(SNAPSHOT_INDEX);
+    let _guard = LANG_STATE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut line = String::new();
+    if !index.exists() {
+        line.push_str("timestamp\tname\thash\tentries\tfile\n");
+    }
+    line.push_str(&format!("{}\t{}\t{}\t{}\t{}\n", timestamp, name, hash, entries, file_name));
+    io::Write::write_all(&mut fs::OpenOptions::new().create(true).append(true).open(&index)?, line.as_bytes())?;
+    Ok(path)
+}
+
+/// Снимки файла name от старых к новым
+pub fn list_lang_snapshots(dir: &Path, name: &str) -> Result<Vec<LangSnapshot>, LangError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        // ru_ не должен захватывать снимки ru_ots_: после имени сразу идут время и хэш
+        let Some((rest, short_hash)) = file_name.strip_suffix(".lang").and_then(|stem| stem.rsplit_once('_')) else {
+            continue;
+        };
+        let Some(timestamp) = rest.strip_prefix(name).and_then(|rest| rest.strip_prefix('_')) else {
+            continue;
+        };
+        if timestamp.len() != HISTORY_TIMESTAMP_LEN || !timestamp.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        snapshots.push(LangSnapshot {
+            name: name.to_string(),
+            timestamp: timestamp.to_string(),
+            short_hash: short_hash.to_string(),
+            path: path.clone(),
+        });
+    }
+    snapshots.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    Ok(snapshots)
+}
+
+/// Удаляет самые старые снимки файла name сверх keep
+pub fn prune_lang_snapshots(dir: &Path, name: &str, keep: usize) -> Result<Vec<PathBuf>, LangError> {
+    let snapshots = list_lang_snapshots(dir, name)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    let removed: Vec<_> = snapshots.into_iter().take(excess).map(|snapshot| snapshot.path).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+/// Копирует снимок поверх копии окружения, чтобы следующий цикл построил diff от этой версии.
+/// timestamp - время снимка целиком или его начало (20250101_1200); из подходящих берётся последний.
+/// Прерванная замена копии отменяется, иначе восстановление завершило бы её
+pub fn restore_lang_snapshot(paths: &LangPaths, timestamp: &str) -> Result<LangSnapshot, LangError> {
+    let snapshot = list_lang_snapshots(&paths.out.snapshot_dir, &paths.out.name)?
+        .into_iter()
+        .rev()
+        .find(|snapshot| snapshot.timestamp.starts_with(timestamp))
+        .ok_or_else(|| LangError::SnapshotNotFound(paths.out.name.clone(), timestamp.to_string()))?;
+    remove_if_exists(&with_suffix(&paths.baseline, ".pending"))?;
+    remove_if_exists(&with_suffix(&paths.baseline, ".state"))?;
+    if let Some(parent) = paths.baseline.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&paths.baseline, &fs::read(&snapshot.path)?)?;
+    Ok(snapshot)
+}
+
+// Пояснения в заголовке diff для человека; разбираются вместе с остальным заголовком
+const LANG_DIFF_FILE_HEADER: &str = "# Файл: ";
+const LANG_DIFF_FALLBACK_HEADER: &str = "# Строк, прочитанных как CP1251: ";
+const LANG_DIFF_DUPLICATES_HEADER: &str = "# Повторяющиеся ключи: ";
+// Строка в конце diff с числом изменений, отброшенных фильтром
+const LANG_DIFF_FILTERED_TRAILER: &str = "# Скрыто фильтром lang.ignore_keys: ";
+
+/// Число изменений по видам, в заголовке diff - `# entries: +12 ~3 -1` (и `>2`, если есть переименования)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LangEntryCounts {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+    pub renamed: usize,
+}
+
+impl LangEntryCounts {
+    pub fn of(diff: &LangDiff) -> Self {
+        LangEntryCounts {
+            added: diff.added.len(),
+            modified: diff.modified.len(),
+            removed: diff.removed.len(),
+            renamed: diff.renamed.len(),
+        }
+    }
+
+    /// Сумма по нескольким файлам, для сводки патчноута
+    pub fn sum(diffs: &[LangDiff]) -> Self {
+        diffs.iter().map(LangEntryCounts::of).fold(LangEntryCounts::default(), |total, counts| LangEntryCounts {
+            added: total.added + counts.added,
+            modified: total.modified + counts.modified,
+            removed: total.removed + counts.removed,
+            renamed: total.renamed + counts.renamed,
+        })
+    }
+
+    pub fn total(&self) -> usize {
+        self.added + self.modified + self.removed + self.renamed
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let mut counts = LangEntryCounts::default();
+        for part in value.split_whitespace() {
+            let mut chars = part.chars();
+            let marker = chars.next()?;
+            let count = chars.as_str().parse().ok()?;
+            match marker {
+                '+' => counts.added = count,
+                '~' => counts.modified = count,
+                '-' => counts.removed = count,
+                '>' => counts.renamed = count,
+                _ => return None,
+            }
+        }
+        Some(counts)
+    }
+}
+
+impl std::fmt::Display for LangEntryCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "+{} ~{} -{}", self.added, self.modified, self.removed)?;
+        if self.renamed > 0 {
+            write!(f, " >{}", self.renamed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Заголовок lang_changes.diff: строки `# ключ: значение` перед пустой строкой и телом diff.
+/// Пишется и разбирается только здесь, чтобы скрипты и патчноут читали одно и то же. В diff, записанных
+/// до появления заголовка, есть только пояснения (путь, CP1251, повторы), остальные поля пустые
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LangDiffHeader {
+    // Абсолютный путь файла игры
+    pub source: Option<PathBuf>,
+    // Абсолютный путь копии окружения, с которой сравнивался файл
+    pub baseline: Option<PathBuf>,
+    // Время записи в RFC 3339
+    pub generated: Option<String>,
+    pub locale: Option<String>,
+    // Имя папки ветки в окружении: live, ots или имя runtime
+    pub branch: Option<String>,
+    pub entries: Option<LangEntryCounts>,
+    // Путь относительно modassets/assets при lang.scan_tree
+    pub path: Option<String>,
+    pub fallback_lines: usize,
+    pub duplicates: Vec<String>,
+}
+
+impl LangDiffHeader {
+    /// Заголовок вместе с завершающей пустой строкой
+    pub fn render(&self) -> String {
+        let mut header = String::new();
+        let mut field = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                header.push_str(&format!("# {}: {}\n", name, value));
+            }
+        };
+        field("source", self.source.as_ref().map(|path| path.display().to_string()));
+        field("baseline", self.baseline.as_ref().map(|path| path.display().to_string()));
+        field("generated", self.generated.clone());
+        field("locale", self.locale.clone());
+        field("branch", self.branch.clone());
+        field("entries", self.entries.map(|entries| entries.to_string()));
+        if let Some(path) = &self.path {
+            header.push_str(&format!("{}{}\n", LANG_DIFF_FILE_HEADER, path));
+        }
+        if self.fallback_lines > 0 {
+            header.push_str(&format!("{}{}\n", LANG_DIFF_FALLBACK_HEADER, self.fallback_lines));
+        }
+        if !self.duplicates.is_empty() {
+            header.push_str(&format!("{}{}\n", LANG_DIFF_DUPLICATES_HEADER, self.duplicates.join(", ")));
+        }
+        header.push('\n');
+        header
+    }
+
+    /// Разбирает строки `#` в начале файла; незнакомые поля пропускаются
+    pub fn parse(content: &str) -> Self {
+        let mut header = LangDiffHeader::default();
+        for line in content.lines().take_while(|line| line.starts_with('#')) {
+            if let Some(path) = line.strip_prefix(LANG_DIFF_FILE_HEADER) {
+                header.path = Some(path.trim().to_string());
+            } else if let Some(count) = line.strip_prefix(LANG_DIFF_FALLBACK_HEADER) {
+                header.fallback_lines = count.trim().parse().unwrap_or_default();
+            } else if let Some(keys) = line.strip_prefix(LANG_DIFF_DUPLICATES_HEADER) {
+                header.duplicates = keys.split(", ").map(|key| key.trim().to_string()).filter(|k| !k.is_empty()).collect();
+            } else if let Some((name, value)) = line[1..].split_once(':') {
+                let value = value.trim().to_string();
+                match name.trim() {
+                    "source" => header.source = Some(PathBuf::from(value)),
+                    "baseline" => header.baseline = Some(PathBuf::from(value)),
+                    "generated" => header.generated = Some(value),
+                    "locale" => header.locale = Some(value),
+                    "branch" => header.branch = Some(value),
+                    "entries" => header.entries = LangEntryCounts::parse(&value),
+                    _ => {}
+                }
+            }
+        }
+        header
+    }
+}
+
+/// Читает записанный diff: заголовок и изменения
+pub fn read_lang_diff(path: &Path) -> Result<(LangDiffHeader, Vec<LangChange>), LangError> {
+    let content = fs::read_to_string(path)?;
+    Ok((LangDiffHeader::parse(&content), parse_lang_diff_file(&content)))
+}
+
+/// Число изменений, скрытых фильтром lang.ignore_keys, из строки в конце diff; 0, если строки нет
+pub fn parse_filtered_trailer(content: &str) -> usize {
+    content
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix(LANG_DIFF_FILTERED_TRAILER))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+/// Разбирает файл diff любого из форматов lang.diff_format
+pub fn parse_lang_diff_file(content: &str) -> Vec<LangChange> {
+    if content.lines().any(|line| line.starts_with("@@")) {
+        parse_unified_lang_diff(content)
+    } else {
+        parse_lang_diff(content)
+    }
+}
+
+pub fn lang_json_path(branch: &Branch, locale: &str) -> std::path::PathBuf {
+    lang_diff_path(branch, locale).with_extension("json")
+}
+
+/// Сколько последних значений каждого ключа хранится для распознавания откатов
+pub const VALUE_HISTORY_PER_KEY: usize = 5;
+/// Сколько ключей всего хранится в истории значений; давно не менявшиеся вытесняются первыми
+pub const VALUE_HISTORY_MAX_KEYS: usize = 20_000;
+
+/// Последние значения изменённых ключей по файлам локализации (<окружение>/lang/history.json).
+/// По ним изменение, вернувшее строку к одному из прежних значений, помечается как откат
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangValueHistory {
+    // Номер последней записи: по нему при переполнении вытесняются самые старые ключи
+    #[serde(default)]
+    seq: u64,
+    // Имя артефактов файла -> ключ -> значения
+    #[serde(default)]
+    files: HashMap<String, HashMap<String, KeyValueHistory>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct KeyValueHistory {
+    // От старых к новым, без повторов
+    values: Vec<String>,
+    seq: u64,
+}
+
+impl KeyValueHistory {
+    fn record(&mut self, value: &str) {
+        if self.values.last().map(String::as_str) == Some(value) {
+            return;
+        }
+        self.values.retain(|v| v != value);
+        self.values.push(value.to_string());
+        if self.values.len() > VALUE_HISTORY_PER_KEY {
+            self.values.drain(..self.values.len() - VALUE_HISTORY_PER_KEY);
+        }
+    }
+}
+
+impl LangValueHistory {
+    /// Отсутствующий или повреждённый файл - пустая история
+    pub fn load(path: &Path) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return LangValueHistory::default(),
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Предупреждение: история значений {} повреждена и начата заново: {}", path.display(), e);
+            LangValueHistory::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LangError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Помечает в diff файла name изменения, вернувшие ключ к прежнему значению, и запоминает новые значения.
+    /// Последнее записанное значение откатом не считается, поэтому повторная обработка того же diff
+    /// (например, после сбоя до обновления копии) ничего не меняет
+    pub fn mark_reverts(&mut self, name: &str, diff: &mut LangDiff) {
+        let keys = self.files.entry(name.to_string()).or_default();
+        for entry in &mut diff.modified {
+            let history = keys.entry(entry.key.clone()).or_default();
+            if let Some(new) = &entry.new {
+                let latest = history.values.last() == Some(new);
+                entry.revert = !latest && history.values.contains(new);
+            }
+            if let Some(old) = &entry.old {
+                history.record(old);
+            }
+            if let Some(new) = &entry.new {
+                history.record(new);
+            }
+            self.seq += 1;
+            history.seq = self.seq;
+        }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        let total: usize = self.files.values().map(HashMap::len).sum();
+        if total <= VALUE_HISTORY_MAX_KEYS {
+            return;
+        }
+        let mut ages: Vec<u64> = self.files.values().flat_map(|keys| keys.values().map(|h| h.seq)).collect();
+        ages.sort_unstable();
+        let threshold = ages[total - VALUE_HISTORY_MAX_KEYS];
+        for keys in self.files.values_mut() {
+            keys.retain(|_, history| history.seq >= threshold);
+        }
+        self.files.retain(|_, keys| !keys.is_empty());
+    }
+}
+
+/// Куда пишутся артефакты изменений одного lang файла
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangOutputPaths {
+    pub diff: PathBuf,
+    pub json: PathBuf,
+    pub history_dir: PathBuf,
+    // История значений для пометки откатов (LangValueHistory)
+    pub value_history: PathBuf,
+    // Снимки принятых версий файла (archive_lang_snapshot)
+    pub snapshot_dir: PathBuf,
+    // Имя в файлах истории lang_<имя>_<время>.diff
+    pub name: String,
+    // Ветка для заголовка diff
+    pub branch: Branch,
+}
+
+impl LangOutputPaths {
+    /// Артефакты ветки окружения в changes/ под именем name
+    pub fn new(env: &Environment, name: &str) -> Self {
+        LangOutputPaths {
+            diff: lang_diff_path(env.branch(), name),
+            json: lang_json_path(env.branch(), name),
+            history_dir: lang_history_dir(env.branch()),
+            value_history: env.lang_dir().join("history.json"),
+            snapshot_dir: env.lang_dir().join("snapshots"),
+            name: name.to_string(),
+            branch: env.branch().clone(),
+        }
+    }
+}
+
+/// Пути одной локализации ветки: файл игры, его копия в окружении и артефакты изменений
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangPaths {
+    pub game_lang: PathBuf,
+    pub baseline: PathBuf,
+    pub out: LangOutputPaths,
+}
+
+impl LangPaths {
+    pub fn resolve(game_path: &Path, env: &Environment, locale: &str) -> Self {
+        LangPaths {
+            game_lang: lang_file_path(game_path, env.branch(), locale),
+            baseline: env.lang_path(locale),
+            out: LangOutputPaths::new(env, locale),
+        }
+    }
+}
+
+// Запись во временный файл и переименование: читатель видит либо старый файл, либо новый целиком
+pub(crate) fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    let result = fs::write(&tmp, content).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Изменения в формате changes/<ветка>/<локаль>_changes.json: массив LangChangeRecord в порядке строк файла
+pub fn lang_changes_json(diff: &LangDiff) -> Result<String, LangError> {
+    let records: Vec<LangChangeRecord> = diff.changes().into_iter().map(LangChangeRecord::from).collect();
+    Ok(serde_json::to_string_pretty(&records)?)
+}
+
+/// Перезаписывает копию локализации без построения diff (например, после смены папки игры)
+pub fn rebaseline_lang_file(game_path: &Path, env: &Environment, locale: &str) -> Result<(), LangError> {
+    let lang_path = lang_file_path(game_path, env.branch(), locale);
+    if !lang_path.exists() {
+        return Ok(());
+    }
+    copy_game_lang_file(&lang_path, &env.lang_path(locale))?;
+    Ok(())
+}
+
+/// Сравнивает локализацию ветки с копией этой же ветки в окружении (у ОТС своя копия, не общая с основным
+/// сервером) и при отличиях обновляет копию. Первая копия создаётся из файла игры той же ветки.
+/// Возвращает None, если файла нет или копия только что создана
+/// Копия не перезаписывается, если изменились только порядок ключей или пробелы: тогда diff пустой
+pub fn sync_lang_file(
+    game_path: &Path,
+    env: &Environment,
+    locale: &str,
+    options: &LangParseOptions,
+) -> Result<Option<LangDiff>, LangError> {
+    let paths = LangPaths::resolve(game_path, env, locale);
+    let Some(comparison) = compare_lang_baseline(&paths.game_lang, &paths.baseline, locale, options)? else {
+        return Ok(None);
+    };
+    if !comparison.diff.is_empty() {
+        write_atomic(&paths.baseline, &comparison.game_bytes)?;
+    }
+    Ok(Some(comparison.diff))
+}
+
+// Результат compare_lang_baseline. Содержимое обоих файлов нужно для unified diff; оно пустое, если файлы
+// совпали побайтно и не декодировались
+struct BaselineComparison {
+    diff: LangDiff,
+    // Прочитанные байты файла игры, которыми вызывающий заменит копию
+    game_bytes: Vec<u8>,
+    old_content: String,
+    new_content: String,
+}
+
+// Файлы совпадают: сначала сравнивается размер, затем xxh3, поэтому неизменившийся файл в несколько
+// мегабайт не декодируется и не разбирается
+fn same_lang_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && xxh3_64(a) == xxh3_64(b)
+}
+
+// Сравнивает файл игры с копией, копию не меняет (кроме первичного создания); копия читается один раз.
+// None, если файла нет или копия только создана
+fn compare_lang_baseline(
+    game_lang: &Path,
+    baseline: &Path,
+    locale: &str,
+    options: &LangParseOptions,
+) -> Result<Option<BaselineComparison>, LangError> {
+    recover_lang_baseline(baseline)?;
+    if !game_lang.exists() {
+        println!("Файл локализации не найден: {}", game_lang.display());
+        return Ok(None);
+    }
+
+    if let Some(env_dir) = baseline.parent() {
+        fs::create_dir_all(env_dir)?;
+    }
+
+    let game_bytes = read_stable_bytes(game_lang)?;
+    if !baseline.exists() {
+        write_atomic(baseline, &game_bytes)?;
+        println!("Создана первичная копия файла локализации {}", game_lang.display());
+        return Ok(None);
+    }
+
+    let baseline_bytes = fs::read(baseline)?;
+    let unchanged = LangDiff {
+        locale: locale.to_string(),
+        ..LangDiff::default()
+    };
+    if same_lang_bytes(&game_bytes, &baseline_bytes) {
+        return Ok(Some(BaselineComparison {
+            diff: unchanged,
+            game_bytes,
+            old_content: String::new(),
+            new_content: String::new(),
+        }));
+    }
+
+    let game = decode_lang(game_lang, &game_bytes);
+    let old_content = decode_lang(baseline, &baseline_bytes).content;
+    let mut diff = if game.content == old_content {
+        unchanged
+    } else {
+        diff_lang_content_with(locale, &old_content, &game.content, options)
+    };
+    diff.fallback_lines = game.fallback_lines;
+    warn_duplicate_keys(game_lang, &diff.duplicates);
+    Ok(Some(BaselineComparison {
+        diff,
+        game_bytes,
+        old_content,
+        new_content: game.content,
+    }))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", xxh3_64(bytes))
+}
+
+// Замена копии окружения идёт после записи артефактов, чтобы копия никогда не опережала сохранённый diff:
+// новое содержимое кладётся в <копия>.pending, пишутся артефакты, затем маркер <копия>.state с хэшем
+// нового содержимого, и только после этого .pending переименовывается в копию
+fn replace_baseline<T>(
+    baseline: &Path,
+    bytes: &[u8],
+    write_artifacts: impl FnOnce() -> Result<T, LangError>,
+) -> Result<T, LangError> {
+    stage_baseline(baseline, bytes)?;
+    let result = write_artifacts()?;
+    mark_artifacts_written(baseline, bytes)?;
+    commit_baseline(baseline)?;
+    Ok(result)
+}
+
+// Удаление копии устроено так же, но без .pending: после артефактов в <копия>.state пишется
+// REMOVED_BASELINE_STATE, затем копия удаляется
+const REMOVED_BASELINE_STATE: &str = "removed";
+
+fn remove_baseline<T>(baseline: &Path, write_artifacts: impl FnOnce() -> Result<T, LangError>) -> Result<T, LangError> {
+    let result = write_artifacts()?;
+    mark_baseline_removed(baseline)?;
+    commit_baseline_removal(baseline)?;
+    Ok(result)
+}
+
+fn mark_baseline_removed(baseline: &Path) -> io::Result<()> {
+    write_atomic(&with_suffix(baseline, ".state"), REMOVED_BASELINE_STATE.as_bytes())
+}
+
+fn commit_baseline_removal(baseline: &Path) -> io::Result<()> {
+    remove_if_exists(baseline)?;
+    remove_if_exists(&with_suffix(baseline, ".state"))
+}
+
+fn stage_baseline(baseline: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = baseline.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&with_suffix(baseline, ".pending"), bytes)
+}
+
+fn mark_artifacts_written(baseline: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_atomic(&with_suffix(baseline, ".state"), content_hash(bytes).as_bytes())
+}
+
+fn commit_baseline(baseline: &Path) -> io::Result<()> {
+    fs::rename(with_suffix(baseline, ".pending"), baseline)?;
+    remove_if_exists(&with_suffix(baseline, ".state"))
+}
+
+/// Доводит до конца замену или удаление копии, прерванные падением процесса. Если маркер подтверждает, что
+/// артефакты записаны, копия заменяется или удаляется; иначе подготовленное содержимое удаляется, и diff
+/// будет построен заново. Возвращает true, если копия была заменена или удалена
+pub fn recover_lang_baseline(baseline: &Path) -> Result<bool, LangError> {
+    let pending = with_suffix(baseline, ".pending");
+    let state = with_suffix(baseline, ".state");
+    if !pending.exists() {
+        if fs::read_to_string(&state).is_ok_and(|marker| marker.trim() == REMOVED_BASELINE_STATE) {
+            commit_baseline_removal(baseline)?;
+            println!("Завершено прерванное удаление копии локализации {}", baseline.display());
+            return Ok(true);
+        }
+        // Копия уже заменена, остался только маркер
+        remove_if_exists(&state)?;
+        return Ok(false);
+    }
+    let pending_hash = content_hash(&fs::read(&pending)?);
+    if fs::read_to_string(&state).is_ok_and(|hash| hash.trim() == pending_hash) {
+        commit_baseline(baseline)?;
+        println!("Завершена прерванная замена копии локализации {}", baseline.display());
+        return Ok(true);
+    }
+    remove_if_exists(&pending)?;
+    remove_if_exists(&state)?;
+    Ok(false)
+}
+
+/// Изменения, которые нашёл бы следующий цикл мониторинга (с учётом lang.ignore_keys), без записи копии
+/// и артефактов. None, если файла игры или копии окружения нет
+pub fn preview_lang_diff(
+    game_path: &Path,
+    env: &Environment,
+    locale: &str,
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    let paths = LangPaths::resolve(game_path, env, locale);
+    if !paths.game_lang.exists() || !paths.baseline.exists() {
+        return Ok(None);
+    }
+    let (_, game) = read_game_lang_file(&paths.game_lang)?;
+    let baseline = read_lang_file(&paths.baseline)?;
+    let mut diff =
+        diff_lang_content_with(locale, &baseline.content, &game.content, &LangParseOptions::from_config(config));
+    diff.remove_ignored(&config.ignore_keys);
+    Ok(Some(diff))
+}
+
+/// Возвращает изменения, если они есть; файлы changes/<ветка>/<локаль>_changes.diff (и его копия в history)
+/// и changes/<ветка>/<локаль>_changes.json пишутся только как артефакты
+pub fn process_lang_file(
+    game_path: &Path,
+    env: &Environment,
+    locale: &str,
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    let paths = LangPaths::resolve(game_path, env, locale);
+    diff_lang_files(&paths.game_lang, &paths.baseline, &paths.out, config)
+}
+
+/// process_lang_file для нескольких локалей параллельно. Результаты идут в порядке locales, чтобы патчноут
+/// не зависел от того, какой поток закончил первым; ошибка одной локали не мешает остальным
+pub fn process_lang_files(
+    game_path: &Path,
+    env: &Environment,
+    locales: &[String],
+    config: &LangConfig,
+) -> Vec<(String, Result<Option<LangDiff>, LangError>)> {
+    // Локалей немного, а большую часть времени поток ждёт проверки стабильности файла, поэтому у каждой
+    // локали свой поток, а не пул по числу ядер
+    thread::scope(|scope| {
+        let handles: Vec<_> = locales
+            .iter()
+            .map(|locale| scope.spawn(move || process_lang_file(game_path, env, locale, config)))
+            .collect();
+        locales
+            .iter()
+            .zip(handles)
+            .map(|(locale, handle)| {
+                let result = handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                (locale.clone(), result)
+            })
+            .collect()
+    })
+}
+
+/// Сравнивает файл игры game_lang с копией baseline, обновляет копию и пишет артефакты в out.
+/// Локаль берётся из имени файла. None, если копия только что создана или изменений нет
+pub fn diff_lang_files(
+    game_lang: &Path,
+    baseline: &Path,
+    out: &LangOutputPaths,
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    let locale = game_lang.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let options = LangParseOptions::from_config(config);
+    let Some(comparison) = compare_lang_baseline(game_lang, baseline, locale, &options)? else {
+        return Ok(None);
+    };
+    if comparison.diff.is_empty() {
+        return Ok(None);
+    }
+    let BaselineComparison {
+        diff,
+        game_bytes,
+        old_content,
+        new_content,
+    } = comparison;
+    let input = LangDiffInput {
+        game_lang,
+        baseline,
+        old_content,
+        new_content,
+    };
+    replace_baseline(baseline, &game_bytes, || accept_lang_change(diff, out, input, &game_bytes, config))
+}
+
+// Из чего построен diff: пути для заголовка артефакта и оба содержимого для unified diff
+struct LangDiffInput<'a> {
+    game_lang: &'a Path,
+    baseline: &'a Path,
+    old_content: String,
+    new_content: String,
+}
+
+// Запись общего состояния локализаций из параллельно обрабатываемых файлов
+static LANG_STATE_LOCK: Mutex<()> = Mutex::new(());
+
+// save_lang_diff и снимок новой версии файла, если изменения остались после фильтра
+fn accept_lang_change(
+    diff: LangDiff,
+    out: &LangOutputPaths,
+    input: LangDiffInput,
+    bytes: &[u8],
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    let saved = save_lang_diff(diff, out, input, config)?;
+    if saved.is_some() && config.snapshot_retention > 0 {
+        archive_lang_snapshot(&out.snapshot_dir, &out.name, bytes)?;
+        prune_lang_snapshots(&out.snapshot_dir, &out.name, config.snapshot_retention)?;
+    }
+    Ok(saved)
+}
+
+// Применяет lang.ignore_keys и пишет артефакты изменений файла локализации в out.
+// None, если после фильтра изменений не осталось
+fn save_lang_diff(
+    mut diff: LangDiff,
+    out: &LangOutputPaths,
+    input: LangDiffInput,
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    diff.remove_ignored(&config.ignore_keys);
+    if diff.filtered > 0 {
+        println!("Изменений в ключах из lang.ignore_keys ({}): {}", diff.file_name(), diff.filtered);
+    }
+    if diff.is_empty() {
+        return Ok(None);
+    }
+    // История значений и папка истории ветки общие для всех файлов, которые обрабатываются параллельно
+    let _guard = LANG_STATE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut value_history = LangValueHistory::load(&out.value_history);
+    value_history.mark_reverts(&out.name, &mut diff);
+
+    // Заголовок перед diff: parse_lang_diff, patch и git apply пропускают текст до первого изменения
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let header = LangDiffHeader {
+        source: Some(absolute(input.game_lang)),
+        baseline: Some(absolute(input.baseline)),
+        generated: Some(chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false)),
+        locale: Some(diff.locale.clone()),
+        branch: Some(out.branch.env_dir_name().to_string()),
+        entries: Some(LangEntryCounts::of(&diff)),
+        path: diff.path.clone(),
+        fallback_lines: diff.fallback_lines,
+        duplicates: diff.duplicates.clone(),
+    };
+    let mut content = header.render();
+    content += &match config.diff_format {
+        LangDiffFormat::Unified => {
+            let file_name = diff.file_name();
+            unified_diff(
+                &format!("a/{}", file_name),
+                &format!("b/{}", file_name),
+                &remove_ignored_lines(&normalize_lang_content(&input.old_content), &config.ignore_keys),
+                &remove_ignored_lines(&normalize_lang_content(&input.new_content), &config.ignore_keys),
+                3,
+            )
+        }
+        LangDiffFormat::Keys => format_lang_changes(&diff.changes()),
+    };
+    if diff.filtered > 0 {
+        content.push_str(&format!("{}{}\n", LANG_DIFF_FILTERED_TRAILER, diff.filtered));
+    }
+    if let Some(parent) = out.diff.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&out.diff, content.as_bytes())?;
+    write_atomic(&out.json, lang_changes_json(&diff)?.as_bytes())?;
+    archive_lang_diff(&out.history_dir, &out.name, &content)?;
+    prune_lang_history(&out.history_dir, config.history_retention)?;
+    value_history.save(&out.value_history)?;
+    println!("Обнаружены и сохранены изменения в файле локализации {}", diff.file_name());
+    Ok(Some(diff))
+}
+
+/// Относительные пути (через /) всех файлов */lang/*.lang внутри root, отсортированные
+pub fn find_lang_files(root: &Path) -> Result<Vec<String>, LangError> {
+    fn walk(dir: &Path, relative: &str, files: &mut Vec<String>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = if relative.is_empty() { name.clone() } else { format!("{}/{}", relative, name) };
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &path, files)?;
+            } else if name.ends_with(".lang") && relative.rsplit('/').next() == Some("lang") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if root.is_dir() {
+        walk(root, "", &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Папка ассетов ветки, в которой лежат все файлы локализации
+pub fn lang_assets_root(game_path: &Path, branch: &Branch) -> std::path::PathBuf {
+    game_path
+        .join("runtime")
+        .join(branch.runtime_dir_name())
+        .join("modassets")
+        .join("assets")
+}
+
+// Имя артефактов файла из дерева: stalker/lang/ru.lang -> stalker.lang.ru
+fn lang_tree_artifact_name(relative: &str) -> String {
+    relative.strip_suffix(".lang").unwrap_or(relative).replace('/', ".")
+}
+
+/// Сравнивает все файлы */lang/*.lang в modassets/assets с копиями в <окружение>/lang/<путь> (lang.scan_tree).
+/// Новый файл - добавление всех его строк, пропавший - удаление всех строк. При первом сканировании
+/// копии только создаются. Возвращает изменения по одному LangDiff на файл
+pub fn process_lang_tree(game_path: &Path, env: &Environment, config: &LangConfig) -> Result<Vec<LangDiff>, LangError> {
+    let assets_root = lang_assets_root(game_path, env.branch());
+    let baseline_root = env.lang_dir();
+    let game_files = find_lang_files(&assets_root)?;
+    let baseline_files = find_lang_files(&baseline_root)?;
+    if baseline_files.is_empty() {
+        for relative in &game_files {
+            copy_game_lang_file(&assets_root.join(relative), &baseline_root.join(relative))?;
+        }
+        println!("Созданы первичные копии файлов локализации: {}", game_files.len());
+        return Ok(Vec::new());
+    }
+
+    let mut all_files = game_files.clone();
+    all_files.extend(baseline_files.iter().filter(|f| !game_files.contains(f)).cloned());
+    all_files.sort();
+
+    // Файлы независимы и сравниваются параллельно; результаты собираются в порядке путей. Ошибка одного файла
+    // выводится и не мешает остальным, а его копия остаётся прежней до следующего цикла
+    let options = LangParseOptions::from_config(config);
+    let results: Vec<_> = all_files
+        .par_iter()
+        .map(|relative| process_lang_tree_file(relative, &assets_root, &baseline_root, env, &options, config))
+        .collect();
+    let mut diffs = Vec::new();
+    for (relative, result) in all_files.iter().zip(results) {
+        match result {
+            Ok(saved) => diffs.extend(saved),
+            Err(e) => eprintln!("Ошибка при обработке файла локализации {}: {}", relative, e),
+        }
+    }
+    Ok(diffs)
+}
+
+// Один файл дерева: relative - путь относительно modassets/assets и корня копий
+fn process_lang_tree_file(
+    relative: &str,
+    assets_root: &Path,
+    baseline_root: &Path,
+    env: &Environment,
+    options: &LangParseOptions,
+    config: &LangConfig,
+) -> Result<Option<LangDiff>, LangError> {
+    let game_file = assets_root.join(relative);
+    let baseline = baseline_root.join(relative);
+    recover_lang_baseline(&baseline)?;
+    let new_bytes = if game_file.exists() { Some(read_stable_bytes(&game_file)?) } else { None };
+    let old_bytes = if baseline.exists() { fs::read(&baseline)? } else { Vec::new() };
+    if new_bytes.as_deref().is_some_and(|new_bytes| same_lang_bytes(new_bytes, &old_bytes)) {
+        return Ok(None);
+    }
+    let new = new_bytes.map(|bytes| {
+        let decoded = decode_lang(&game_file, &bytes);
+        (bytes, decoded)
+    });
+    let old_content = decode_lang(&baseline, &old_bytes).content;
+    let new_content = new.as_ref().map(|(_, decoded)| decoded.content.clone()).unwrap_or_default();
+
+    let locale = Path::new(relative).file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let mut diff = diff_lang_content_with(locale, &old_content, &new_content, options);
+    if diff.is_empty() && new.is_some() && baseline.exists() {
+        // Только порядок ключей или пробелы: копия остаётся прежней
+        return Ok(None);
+    }
+    diff.path = Some(relative.to_string());
+    diff.fallback_lines = new.as_ref().map_or(0, |(_, decoded)| decoded.fallback_lines);
+    warn_duplicate_keys(&game_file, &diff.duplicates);
+    let out = LangOutputPaths::new(env, &lang_tree_artifact_name(relative));
+    let input = LangDiffInput {
+        game_lang: &game_file,
+        baseline: &baseline,
+        old_content,
+        new_content,
+    };
+    match &new {
+        Some((bytes, _)) => replace_baseline(&baseline, bytes, || accept_lang_change(diff, &out, input, bytes, config)),
+        None => remove_baseline(&baseline, || save_lang_diff(diff, &out, input, config)),
+    }
+}
+
+/// Перезаписывает копии всех файлов дерева локализации без построения diff
+pub fn rebaseline_lang_tree(game_path: &Path, env: &Environment) -> Result<(), LangError> {
+    let baseline_root = env.lang_dir();
+    for relative in find_lang_files(&baseline_root)? {
+        fs::remove_file(baseline_root.join(relative))?;
+    }
+    let assets_root = lang_assets_root(game_path, env.branch());
+    for relative in find_lang_files(&assets_root)? {
+        copy_game_lang_file(&assets_root.join(&relative), &baseline_root.join(&relative))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        reference_diff, reference_duplicate_keys, reference_keys_in_file_order, reference_lang_lines, LangLines,
+        TempDir,
+    };
+
+    // Артефакты локали ru внутри временной папки теста
+    fn output_paths(dir: &Path) -> LangOutputPaths {
+        LangOutputPaths {
+            diff: dir.join("changes").join("ru_changes.diff"),
+            json: dir.join("changes").join("ru_changes.json"),
+            history_dir: dir.join("changes").join("history"),
+            value_history: dir.join("environment").join("history.json"),
+            snapshot_dir: dir.join("environment").join("snapshots"),
+            name: "ru".to_string(),
+            branch: Branch::Live,
+        }
+    }
+
+    fn write_file(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn modified_keys_keep_both_values() {
+        let old = "item.name = Старое имя\nitem.desc = Описание\nitem.gone = Удалено\n";
+        let new = "item.name = Новое имя\nitem.desc = Описание\nitem.added = Добавлено\n";
+        let changes = diff_lang_content("ru", old, new).changes();
+
+        assert_eq!(
+            changes,
+            [
+                LangChange::Modified {
+                    key: "item.name".to_string(),
+                    old: Some("Старое имя".to_string()),
+                    new: Some("Новое имя".to_string()),
+                    revert: false,
+                },
+                LangChange::Removed {
+                    key: "item.gone".to_string(),
+                    value: Some("Удалено".to_string()),
+                },
+                LangChange::Added {
+                    key: "item.added".to_string(),
+                    value: Some("Добавлено".to_string()),
+                },
+            ]
+        );
+        let formatted = format_lang_changes(&changes);
+        assert!(formatted.contains("~item.name = Старое имя\n~item.name => Новое имя\n"));
+        assert_eq!(parse_lang_diff(&formatted), changes);
+
+        let unified = unified_diff("a/ru.lang", "b/ru.lang", old, new, 3);
+        assert!(unified.starts_with("--- a/ru.lang\n+++ b/ru.lang\n@@ -1,3 +1,3 @@\n"));
+        assert_eq!(parse_unified_lang_diff(&unified), changes);
+    }
+
+    const ORDER_OLD: &str = "weapon.ak.name = АК\nweapon.ak.desc = Автомат\n\
+                             weapon.old.name = Старое\nweapon.svd.name = СВД\n";
+    const ORDER_NEW: &str = "weapon.svd.name = СВД-М\nweapon.ak.name = АК-74\n\
+                             weapon.ak.desc = Автомат Калашникова\nweapon.new.name = Новое\n";
+
+    #[test]
+    fn repeated_diffs_are_identical() {
+        let first = diff_lang_content("ru", ORDER_OLD, ORDER_NEW);
+        for _ in 0..20 {
+            assert_eq!(diff_lang_content("ru", ORDER_OLD, ORDER_NEW), first);
+        }
+    }
+
+    #[test]
+    fn changes_follow_new_file_order() {
+        let changes = diff_lang_content("ru", ORDER_OLD, ORDER_NEW).changes();
+        let kept = changes.iter().filter(|change| !matches!(change, LangChange::Removed { .. }));
+        let keys: Vec<&str> = kept.map(LangChange::key).collect();
+        assert_eq!(keys, ["weapon.svd.name", "weapon.ak.name", "weapon.ak.desc", "weapon.new.name"]);
+    }
+
+    #[test]
+    fn removed_key_follows_its_old_neighbour() {
+        // weapon.old.name стоял после weapon.ak.desc, поэтому идёт сразу за ним, а не в конце или начале
+        assert_eq!(
+            format_lang_changes(&diff_lang_content("ru", ORDER_OLD, ORDER_NEW).changes()),
+            "~weapon.svd.name = СВД\n~weapon.svd.name => СВД-М\n\
+             ~weapon.ak.name = АК\n~weapon.ak.name => АК-74\n\
+             ~weapon.ak.desc = Автомат\n~weapon.ak.desc => Автомат Калашникова\n\
+             -weapon.old.name = Старое\n\
+             +weapon.new.name = Новое\n"
+        );
+    }
+
+    #[test]
+    fn removed_first_key_comes_before_everything() {
+        let changes = diff_lang_content("ru", "a = 1\nb = 2\nc = 3\n", "c = 3\nb = 20\n").changes();
+        assert_eq!(changes.iter().map(LangChange::key).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(matches!(changes[0], LangChange::Removed { .. }));
+    }
+
+    #[test]
+    fn placeholders_are_extracted_as_multiset() {
+        assert_eq!(extract_placeholders("Урон %d%% от %s, %1$s и %.2f"), ["%.2f", "%1$s", "%d", "%s"]);
+        assert_eq!(extract_placeholders("{0} убил {name}, {{не плейсхолдер}} и {не закрыт"), ["{0}", "{name}"]);
+        assert_eq!(extract_placeholders("&aЗелёный&r и §C красный & не код"), ["&a", "&r", "§c"]);
+        assert_eq!(extract_placeholders("%s и %s"), ["%s", "%s"]);
+        assert!(extract_placeholders("100% готово").is_empty());
+
+        let modified = |old: &str, new: &str| LangChange::Modified {
+            key: "item.desc".to_string(),
+            old: Some(old.to_string()),
+            new: Some(new.to_string()),
+            revert: false,
+        };
+        assert!(!modified("Урон: %d", "Повреждения: %d").placeholder_mismatch());
+        assert!(!modified("{0} и {1}", "{1} и {0}").placeholder_mismatch());
+        assert!(modified("%s и %s", "%s").placeholder_mismatch());
+        assert!(modified("&aЦена: %d", "Цена: %d").placeholder_mismatch());
+
+        let changes = vec![modified("Урон: %d", "Урон")];
+        let formatted = format_lang_changes(&changes);
+        assert_eq!(formatted, "~item.desc = Урон: %d\n~item.desc => Урон [!] placeholder mismatch\n");
+        assert_eq!(parse_lang_diff(&formatted), changes);
+    }
+
+    #[test]
+    fn json_export_keeps_stable_schema() {
+        let diff = diff_lang_content("ru", "item.name = Имя\nitem.gone = Удалено\n", "item.name = Новое имя\nitem.new = Добавлено\n");
+        let json: serde_json::Value = serde_json::from_str(&lang_changes_json(&diff).unwrap()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "kind": "modified", "key": "item.name", "old": "Имя", "new": "Новое имя" },
+                { "kind": "removed", "key": "item.gone", "old": "Удалено", "new": null },
+                { "kind": "added", "key": "item.new", "old": null, "new": "Добавлено" },
+            ])
+        );
+    }
+
+    #[test]
+    fn lang_tree_finds_only_files_in_lang_dirs() {
+        let root = TempDir::new("lang_tree");
+        for file in ["stalker/lang/ru.lang", "stalker/lang/en.lang", "global/lang/ru.lang", "stalker/items/ru.lang", "ru.lang"] {
+            write_file(&root.join(file), "key = value\n");
+        }
+        fs::write(root.join("stalker/lang/readme.txt"), "").unwrap();
+
+        let files = find_lang_files(&root);
+
+        assert_eq!(files.unwrap(), ["global/lang/ru.lang", "stalker/lang/en.lang", "stalker/lang/ru.lang"]);
+        assert_eq!(lang_tree_artifact_name("stalker/lang/ru.lang"), "stalker.lang.ru");
+    }
+
+    #[test]
+    fn compare_branches_lists_ots_exclusive_strings() {
+        let root = TempDir::new("compare_branches");
+        fs::create_dir_all(root.join("ots")).unwrap();
+        let live_path = root.join("ru.lang");
+        let ots_path = root.join("ots").join("ru.lang");
+        fs::write(&live_path, "item.ak74 = АК-74\nitem.old = Патрон\nitem.live = Только основной\n").unwrap();
+        fs::write(&ots_path, "item.ak74 = АК-74М\nitem.new = Патрон\nitem.leak = Новый артефакт\n").unwrap();
+
+        let diff = compare_branches(&live_path, &ots_path);
+
+        let diff = diff.unwrap();
+        assert_eq!(diff.locale, "ru");
+        assert!(diff.renamed.is_empty());
+        let keys = |entries: &[LangEntry]| entries.iter().map(|e| e.key.clone()).collect::<Vec<_>>();
+        assert_eq!(keys(&diff.added), ["item.new", "item.leak"]);
+        assert_eq!(keys(&diff.removed), ["item.old", "item.live"]);
+        assert_eq!(diff.modified.len(), 1);
+    }
+
+    #[test]
+    fn renamed_keys_are_paired_only_when_unique() {
+        let old = "item.ak74 = АК-74\nitem.old_a = Патрон\nitem.old_b = Патрон\nitem.gone = Удалено\n";
+        let new = "weapon.ak74 = АК-74\nitem.new_a = Патрон\nitem.new_b = Патрон\n";
+        let diff = diff_lang_content("ru", old, new);
+
+        assert_eq!(
+            diff.renamed,
+            [RenamedLangEntry {
+                old_key: "item.ak74".to_string(),
+                new_key: "weapon.ak74".to_string(),
+                value: "АК-74".to_string(),
+                position: 4,
+            }]
+        );
+        // Одинаковое значение у нескольких ключей - пары неоднозначны, остаются удаления и добавления
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(diff.removed.len(), 3);
+
+        let formatted = format_lang_changes(&diff.changes());
+        assert!(formatted.contains(">item.ak74 -> weapon.ak74 = АК-74\n"));
+        assert_eq!(parse_lang_diff(&formatted), diff.changes());
+        assert_eq!(parse_unified_lang_diff(&unified_diff("a", "b", old, new, 3)), diff.changes());
+    }
+
+    #[test]
+    fn ignored_keys_are_filtered_once() {
+        let old = "ui.news.banner = Старый баннер\nui.news.title = Новости\nui.event.timer = 1\nitem.name = Имя\n";
+        let new = "ui.news.banner = Новый баннер\nui.event.timer = 2\nitem.name = Новое имя\nui.season.timer = 3\n";
+        let ignore_keys: Vec<String> = ["ui.news.*", "ui.news.banner", "ui.*.timer"].iter().map(|s| s.to_string()).collect();
+
+        let mut diff = diff_lang_content("ru", old, new);
+        diff.remove_ignored(&ignore_keys);
+        // Баннер подходит под два шаблона, но считается одним изменением
+        assert_eq!(diff.filtered, 4);
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Modified {
+                key: "item.name".to_string(),
+                old: Some("Имя".to_string()),
+                new: Some("Новое имя".to_string()),
+                revert: false,
+            }]
+        );
+        assert!(is_ignored_key("ui.news.banner", &ignore_keys));
+        assert!(!is_ignored_key("ui.newsletter", &ignore_keys));
+
+        let unified = unified_diff(
+            "a",
+            "b",
+            &remove_ignored_lines(old, &ignore_keys),
+            &remove_ignored_lines(new, &ignore_keys),
+            3,
+        );
+        assert_eq!(parse_unified_lang_diff(&unified), diff.changes());
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn nested_ignore_patterns_filter_each_change_once() {
+        let old = "ui.news.banner = 1\nui.menu.title = Меню\nui.event.timer = 1\nitem.name = Имя\n";
+        let new = "ui.news.banner = 2\nui.menu.title = Главное меню\nui.event.timer = 2\nitem.name = Новое имя\n";
+
+        // Каждый изменённый ключ ui.* подходит под два или три шаблона
+        let mut diff = diff_lang_content("ru", old, new);
+        diff.remove_ignored(&patterns(&["ui.*", "ui.news.*", "ui.news.banner", "*.timer"]));
+        assert_eq!(diff.filtered, 3);
+        assert_eq!(diff.changes().iter().map(LangChange::key).collect::<Vec<_>>(), ["item.name"]);
+    }
+
+    #[test]
+    fn repeated_remove_ignored_with_overlapping_patterns_counts_once() {
+        let mut diff = diff_lang_content("ru", "ui.a = 1\nui.b = 1\n", "ui.a = 2\nui.b = 2\n");
+        diff.remove_ignored(&patterns(&["ui.a"]));
+        diff.remove_ignored(&patterns(&["ui.*", "ui.a"]));
+        assert_eq!(diff.filtered, 2);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn rename_is_ignored_when_either_key_matches() {
+        let old = "ui.old.title = Заголовок\nitem.old = Предмет\n";
+        let new = "item.title = Заголовок\nui.item = Предмет\n";
+
+        let mut diff = diff_lang_content("ru", old, new);
+        assert_eq!(diff.renamed.len(), 2);
+        diff.remove_ignored(&patterns(&["ui.*"]));
+        assert_eq!(diff.filtered, 2);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn ignore_keys_win_over_overlapping_key_filter() {
+        let old = "weapon.ak.name = АК\nweapon.ak.desc = Автомат\nitem.name = Имя\n";
+        let new = "weapon.ak.name = АКМ\nweapon.ak.desc = Автомат Калашникова\nitem.name = Новое имя\n";
+
+        // --filter оставляет оружие, lang.ignore_keys убирает из него описания; --filter в filtered не считается
+        let mut diff = diff_lang_content("ru", old, new);
+        diff.retain_keys(&patterns(&["weapon.*", "weapon.ak.*"]));
+        diff.remove_ignored(&patterns(&["*.desc"]));
+        assert_eq!(diff.changes().iter().map(LangChange::key).collect::<Vec<_>>(), ["weapon.ak.name"]);
+        assert_eq!(diff.filtered, 1);
+    }
+
+    #[test]
+    fn invalid_utf8_lines_fall_back_to_cp1251() {
+        let mut bytes = "item.name = Имя\n".as_bytes().to_vec();
+        // «Тест» в CP1251 и байт 0x98, которого в CP1251 нет
+        bytes.extend_from_slice(b"item.cp = \xD2\xE5\xF1\xF2\r\n");
+        bytes.extend_from_slice(b"item.bad = \x98\n");
+        let decoded = decode_lang_bytes(&bytes);
+
+        assert_eq!(decoded.fallback_lines, 2);
+        assert_eq!(decoded.content, "item.name = Имя\nitem.cp = Тест\r\nitem.bad = \u{fffd}\n");
+        assert_eq!(decode_lang_bytes("item = Имя".as_bytes()).fallback_lines, 0);
+    }
+
+    #[test]
+    fn new_keys_missing_in_other_locales_are_reported() {
+        let diffs: HashMap<String, LangDiff> = [
+            ("ru", diff_lang_content("ru", "a = А\n", "a = Б\nquest.new = Квест\nboth = Оба\n")),
+            ("en", diff_lang_content("en", "a = A\n", "a = A\nboth = Both\nen.only = Only\n")),
+            ("de", diff_lang_content("de", "a = A\n", "a = A\nboth = Beide\n")),
+        ]
+        .into_iter()
+        .map(|(locale, diff)| (locale.to_string(), diff))
+        .collect();
+
+        let gaps = cross_check_locales(&diffs);
+        assert_eq!(
+            gaps,
+            [
+                LocaleGap {
+                    key: "en.only".to_string(),
+                    added_in: vec!["en".to_string()],
+                    missing_in: vec!["de".to_string(), "ru".to_string()],
+                },
+                LocaleGap {
+                    key: "quest.new".to_string(),
+                    added_in: vec!["ru".to_string()],
+                    missing_in: vec!["de".to_string(), "en".to_string()],
+                },
+            ]
+        );
+        // Одна локаль сравнивать не с чем
+        assert!(cross_check_locales(&diffs.into_iter().filter(|(l, _)| l == "ru").collect()).is_empty());
+    }
+
+    #[test]
+    fn key_filter_keeps_matching_changes_and_renames() {
+        let mut diff = diff_lang_content(
+            "ru",
+            "item.artefact.moon = Луна\nitem.weapon.ak = АК\nold.name = Переименовано\n",
+            "item.artefact.moon = Луна 2\nitem.weapon.ak = АКМ\nitem.artefact.flash = Переименовано\n",
+        );
+        diff.retain_keys(&["item.artefact.*".to_string()]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.renamed[0].new_key, "item.artefact.flash");
+        assert_eq!(diff.total(), 2);
+        assert_eq!(diff.filtered, 0);
+
+        diff.retain_keys(&["quest.*".to_string()]);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn quiet_keys_do_not_count_towards_publish() {
+        let diff = diff_lang_content(
+            "ru",
+            "news.title = Старая\nitem.name = АК\n",
+            "news.title = Новая\nitem.name = АКМ\nnews.text = Текст\n",
+        );
+        assert_eq!(diff.publishable_count(&[]), 3);
+        assert_eq!(diff.publishable_count(&["news.*".to_string()]), 1);
+    }
+
+    #[test]
+    fn inline_comments_are_stripped_outside_quotes() {
+        assert_eq!(strip_inline_comment(" a = b  # внутренняя заметка"), "a = b");
+        assert_eq!(strip_inline_comment(" Текст // заметка"), "Текст");
+        assert_eq!(strip_inline_comment(" Выпуск #5 для C# //"), "Выпуск #5 для C#");
+        assert_eq!(strip_inline_comment(" Ссылка http://example.com"), "Ссылка http://example.com");
+        assert_eq!(strip_inline_comment(r#" "Цитата # не комментарий" # комментарий"#), r#""Цитата # не комментарий""#);
+        assert_eq!(strip_inline_comment(r#" "Кавычка \" # внутри" и всё"#), r#""Кавычка \" # внутри" и всё"#);
+        assert_eq!(strip_inline_comment(" # Заголовок"), "# Заголовок");
+
+        let old = "item.formula = урон = 10  # старая заметка\nitem.name = АК # было\n";
+        let new = "item.formula = урон = 10  # новая заметка\nitem.name = АКМ // стало\n";
+        let options = LangParseOptions {
+            strip_comments: true,
+            ..LangParseOptions::default()
+        };
+        let diff = diff_lang_content_with("ru", old, new, &options);
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Modified {
+                key: "item.name".to_string(),
+                old: Some("АК".to_string()),
+                new: Some("АКМ".to_string()),
+                revert: false,
+            }]
+        );
+        // Без настройки комментарий - часть значения
+        assert_eq!(diff_lang_content("ru", old, new).modified.len(), 2);
+    }
+
+    #[test]
+    fn diff_header_round_trips_and_is_skipped_by_parsers() {
+        let header = LangDiffHeader {
+            source: Some(PathBuf::from("/games/stalcraft/ru.lang")),
+            baseline: Some(PathBuf::from("/env/live/lang/ru.lang")),
+            generated: Some("2025-01-01T12:00:00+03:00".to_string()),
+            locale: Some("ru".to_string()),
+            branch: Some("live".to_string()),
+            entries: Some(LangEntryCounts {
+                added: 12,
+                modified: 3,
+                removed: 1,
+                renamed: 0,
+            }),
+            path: Some("stalker/lang/ru.lang".to_string()),
+            fallback_lines: 2,
+            duplicates: vec!["a".to_string(), "b".to_string()],
+        };
+        let rendered = header.render();
+        assert!(rendered.contains("# entries: +12 ~3 -1\n"));
+        assert!(rendered.ends_with("\n\n"));
+        assert_eq!(LangDiffHeader::parse(&rendered), header);
+
+        let body = "+item.name = # не заголовок\n~item.desc = Старое\n~item.desc => Новое\n";
+        let content = format!("{}{}", rendered, body);
+        assert_eq!(LangDiffHeader::parse(&content), header);
+        assert_eq!(parse_lang_diff_file(&content), parse_lang_diff(body));
+        let unified = format!("{}--- a/ru.lang\n+++ b/ru.lang\n@@ -1 +1 @@\n-a = 1\n+a = 2\n", rendered);
+        assert_eq!(parse_lang_diff_file(&unified).len(), 1);
+        // diff без заголовка
+        assert_eq!(LangDiffHeader::parse(body), LangDiffHeader::default());
+    }
+
+    #[test]
+    fn lang_keys_are_categorized_by_table() {
+        let builtin = LangCategories::default();
+        assert_eq!(builtin.category("item.wpn.ak74.name"), "Оружие");
+        assert_eq!(builtin.category("item.armor.exo.desc"), "Броня");
+        assert_eq!(builtin.category("item.art.flash.name"), "Артефакты");
+        assert_eq!(builtin.category("quest.intro.title"), "Квесты");
+        assert_eq!(builtin.category("ui.weapon.tab"), "Интерфейс");
+        assert_eq!(builtin.category("item.food.bread"), OTHER_LANG_CATEGORY);
+        assert_eq!(builtin.category("weaponry"), OTHER_LANG_CATEGORY);
+
+        let custom = LangCategories::with_overrides(&[
+            LangCategoryRule {
+                name: "Ивенты".to_string(),
+                keys: vec!["event.*".to_string(), "ui.event.*".to_string()],
+            },
+            LangCategoryRule {
+                name: "Оружие".to_string(),
+                keys: vec!["item.gun.*".to_string()],
+            },
+        ]);
+        assert_eq!(custom.category("ui.event.banner"), "Ивенты");
+        assert_eq!(custom.category("item.gun.vsk.name"), "Оружие");
+        assert_eq!(custom.category("item.wpn.ak74.name"), "Оружие");
+
+        let changes: Vec<_> = ["misc.key", "item.wpn.ak74.name", "event.title", "item.gun.vsk.name", "ui.menu"]
+            .iter()
+            .map(|key| LangChange::Added {
+                key: key.to_string(),
+                value: None,
+            })
+            .collect();
+        let groups: Vec<(&str, Vec<&str>)> = custom
+            .group(&changes)
+            .into_iter()
+            .map(|(name, changes)| (name, changes.iter().map(|c| c.key()).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                ("Ивенты", vec!["event.title"]),
+                ("Оружие", vec!["item.wpn.ak74.name", "item.gun.vsk.name"]),
+                ("Интерфейс", vec!["ui.menu"]),
+                (OTHER_LANG_CATEGORY, vec!["misc.key"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn semantic_hash_ignores_order_and_format() {
+        let old = "a = 1\nb = 2\nc = 3\n";
+        let diff = diff_lang_content("ru", old, "c = 4\nb = 2\nd = 5\n");
+        let mut reversed = diff.changes();
+        reversed.reverse();
+        let reordered = LangDiff::from_changes("ru", reversed);
+        assert_eq!(diff.semantic_hash(), reordered.semantic_hash());
+        assert_ne!(diff.semantic_hash(), diff_lang_content("ru", old, "c = 4\n").semantic_hash());
+        assert_ne!(diff.semantic_hash(), LangDiff::from_changes("en", diff.changes()).semantic_hash());
+
+        let en = diff_lang_content("en", "x = 1\n", "x = 2\n");
+        assert_eq!(
+            lang_diffs_hash(&[diff.clone(), en.clone()]),
+            lang_diffs_hash(&[en, LangDiff::default(), diff])
+        );
+
+        let dir = TempDir::new("lang_hash");
+        assert_eq!(load_last_lang_hash(&dir), None);
+        save_last_lang_hash(&dir, u64::MAX - 1).unwrap();
+        assert_eq!(load_last_lang_hash(&dir), Some(u64::MAX - 1));
+    }
+
+    #[test]
+    fn reverted_values_are_tagged() {
+        let mut history = LangValueHistory::default();
+        let mut step = |old: &str, new: &str| {
+            let mut diff = diff_lang_content("ru", &format!("item.name = {}\n", old), &format!("item.name = {}\n", new));
+            history.mark_reverts("ru", &mut diff);
+            diff
+        };
+        assert!(!step("АК", "АКМ").modified[0].revert);
+        let reverted = step("АКМ", "АК");
+        assert!(reverted.modified[0].revert);
+        // Повторная обработка того же изменения откатом не считается
+        assert!(!step("АКМ", "АК").modified[0].revert);
+        assert!(step("АК", "АКМ").modified[0].revert);
+
+        let content = format_lang_changes(&reverted.changes());
+        assert_eq!(content, "~item.name (revert) = АКМ\n~item.name => АК\n");
+        assert_eq!(parse_lang_diff(&content), reverted.changes());
+        assert!(lang_changes_json(&reverted).unwrap().contains("\"revert\": true"));
+    }
+
+    #[test]
+    fn interrupted_baseline_replacement_is_recovered() {
+        let dir = TempDir::new("lang_recover");
+        let baseline = dir.join("ru.lang");
+        let leftovers = || fs::read_dir(&dir).unwrap().count();
+        fs::write(&baseline, "a = old\n").unwrap();
+
+        // Падение до записи артефактов: подготовленная копия отбрасывается
+        stage_baseline(&baseline, b"a = new\n").unwrap();
+        assert!(!recover_lang_baseline(&baseline).unwrap());
+        assert_eq!(fs::read_to_string(&baseline).unwrap(), "a = old\n");
+        assert_eq!(leftovers(), 1);
+
+        // Падение после артефактов, но до замены: копия заменяется
+        stage_baseline(&baseline, b"a = new\n").unwrap();
+        mark_artifacts_written(&baseline, b"a = new\n").unwrap();
+        assert!(recover_lang_baseline(&baseline).unwrap());
+        assert_eq!(fs::read_to_string(&baseline).unwrap(), "a = new\n");
+        assert_eq!(leftovers(), 1);
+
+        // Ошибка записи артефактов оставляет прежнюю копию
+        let failed: Result<(), LangError> =
+            replace_baseline(&baseline, b"a = third\n", || Err(io::Error::other("диск заполнен").into()));
+        assert!(failed.is_err());
+        assert!(!recover_lang_baseline(&baseline).unwrap());
+        assert_eq!(fs::read_to_string(&baseline).unwrap(), "a = new\n");
+
+        replace_baseline(&baseline, b"a = third\n", || Ok(())).unwrap();
+        assert_eq!(fs::read_to_string(&baseline).unwrap(), "a = third\n");
+        assert_eq!(leftovers(), 1);
+    }
+
+    #[test]
+    fn interrupted_baseline_removal_is_recovered() {
+        let dir = TempDir::new("lang_recover_removal");
+        let baseline = dir.join("ru.lang");
+        let leftovers = || fs::read_dir(&dir).unwrap().count();
+        fs::write(&baseline, "a = old\n").unwrap();
+
+        // Ошибка записи артефактов оставляет копию, следующий цикл построит diff заново
+        let failed: Result<(), LangError> =
+            remove_baseline(&baseline, || Err(io::Error::other("диск заполнен").into()));
+        assert!(failed.is_err());
+        assert!(!recover_lang_baseline(&baseline).unwrap());
+        assert!(baseline.exists());
+        assert_eq!(leftovers(), 1);
+
+        // Падение после артефактов, но до удаления: копия удаляется при восстановлении
+        mark_baseline_removed(&baseline).unwrap();
+        assert!(recover_lang_baseline(&baseline).unwrap());
+        assert!(!baseline.exists());
+        assert_eq!(leftovers(), 0);
+
+        fs::write(&baseline, "a = old\n").unwrap();
+        remove_baseline(&baseline, || Ok(())).unwrap();
+        assert!(!baseline.exists());
+        assert_eq!(leftovers(), 0);
+    }
+
+    // Файл игры, копия и артефакты одной локали во временной папке
+    struct LangFixture {
+        // Папка удаляется вместе с fixture
+        _dir: TempDir,
+        game_lang: PathBuf,
+        baseline: PathBuf,
+        out: LangOutputPaths,
+        config: LangConfig,
+    }
+
+    impl LangFixture {
+        fn new(name: &str, content: &str) -> Self {
+            let dir = TempDir::new(name);
+            let fixture = LangFixture {
+                game_lang: dir.join("game").join("ru.lang"),
+                baseline: dir.join("environment").join("ru.lang"),
+                out: output_paths(&dir),
+                config: LangConfig::default(),
+                _dir: dir,
+            };
+            write_file(&fixture.game_lang, content);
+            fixture
+        }
+
+        // Первый запуск, который создаёт копию
+        fn seeded(name: &str, content: &str) -> Self {
+            let fixture = Self::new(name, content);
+            assert_eq!(fixture.run(), None);
+            fixture
+        }
+
+        fn run(&self) -> Option<LangDiff> {
+            diff_lang_files(&self.game_lang, &self.baseline, &self.out, &self.config).unwrap()
+        }
+
+        fn update(&self, content: &str) -> LangDiff {
+            fs::write(&self.game_lang, content).unwrap();
+            self.run().unwrap()
+        }
+    }
+
+    const FIXTURE_LANG: &str = "item.name = АК\nitem.desc = Автомат\n";
+
+    #[test]
+    fn first_run_only_seeds_baseline() {
+        let fixture = LangFixture::new("lang_first_run", FIXTURE_LANG);
+        assert_eq!(fixture.run(), None);
+        assert_eq!(fs::read(&fixture.baseline).unwrap(), fs::read(&fixture.game_lang).unwrap());
+        assert!(!fixture.out.diff.exists() && !fixture.out.json.exists());
+    }
+
+    #[test]
+    fn identical_file_writes_no_artifacts() {
+        let fixture = LangFixture::seeded("lang_identical", FIXTURE_LANG);
+        assert_eq!(fixture.run(), None);
+        assert!(!fixture.out.diff.exists() && !fixture.out.json.exists());
+        assert!(!fixture.out.history_dir.exists());
+    }
+
+    #[test]
+    fn added_key_is_reported() {
+        let fixture = LangFixture::seeded("lang_added", FIXTURE_LANG);
+        let diff = fixture.update("item.name = АК\nitem.desc = Автомат\nnew.key = Новый\n");
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Added {
+                key: "new.key".to_string(),
+                value: Some("Новый".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn modified_key_is_reported() {
+        let fixture = LangFixture::seeded("lang_modified", FIXTURE_LANG);
+        let diff = fixture.update("item.name = АКМ\nitem.desc = Автомат\n");
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Modified {
+                key: "item.name".to_string(),
+                old: Some("АК".to_string()),
+                new: Some("АКМ".to_string()),
+                revert: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn removed_key_is_reported() {
+        let fixture = LangFixture::seeded("lang_removed", FIXTURE_LANG);
+        let diff = fixture.update("item.name = АК\n");
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Removed {
+                key: "item.desc".to_string(),
+                value: Some("Автомат".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn renamed_key_is_reported() {
+        let fixture = LangFixture::seeded("lang_renamed", FIXTURE_LANG);
+        let diff = fixture.update("item.name = АК\nitem.description = Автомат\n");
+        assert_eq!(
+            diff.changes(),
+            [LangChange::Renamed {
+                old_key: "item.desc".to_string(),
+                new_key: "item.description".to_string(),
+                value: "Автомат".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn accepted_change_replaces_baseline() {
+        let fixture = LangFixture::seeded("lang_replaced", FIXTURE_LANG);
+        fixture.update("item.name = АКМ\n");
+        assert_eq!(fs::read(&fixture.baseline).unwrap(), fs::read(&fixture.game_lang).unwrap());
+        assert_eq!(fixture.run(), None);
+    }
+
+    #[test]
+    fn diff_artifacts_describe_the_change() {
+        let fixture = LangFixture::seeded("lang_artifacts", FIXTURE_LANG);
+        let diff = fixture.update("item.name = АКМ\nnew.key = Новый\n");
+
+        let (header, changes) = read_lang_diff(&fixture.out.diff).unwrap();
+        assert_eq!(changes, diff.changes());
+        assert_eq!(parse_lang_diff(&fs::read_to_string(&fixture.out.diff).unwrap()), diff.changes());
+        assert_eq!(header.source, Some(std::path::absolute(&fixture.game_lang).unwrap()));
+        assert_eq!(header.baseline, Some(std::path::absolute(&fixture.baseline).unwrap()));
+        assert_eq!(header.locale.as_deref(), Some("ru"));
+        assert_eq!(header.branch.as_deref(), Some("live"));
+        assert_eq!(header.entries, Some(LangEntryCounts::of(&diff)));
+        assert!(header.generated.is_some_and(|time| chrono::DateTime::parse_from_rfc3339(&time).is_ok()));
+        assert!(fixture.out.json.exists());
+        assert_eq!(list_lang_history(&fixture.out.history_dir).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn changes_are_returned_as_structured_diff() {
+        let root = TempDir::new("structured_lang_diff");
+        let game = root.join("game");
+        let env = Environment::new(&root.join("environment"), Branch::Live);
+        // Пути process_lang_file, только артефакты пишутся во временную папку, а не в changes/ рабочей папки
+        let paths = LangPaths {
+            out: output_paths(&root),
+            ..LangPaths::resolve(&game, &env, "ru")
+        };
+        let config = LangConfig::default();
+        write_file(&paths.game_lang, "item.name = АК\nitem.desc = Автомат\nold.key = Старый\n");
+        assert_eq!(diff_lang_files(&paths.game_lang, &paths.baseline, &paths.out, &config).unwrap(), None);
+
+        fs::write(&paths.game_lang, "item.name = АКМ\nitem.desc = Автомат\nnew.key = Новый\n").unwrap();
+        let diff = diff_lang_files(&paths.game_lang, &paths.baseline, &paths.out, &config).unwrap().unwrap();
+
+        assert_eq!(diff.locale, "ru");
+        assert_eq!(
+            diff.added,
+            [LangEntry {
+                key: "new.key".to_string(),
+                value: Some("Новый".to_string()),
+                position: 3,
+            }]
+        );
+        assert_eq!(
+            diff.modified,
+            [ModifiedLangEntry {
+                key: "item.name".to_string(),
+                old: Some("АК".to_string()),
+                new: Some("АКМ".to_string()),
+                revert: false,
+                position: 0,
+            }]
+        );
+        assert_eq!(
+            diff.removed,
+            [LangEntry {
+                key: "old.key".to_string(),
+                value: Some("Старый".to_string()),
+                position: 2,
+            }]
+        );
+        assert!(diff.renamed.is_empty());
+        // Файл .diff остаётся артефактом и описывает те же изменения
+        assert_eq!(read_lang_diff(&paths.out.diff).unwrap().1, diff.changes());
+    }
+
+    #[test]
+    fn accepted_versions_are_snapshotted_and_restorable() {
+        let dir = TempDir::new("lang_snapshots");
+        let paths = LangPaths {
+            game_lang: dir.join("game").join("ru.lang"),
+            baseline: dir.join("environment").join("ru.lang"),
+            out: output_paths(&dir),
+        };
+        let config = LangConfig {
+            snapshot_retention: 2,
+            ..LangConfig::default()
+        };
+        fs::create_dir_all(paths.game_lang.parent().unwrap()).unwrap();
+        let versions = ["a = 1\n", "a = 2\nb = 1\n", "a = 3\nb = 1\n", "a = 4\nb = 1\nc = 1\n"];
+        for version in versions {
+            fs::write(&paths.game_lang, version).unwrap();
+            diff_lang_files(&paths.game_lang, &paths.baseline, &paths.out, &config).unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        // Первичная копия снимком не считается, из трёх принятых версий остаются две последние
+        let snapshots = list_lang_snapshots(&paths.out.snapshot_dir, "ru").unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(fs::read_to_string(&snapshots[0].path).unwrap(), versions[2]);
+        let index = fs::read_to_string(paths.out.snapshot_dir.join(SNAPSHOT_INDEX)).unwrap();
+        let rows: Vec<Vec<&str>> = index.lines().skip(1).map(|line| line.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2][0], snapshots[1].timestamp);
+        assert_eq!(rows[2][3], "3");
+        assert!(rows[2][2].starts_with(&snapshots[1].short_hash));
+
+        let restored = restore_lang_snapshot(&paths, &snapshots[0].timestamp).unwrap();
+        assert_eq!(restored, snapshots[0]);
+        assert_eq!(fs::read_to_string(&paths.baseline).unwrap(), versions[2]);
+        assert!(matches!(restore_lang_snapshot(&paths, "1999"), Err(LangError::SnapshotNotFound(..))));
+    }
+
+    #[test]
+    fn escapes_and_color_codes_are_decoded_for_display() {
+        assert!(matches!(unescape_lang_value("Без экранирования"), Cow::Borrowed(_)));
+        assert_eq!(unescape_lang_value(r"Строка\nвторая\tтаб \\n"), "Строка\nвторая\tтаб \\n");
+        assert_eq!(unescape_lang_value(r"\u00a7cКрасный \u041fривет"), "§cКрасный Привет");
+        assert_eq!(unescape_lang_value(r"\x \u12 конец\"), r"\x \u12 конец\");
+
+        assert_eq!(
+            split_color_codes("Урон &cвысокий§l!&r обычный & ещё"),
+            [(None, "Урон "), (Some('c'), "высокий"), (Some('c'), "!"), (None, " обычный & ещё")]
+        );
+        assert_eq!(split_color_codes("&a"), []);
+    }
+
+    #[test]
+    fn unstable_reads_are_retried() {
+        let reads = |versions: Vec<&'static str>| {
+            let mut versions = versions.into_iter();
+            move || Ok(versions.next().unwrap_or("final").as_bytes().to_vec())
+        };
+        let read = |versions, attempts| read_until_stable(reads(versions), Duration::ZERO, attempts).unwrap();
+
+        assert_eq!(read(vec!["a = 1", "a = 1"], 1), Some(b"a = 1".to_vec()));
+        assert_eq!(read(vec!["a", "a = 1\nb", "a = 1\nb = 2", "a = 1\nb = 2"], 5), Some(b"a = 1\nb = 2".to_vec()));
+        // Тот же размер, но другое содержимое
+        assert_eq!(read(vec!["a = 1", "a = 2", "a = 2"], 5), Some(b"a = 2".to_vec()));
+        assert_eq!(read(vec!["a", "ab", "abc", "abcd"], 2), None);
+    }
+
+    const UNIQUE_LANG: &str = "item.name = Имя\nitem.desc = Описание\n";
+    const DUPLICATED_LANG: &str = "item.name = Старое\nitem.desc = Описание\nitem.name = Новое\nitem.desc = Описание\n";
+
+    #[test]
+    fn duplicate_keys_use_last_value() {
+        let diff = diff_lang_content("ru", UNIQUE_LANG, DUPLICATED_LANG);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].old.as_deref(), Some("Имя"));
+        assert_eq!(diff.modified[0].new.as_deref(), Some("Новое"));
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn duplicate_keys_give_the_same_diff_every_time() {
+        let first = diff_lang_content("ru", UNIQUE_LANG, DUPLICATED_LANG);
+        for _ in 0..20 {
+            assert_eq!(diff_lang_content("ru", UNIQUE_LANG, DUPLICATED_LANG), first);
+        }
+    }
+
+    #[test]
+    fn duplicate_keys_are_reported_in_file_order() {
+        let diff = diff_lang_content("ru", UNIQUE_LANG, DUPLICATED_LANG);
+        assert_eq!(diff.duplicates, vec!["item.name", "item.desc"]);
+    }
+
+    #[test]
+    fn file_with_duplicates_equals_itself() {
+        assert!(diff_lang_content("ru", DUPLICATED_LANG, DUPLICATED_LANG).is_empty());
+    }
+
+    #[test]
+    fn duplicate_keys_round_trip_through_diff_header() {
+        let duplicates = diff_lang_content("ru", UNIQUE_LANG, DUPLICATED_LANG).duplicates;
+        let header = format!("{}{}\n+a = b\n", LANG_DIFF_DUPLICATES_HEADER, duplicates.join(", "));
+        assert_eq!(LangDiffHeader::parse(&header).duplicates, duplicates);
+    }
+
+    #[test]
+    fn reorder_and_whitespace_are_not_changes() {
+        let old = "item.name = Имя\nitem.desc = Длинное описание\n";
+        let reordered = "item.desc=Длинное описание\t\n  item.name   =   Имя  \n";
+        let respaced = "item.name = Имя\nitem.desc = Длинное\t описание\n";
+        let collapse = LangParseOptions {
+            collapse_whitespace: true,
+            ..LangParseOptions::default()
+        };
+
+        assert!(diff_lang_content("ru", old, reordered).is_empty());
+        assert!(diff_lang_content_with("ru", old, reordered, &collapse).is_empty());
+        assert_eq!(diff_lang_content("ru", old, respaced).modified.len(), 1);
+        assert!(diff_lang_content_with("ru", old, respaced, &collapse).is_empty());
+        assert_eq!(canonical_value("  a \t b  ", &collapse), "a b");
+        assert!(matches!(canonical_value(" a b ", &collapse), Cow::Borrowed("a b")));
+    }
+
+    const PLAIN_LANG: &str = "item.name = Имя\nitem.desc = Описание\nitem.last = Последний\n";
+    const BOM_CRLF_LANG: &str = "\u{feff}item.name = Имя\r\nitem.desc = Описание\r\nitem.last = Последний\r\n";
+    // BOM, одиночный \r, \r\n и последняя строка без перевода строки
+    const MIXED_LANG: &str = "\u{feff}item.name = Имя\ritem.desc = Описание\r\nitem.last = Последний";
+
+    #[test]
+    fn bom_and_crlf_are_not_changes() {
+        assert!(diff_lang_content("ru", PLAIN_LANG, BOM_CRLF_LANG).is_empty());
+    }
+
+    #[test]
+    fn mixed_line_endings_are_not_changes() {
+        assert!(diff_lang_content("ru", BOM_CRLF_LANG, MIXED_LANG).is_empty());
+        assert!(diff_lang_content("ru", PLAIN_LANG, MIXED_LANG).is_empty());
+    }
+
+    #[test]
+    fn unified_diff_of_normalized_content_ignores_line_endings() {
+        let (plain, mixed) = (normalize_lang_content(PLAIN_LANG), normalize_lang_content(MIXED_LANG));
+        assert!(unified_diff("a", "b", &plain, &mixed, 3).is_empty());
+    }
+
+    #[test]
+    fn changes_are_found_through_bom_and_crlf() {
+        let changed = diff_lang_content("ru", BOM_CRLF_LANG, "item.name = Новое имя\r\nitem.desc = Описание\r\n");
+        assert_eq!(
+            changed.changes(),
+            [
+                LangChange::Modified {
+                    key: "item.name".to_string(),
+                    old: Some("Имя".to_string()),
+                    new: Some("Новое имя".to_string()),
+                    revert: false,
+                },
+                LangChange::Removed {
+                    key: "item.last".to_string(),
+                    value: Some("Последний".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel_locales_keep_order_and_isolate_errors() {
+        let root = TempDir::new("parallel_lang");
+        let game = root.join("game");
+        let env = Environment::new(&root.join("environment"), Branch::Live);
+        let locales: Vec<String> = ["ru", "de", "en"].iter().map(|l| l.to_string()).collect();
+        for locale in &locales {
+            write_file(&lang_file_path(&game, &Branch::Live, locale), "item.name = Имя\n");
+        }
+        // Вместо копии de - папка: чтение копии завершится ошибкой
+        fs::create_dir_all(env.lang_path("de")).unwrap();
+
+        let results = process_lang_files(&game, &env, &locales, &LangConfig::default());
+        let order: Vec<&str> = results.iter().map(|(locale, _)| locale.as_str()).collect();
+        assert_eq!(order, ["ru", "de", "en"]);
+        assert!(matches!(results[0].1, Ok(None)));
+        assert!(matches!(results[1].1, Err(LangError::IoError(_))));
+        assert!(matches!(results[2].1, Ok(None)));
+        assert!(env.lang_path("ru").is_file() && env.lang_path("en").is_file());
+    }
+
+    #[test]
+    fn ots_lang_is_compared_with_its_own_baseline() {
+        let root = TempDir::new("ots_lang");
+        let game = root.join("game");
+        let env_root = root.join("environment");
+        let live_env = Environment::new(&env_root, Branch::Live);
+        let ots_env = Environment::new(&env_root, Branch::Ots);
+
+        // Основной сервер уже ушёл вперёд, ОТС меняет только одну строку
+        for (path, content) in [
+            (live_env.lang_path("ru"), "shared = Основной\nlive.only = Есть только на основном\n"),
+            (ots_env.lang_path("ru"), "shared = Тест\nots.key = Старое\n"),
+            (lang_file_path(&game, &Branch::Ots, "ru"), "shared = Тест\nots.key = Новое\n"),
+        ] {
+            write_file(&path, content);
+        }
+
+        let changes = sync_lang_file(&game, &ots_env, "ru", &LangParseOptions::default());
+        let live_baseline = fs::read_to_string(live_env.lang_path("ru"));
+        let ots_baseline = fs::read_to_string(ots_env.lang_path("ru"));
+
+        let diff = changes.unwrap().unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(
+            diff.modified,
+            [ModifiedLangEntry {
+                key: "ots.key".to_string(),
+                old: Some("Старое".to_string()),
+                new: Some("Новое".to_string()),
+                revert: false,
+                position: 1,
+            }]
+        );
+        assert_eq!(live_baseline.unwrap(), "shared = Основной\nlive.only = Есть только на основном\n");
+        assert_eq!(ots_baseline.unwrap(), "shared = Тест\nots.key = Новое\n");
+    }
+
+    #[test]
+    fn locale_baselines_are_separate_copies() {
+        let root = TempDir::new("locale_baselines");
+        let game = root.join("game");
+        let env = Environment::new(&root.join("environment"), Branch::Live);
+        let options = LangParseOptions::default();
+        write_file(&lang_file_path(&game, &Branch::Live, "ru"), "item.name = Имя\n");
+        write_file(&lang_file_path(&game, &Branch::Live, "en"), "item.name = Name\n");
+
+        // Первый запуск создаёт копию каждой локали из её собственного файла
+        for locale in ["ru", "en"] {
+            assert_eq!(sync_lang_file(&game, &env, locale, &options).unwrap(), None);
+        }
+        assert_ne!(env.lang_path("ru"), env.lang_path("en"));
+        assert_eq!(fs::read_to_string(env.lang_path("ru")).unwrap(), "item.name = Имя\n");
+        assert_eq!(fs::read_to_string(env.lang_path("en")).unwrap(), "item.name = Name\n");
+
+        // Изменение en обновляет только копию en
+        fs::write(lang_file_path(&game, &Branch::Live, "en"), "item.name = New name\n").unwrap();
+        let ru = sync_lang_file(&game, &env, "ru", &options).unwrap().unwrap();
+        let en = sync_lang_file(&game, &env, "en", &options).unwrap().unwrap();
+        assert!(ru.is_empty());
+        assert_eq!(en.locale, "en");
+        assert_eq!(en.modified.len(), 1);
+        assert_eq!(fs::read_to_string(env.lang_path("ru")).unwrap(), "item.name = Имя\n");
+        assert_eq!(fs::read_to_string(env.lang_path("en")).unwrap(), "item.name = New name\n");
+    }
+
+    // Пары (старый файл, новый файл), на которых сравниваются обе реализации
+    const EQUIVALENCE_CASES: &[(&str, &str, &str)] = &[
+        (
+            "повторяющиеся ключи",
+            "item.name = Имя\nitem.desc = Описание\nitem.price = 10\n",
+            "item.name = Старое\nitem.desc = Описание\nitem.name = Новое\nitem.price = 10\nitem.price = 20\n",
+        ),
+        (
+            "переставленные ключи",
+            "a.one = 1\na.two = 2\na.three = 3\na.four = 4\n",
+            "a.four = 4\na.two = 22\na.one = 1\na.three = 3\n",
+        ),
+        (
+            "изменены только пробелы",
+            "item.name = Имя\nitem.desc = Длинное описание\nitem.note = a  b\n",
+            "  item.name=Имя\t\nitem.desc   =   Длинное описание  \n\nitem.note = a \t b\n",
+        ),
+        (
+            "порядок удалённых ключей",
+            "gone.first = 1\nkept.a = 2\ngone.middle = 3\ngone.next = 4\nkept.b = 5\ngone.last = 6\n",
+            "kept.b = 5\nnew.key = 7\nkept.a = 2\n",
+        ),
+        (
+            "переименования",
+            "ui.old.title = Заголовок\nitem.a = Одинаковое\nitem.b = Одинаковое\nitem.c = 1\n",
+            "ui.title = Заголовок\nitem.x = Одинаковое\nitem.c = 1\n",
+        ),
+        (
+            "BOM и CRLF",
+            "\u{feff}item.name = Имя\r\nitem.desc = Описание\r\n",
+            "item.name = Имя 2\ritem.extra = Ещё\n",
+        ),
+        ("пустой старый файл", "", "item.name = Имя\nitem.name = Имя\n"),
+        ("пустой новый файл", "item.name = Имя\nitem.desc = Описание\n", ""),
+    ];
+
+    #[test]
+    fn diff_matches_hashmap_reference() {
+        let collapse = LangParseOptions {
+            collapse_whitespace: true,
+            ..LangParseOptions::default()
+        };
+        for options in [LangParseOptions::default(), collapse] {
+            for (name, old, new) in EQUIVALENCE_CASES {
+                let expected = reference_diff("ru", old, new, &options);
+                let actual = diff_lang_content_with("ru", old, new, &options);
+                assert_eq!(actual, expected, "{} ({:?})", name, options);
+                let (actual, expected) = (actual.changes(), expected.changes());
+                assert_eq!(format_lang_changes(&actual), format_lang_changes(&expected), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn parsed_lang_matches_hashmap_reference() {
+        let options = LangParseOptions::default();
+        for (name, old, new) in EQUIVALENCE_CASES {
+            for content in [old, new] {
+                let content = normalize_lang_content(content);
+                let parsed = ParsedLang::parse(&content);
+                let expected = reference_lang_lines(&content, &options);
+
+                let lines: LangLines = parsed
+                    .entries
+                    .iter()
+                    .map(|e| (e.key, (e.line, e.value.map(|value| canonical_value(value, &options)))))
+                    .collect();
+                assert_eq!(lines, expected, "{}", name);
+                let order: Vec<&str> = parsed.order.iter().map(|&i| parsed.entries[i].key).collect();
+                assert_eq!(order, reference_keys_in_file_order(&expected), "{}", name);
+                assert_eq!(parsed.duplicates, reference_duplicate_keys(&content), "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Krevetka: разбор карты ассетов STALCRAFT (`stalcraft.map`), сравнение версий карты и локализации,
+//! генерация патчноутов и их публикация.
+//!
+//! Бинарник `krevetka` - тонкая обёртка над этой библиотекой; те же функции можно использовать
+//! из своих инструментов, например `krevetka::map::read_map_entries`.
+
+/// Генерация HTML патчноута по результату сравнения карт
+pub mod changelog;
+/// Подкоманды бинарника: export, compare, compare-lang, lang-diff
+pub mod commands;
+/// Компактное хранение записей карты с общими папками
+pub mod compact;
+/// Настройки из config.toml
+pub mod config;
+/// Цветной вывод сводки изменений в консоль
+pub mod console;
+/// Сравнение двух наборов записей карты
+pub mod diff;
+/// Общая ошибка приложения
+pub mod error;
+/// Публикация патчноутов в GitHub Pages
+pub mod github;
+/// Отсортированный индекс записей для поиска по пути
+pub mod index;
+/// Подписи патчноута на языке из changelog.language
+pub mod labels;
+/// Отслеживание изменений в файле локализации
+pub mod lang;
+/// Формат карты, пути к игре и копии окружения
+pub mod map;
+/// Основной цикл мониторинга: синхронизация копий окружения, построение изменений и публикация
+pub mod monitor;
+/// Построчное сравнение текстов и вывод в формате unified diff
+pub mod unified;
+/// Построение файлов карты для тестов
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+mod glob;
@@ -1,13 +1,33 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::{self, Read};
-use std::path::PathBuf;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
 use thiserror::Error;
+use xxhash_rust::xxh3::xxh3_64;
+use crate::config::{load_config_or_default, EnvironmentConfig, GameConfig, MapConfig};
+use crate::glob::glob_match_prefix;
+use crate::lang::lang_file_path;
+#[cfg(windows)]
 use winreg::{enums::HKEY_CURRENT_USER, RegKey};
 
+const DEFAULT_MAX_PATH_LEN: usize = 1024;
+// Самый длинный поддерживаемый хэш (SHA-256)
+const MAX_HASH_LEN: usize = 32;
+// Предварительное выделение памяти под записи, больше заявленному количеству не доверяем
+const PREALLOC_LIMIT: usize = 1 << 20;
+const RETRY_INITIAL_DELAY: Duration = Duration::from_millis(100);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_WAIT: Duration = Duration::from_secs(15);
+
 #[derive(Error, Debug)]
 pub enum MapError {
-    #[error("Ошибка чтения реестра: {0}")]
-    RegistryError(String),
+    #[error("Ошибка определения пути к игре: {0}")]
+    PathDiscoveryError(String),
     #[error("Ошибка ввода/вывода: {0}")]
     IoError(#[from] io::Error),
     #[error("Файл игры не найден")]
@@ -18,83 +38,2117 @@ pub enum MapError {
     InvalidFormat(String),
     #[error("Ошибка конфигурации: {0}")]
     ConfigError(String),
+    #[error("Путь длиной {len} байт превышает лимит {limit} ({}); увеличьте map.max_path_len в config.toml", describe_previous(.previous))]
+    PathTooLong { len: usize, limit: usize, previous: String },
+    #[error("Файл обрезан: заявлено {expected} записей, прочитано {got} (смещение {offset})")]
+    TruncatedFile { expected: u32, got: u32, offset: u64 },
+    #[error("Папка {} не похожа на установку STALCRAFT: нет {}", .path.display(), .missing.join(", "))]
+    NotAnInstallation { path: PathBuf, missing: Vec<String> },
+    #[error("Хэши в карте не по {expected} байт: файл целиком разбирается с хэшами по {} байт", .detected.hash_len())]
+    UnknownHashSize { expected: usize, detected: MapFormat },
+    #[error("Ошибка сериализации JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl MapError {
+    /// Ошибки содержимого файла карты, в отличие от ошибок доступа к нему
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            MapError::ParseError(_)
+                | MapError::InvalidFormat(_)
+                | MapError::PathTooLong { .. }
+                | MapError::TruncatedFile { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Branch {
+    Live,
+    Ots,
+    // Дополнительные папки runtime, которые лаунчер создаёт для ивентов и экспериментальных сборок
+    Runtime(String),
+}
+
+impl Branch {
+    pub fn from_runtime_dir(name: &str) -> Self {
+        match name {
+            "stalcraft" => Branch::Live,
+            "stalcraft_ots" => Branch::Ots,
+            _ => Branch::Runtime(name.to_string()),
+        }
+    }
+
+    /// Ветка по имени папки в environment и docs (live, ots, имя runtime)
+    pub fn from_env_dir_name(name: &str) -> Self {
+        match name {
+            "live" => Branch::Live,
+            "ots" => Branch::Ots,
+            _ => Branch::Runtime(name.to_string()),
+        }
+    }
+
+    pub fn map_file_name(&self) -> String {
+        format!("{}.map", self.runtime_dir_name())
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Branch::Live => "основной сервер",
+            Branch::Ots => "ОТС",
+            Branch::Runtime(name) => name,
+        }
+    }
+
+    /// Папка ассетов ветки внутри runtime
+    pub fn runtime_dir_name(&self) -> &str {
+        match self {
+            Branch::Live => "stalcraft",
+            Branch::Ots => "stalcraft_ots",
+            Branch::Runtime(name) => name,
+        }
+    }
+
+    /// Папка ветки внутри environment и docs
+    pub fn env_dir_name(&self) -> &str {
+        match self {
+            Branch::Live => "live",
+            Branch::Ots => "ots",
+            Branch::Runtime(name) => name,
+        }
+    }
+}
+
+/// Вариант формата карты; отличается только размер хэша в записи
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapFormat {
+    Md5,
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl MapFormat {
+    /// Порядок проверки при определении формата: сначала текущий формат игры
+    pub const ALL: [MapFormat; 3] = [MapFormat::Sha1, MapFormat::Sha256, MapFormat::Md5];
+
+    pub fn hash_len(self) -> usize {
+        match self {
+            MapFormat::Md5 => 16,
+            MapFormat::Sha1 => 20,
+            MapFormat::Sha256 => 32,
+        }
+    }
+}
+
+/// Хэш записи; длина зависит от формата карты, неиспользуемые байты всегда нулевые
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MapHash {
+    bytes: [u8; MAX_HASH_LEN],
+    len: u8,
+}
+
+impl MapHash {
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, MapError> {
+        if bytes.is_empty() || bytes.len() > MAX_HASH_LEN {
+            return Err(MapError::InvalidFormat(format!("Некорректный размер хэша: {} байт", bytes.len())));
+        }
+        let mut hash = MapHash {
+            bytes: [0; MAX_HASH_LEN],
+            len: bytes.len() as u8,
+        };
+        hash.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(hash)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl From<[u8; 20]> for MapHash {
+    fn from(bytes: [u8; 20]) -> Self {
+        let mut hash = MapHash {
+            bytes: [0; MAX_HASH_LEN],
+            len: 20,
+        };
+        hash.bytes[..20].copy_from_slice(&bytes);
+        hash
+    }
+}
+
+// В JSON хэш выгружается hex-строкой, как и в export_map_json
+impl Serialize for MapHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::LowerHex for MapHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MapHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MapReadOptions {
+    pub max_path_len: usize,
+    pub format: MapFormat,
+}
+
+impl Default for MapReadOptions {
+    fn default() -> Self {
+        MapReadOptions {
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            format: MapFormat::default(),
+        }
+    }
+}
+
+impl MapReadOptions {
+    pub fn from_config(map: &MapConfig) -> Self {
+        MapReadOptions {
+            max_path_len: map.max_path_len,
+            format: MapFormat::default(),
+        }
+    }
+
+    pub fn with_format(self, format: MapFormat) -> Self {
+        MapReadOptions { format, ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct MapEntry {
     pub path: String,
-    pub hash: Vec<u8>,
+    pub hash: MapHash,
+}
+
+// Порядок задаётся путём; хэш сравнивается только для согласованности с Eq
+impl Ord for MapEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.path.cmp(&other.path).then_with(|| self.hash.cmp(&other.hash))
+    }
+}
+
+impl PartialOrd for MapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 impl MapEntry {
-    pub fn read_from(file: &mut File) -> Result<Self, MapError> {
+    pub fn read_from<R: Read>(reader: &mut R, options: &MapReadOptions) -> Result<Self, MapError> {
+        let max_path_len = options.max_path_len;
         let mut size_buf = [0u8; 2];
-        file.read_exact(&mut size_buf)?;
+        reader.read_exact(&mut size_buf)?;
         let size = u16::from_be_bytes(size_buf);
 
-        if size == 0 || size > 1024 {
-            return Err(MapError::InvalidFormat(format!(
-                "Некорректный размер пути: {} байт",
-                size
-            )));
+        if size == 0 {
+            return Err(MapError::InvalidFormat("Некорректный размер пути: 0 байт".to_string()));
+        }
+        if size as usize > max_path_len {
+            return Err(MapError::PathTooLong {
+                len: size as usize,
+                limit: max_path_len,
+                previous: String::new(),
+            });
         }
 
         let mut path_buf = vec![0u8; size as usize];
-        file.read_exact(&mut path_buf)?;
+        reader.read_exact(&mut path_buf)?;
         let path = String::from_utf8(path_buf)
             .map_err(|e| MapError::ParseError(format!("Некорректная UTF-8 последовательность: {}", e)))?;
 
-        let mut hash = vec![0u8; 20];
-        file.read_exact(&mut hash)?;
+        let mut hash_buf = [0u8; MAX_HASH_LEN];
+        let hash_buf = &mut hash_buf[..options.format.hash_len()];
+        reader.read_exact(hash_buf)?;
+        let hash = MapHash::from_slice(hash_buf)?;
 
         Ok(MapEntry { path, hash })
     }
+
+    /// Проверки те же, что при чтении с теми же options: записанную карту read_from прочитает без ошибок
+    pub fn write_to<W: Write>(&self, writer: &mut W, options: &MapReadOptions) -> Result<(), MapError> {
+        self.check_writable(options)?;
+        writer.write_all(&(self.path.len() as u16).to_be_bytes())?;
+        writer.write_all(self.path.as_bytes())?;
+        writer.write_all(self.hash.as_bytes())?;
+        Ok(())
+    }
+
+    fn check_writable(&self, options: &MapReadOptions) -> Result<(), MapError> {
+        let size = self.path.len();
+        if size == 0 {
+            return Err(MapError::InvalidFormat("Некорректный размер пути: 0 байт".to_string()));
+        }
+        // Длина пути хранится в u16, больший лимит в options не поможет
+        let limit = options.max_path_len.min(u16::MAX as usize);
+        if size > limit {
+            return Err(MapError::PathTooLong {
+                len: size,
+                limit,
+                previous: String::new(),
+            });
+        }
+        let hash_len = options.format.hash_len();
+        if self.hash.as_bytes().len() != hash_len {
+            return Err(MapError::InvalidFormat(format!(
+                "Хэш {} байт, формат карты требует {} байт ({})",
+                self.hash.as_bytes().len(),
+                hash_len,
+                self.path
+            )));
+        }
+        Ok(())
+    }
+}
+
+const GAME_PATH_ENV: &str = "KREVETKA_GAME_PATH";
+
+/// Откуда берётся путь к игре; в тестах подменяется, чтобы не зависеть от реестра и диска
+pub trait GamePathSources {
+    fn env_var(&self, name: &str) -> Option<std::ffi::OsString>;
+    fn registry_root(&self) -> Result<PathBuf, String>;
+    fn default_locations(&self) -> Vec<PathBuf>;
+    fn is_installation(&self, path: &Path) -> bool;
+}
+
+pub struct SystemGamePathSources;
+
+impl GamePathSources for SystemGamePathSources {
+    fn env_var(&self, name: &str) -> Option<std::ffi::OsString> {
+        std::env::var_os(name)
+    }
+
+    #[cfg(windows)]
+    fn registry_root(&self) -> Result<PathBuf, String> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let exbo_key = hkcu.open_subkey("SOFTWARE\\EXBO").map_err(|e| e.to_string())?;
+        let root_path: String = exbo_key.get_value("root").map_err(|e| e.to_string())?;
+        Ok(PathBuf::from(root_path))
+    }
+
+    // Вне Windows (Proton, Wine) реестра нет
+    #[cfg(not(windows))]
+    fn registry_root(&self) -> Result<PathBuf, String> {
+        Err("реестр доступен только в Windows".to_string())
+    }
+
+    // Типичные места установки, если лаунчер ещё ни разу не запускался и не записал путь в реестр
+    fn default_locations(&self) -> Vec<PathBuf> {
+        let mut locations = Vec::new();
+        if cfg!(windows) {
+            for var in ["APPDATA", "LOCALAPPDATA"] {
+                if let Some(dir) = self.env_var(var) {
+                    locations.push(PathBuf::from(dir).join("EXBO"));
+                }
+            }
+            for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+                if let Some(dir) = self.env_var(var) {
+                    locations.push(PathBuf::from(dir).join("EXBO"));
+                }
+            }
+            locations.push(PathBuf::from("C:\\EXBO"));
+            locations.push(PathBuf::from("D:\\EXBO"));
+        } else if let Some(home) = self.env_var("HOME") {
+            locations.push(PathBuf::from(&home).join("EXBO"));
+            locations.push(PathBuf::from(&home).join("Games").join("EXBO"));
+        }
+        locations
+    }
+
+    fn is_installation(&self, path: &Path) -> bool {
+        branch_map_path(path, &Branch::Live).is_file()
+    }
+}
+
+/// Порядок поиска: game.path в config.toml, переменная окружения, реестр, стандартные папки установки.
+/// Если ничего не подошло, в ошибке перечисляется всё, что было проверено
+pub fn resolve_game_path(game: &GameConfig, sources: &impl GamePathSources) -> Result<PathBuf, MapError> {
+    if let Some(path) = &game.path {
+        return Ok(path.clone());
+    }
+    let mut tried = vec!["game.path в config.toml (не задан)".to_string()];
+
+    match sources.env_var(GAME_PATH_ENV) {
+        Some(path) if !path.is_empty() => return Ok(PathBuf::from(path)),
+        _ => tried.push(format!("переменная окружения {} (не задана)", GAME_PATH_ENV)),
+    }
+
+    match sources.registry_root() {
+        Ok(path) => return Ok(path),
+        Err(e) => tried.push(format!("HKCU\\SOFTWARE\\EXBO\\root ({})", e)),
+    }
+
+    for location in sources.default_locations() {
+        if sources.is_installation(&location) {
+            return Ok(location);
+        }
+        tried.push(format!("{} (нет runtime/stalcraft.map)", location.display()));
+    }
+
+    Err(MapError::PathDiscoveryError(format!("проверено: {}", tried.join(", "))))
+}
+
+pub fn get_game_path_with_config(game: &GameConfig) -> Result<PathBuf, MapError> {
+    resolve_game_path(game, &SystemGamePathSources)
 }
 
 pub fn get_game_path() -> Result<PathBuf, MapError> {
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let exbo_key = hkcu
-        .open_subkey("SOFTWARE\\EXBO")
-        .map_err(|e| MapError::RegistryError(e.to_string()))?;
-    let root_path: String = exbo_key
-        .get_value("root")
-        .map_err(|e| MapError::RegistryError(e.to_string()))?;
-    Ok(PathBuf::from(root_path))
+    // Без config.toml остаются остальные источники, но битый конфиг игнорировать нельзя
+    let config = load_config_or_default().map_err(|e| MapError::ConfigError(e.to_string()))?;
+    get_game_path_with_config(&config.game)
+}
+
+// Лаунчер во время обновления держит файл открытым на запись
+fn is_sharing_violation(e: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION (32) и ERROR_LOCK_VIOLATION (33)
+    e.kind() == io::ErrorKind::PermissionDenied || (cfg!(windows) && matches!(e.raw_os_error(), Some(32 | 33)))
+}
+
+pub fn retry_io<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let start = Instant::now();
+    let mut delay = RETRY_INITIAL_DELAY;
+    loop {
+        match op() {
+            Err(e) if is_sharing_violation(&e) && start.elapsed() + delay <= RETRY_MAX_WAIT => {
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(RETRY_MAX_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+pub fn open_with_retry(file_path: &Path) -> io::Result<File> {
+    retry_io(|| File::open(file_path))
+}
+
+/// Копирует во временный файл и переименовывает, чтобы копия окружения никогда не оставалась обрезанной
+pub fn copy_with_retry(from: &Path, to: &Path) -> Result<u64, MapError> {
+    let mut tmp_name = to.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = to.with_file_name(tmp_name);
+
+    let result = retry_io(|| fs::copy(from, &tmp)).and_then(|copied| {
+        retry_io(|| fs::rename(&tmp, to))?;
+        Ok(copied)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(result?)
+}
+
+/// Кэширует путь к игре и заново ищет его, только если закэшированная папка пропала
+#[derive(Default)]
+pub struct GamePathResolver {
+    cached: Option<PathBuf>,
+}
+
+impl GamePathResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Возвращает путь к игре и признак того, что он изменился с прошлого вызова
+    pub fn resolve(&mut self) -> Result<(PathBuf, bool), MapError> {
+        if let Some(path) = &self.cached {
+            if path.exists() {
+                return Ok((path.clone(), false));
+            }
+        }
+
+        let path = get_game_path()?;
+        let changed = self.cached.as_ref().is_some_and(|old| *old != path);
+        self.cached = Some(path.clone());
+        Ok((path, changed))
+    }
+}
+
+pub fn branch_map_path(game_path: &Path, branch: &Branch) -> PathBuf {
+    game_path.join("runtime").join(branch.map_file_name())
+}
+
+/// Файлы игры, изменение которых запускает сравнение: карты веток и их локализация
+pub fn watched_paths(game_path: &Path, branches: &[Branch], locales: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for branch in branches {
+        paths.push(branch_map_path(game_path, branch));
+        paths.extend(locales.iter().map(|locale| lang_file_path(game_path, branch, locale)));
+    }
+    paths
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub branch: Branch,
+    pub map_path: Option<PathBuf>,
+}
+
+/// Папки runtime, похожие на сборку игры: с деревом modassets внутри или с одноимённым .map рядом
+pub fn discover_runtimes(game_path: &Path) -> Vec<RuntimeInfo> {
+    let runtime_dir = game_path.join("runtime");
+    let Ok(dir) = fs::read_dir(&runtime_dir) else {
+        return Vec::new();
+    };
+
+    let mut runtimes: Vec<_> = dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let path = entry.path();
+            let map_path = runtime_dir.join(format!("{}.map", name));
+            let map_path = map_path.is_file().then_some(map_path);
+            if map_path.is_none() && !path.join("modassets").is_dir() {
+                return None;
+            }
+            Some(RuntimeInfo {
+                branch: Branch::from_runtime_dir(&name),
+                name,
+                path,
+                map_path,
+            })
+        })
+        .collect();
+    runtimes.sort_by(|a, b| a.name.cmp(&b.name));
+    runtimes
+}
+
+// Исполняемый файл лаунчера EXBO в корне папки игры
+const LAUNCHER_EXECUTABLES: [&str; 2] = ["ExboLauncher.exe", "launcher.exe"];
+
+/// Что найдено в папке игры при проверке установки
+#[derive(Debug, Clone)]
+pub struct InstallationInfo {
+    pub path: PathBuf,
+    pub launcher: PathBuf,
+    pub runtimes: Vec<RuntimeInfo>,
+}
+
+/// Проверяет, что папка похожа на установку игры: есть runtime, лаунчер и хотя бы одна сборка с modassets.
+/// В ошибке перечисляется всё, чего не хватает
+pub fn probe_game_installation(game_path: &Path) -> Result<InstallationInfo, MapError> {
+    if !game_path.is_dir() {
+        return Err(MapError::NotAnInstallation {
+            path: game_path.to_path_buf(),
+            missing: vec!["самой папки".to_string()],
+        });
+    }
+
+    let mut missing = Vec::new();
+    let runtime_dir = game_path.join("runtime");
+    if !runtime_dir.is_dir() {
+        missing.push(format!("папки {}", runtime_dir.display()));
+    }
+    let launcher = LAUNCHER_EXECUTABLES
+        .iter()
+        .map(|name| game_path.join(name))
+        .find(|path| path.is_file());
+    if launcher.is_none() {
+        missing.push(format!("лаунчера ({})", LAUNCHER_EXECUTABLES.join(" или ")));
+    }
+    let runtimes = discover_runtimes(game_path);
+    if runtime_dir.is_dir() && !runtimes.iter().any(|runtime| runtime.path.join("modassets").is_dir()) {
+        missing.push(format!("ни одной папки {}", runtime_dir.join("<сборка>").join("modassets").display()));
+    }
+
+    match launcher {
+        Some(launcher) if missing.is_empty() => Ok(InstallationInfo {
+            path: game_path.to_path_buf(),
+            launcher,
+            runtimes,
+        }),
+        _ => Err(MapError::NotAnInstallation {
+            path: game_path.to_path_buf(),
+            missing,
+        }),
+    }
+}
+
+pub fn get_branch_map_path(branch: &Branch) -> Result<PathBuf, MapError> {
+    Ok(branch_map_path(&get_game_path()?, branch))
 }
 
 pub fn get_stalcraft_map_path() -> Result<PathBuf, MapError> {
-    let game_path = get_game_path()?;
-    Ok(game_path.join("runtime").join("stalcraft.map"))
+    get_branch_map_path(&Branch::Live)
+}
+
+pub fn get_stalcraft_ots_map_path() -> Result<PathBuf, MapError> {
+    get_branch_map_path(&Branch::Ots)
+}
+
+const ENV_ROOT: &str = "environment";
+const ENV_MAP_FILE_NAME: &str = "stalcraft.map";
+
+/// Папка окружения: --env-dir, затем environment.dir из config.toml, иначе environment рядом с программой.
+/// Относительные пути считаются от папки с программой, чтобы запуск из другой папки не создавал вторую копию
+pub fn resolve_environment_root(cli_dir: Option<&Path>, config: &EnvironmentConfig) -> PathBuf {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let root = match (cli_dir, &config.dir) {
+        (Some(dir), _) => dir.to_path_buf(),
+        (None, Some(dir)) => match &exe_dir {
+            Some(exe_dir) if dir.is_relative() => exe_dir.join(dir),
+            _ => dir.clone(),
+        },
+        (None, None) => exe_dir.unwrap_or_default().join(ENV_ROOT),
+    };
+    std::path::absolute(&root).unwrap_or(root)
+}
+
+/// Копии файлов игры для одной ветки: <папка окружения>/live или <папка окружения>/ots
+#[derive(Debug, Clone)]
+pub struct Environment {
+    branch: Branch,
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl Environment {
+    pub fn new(root: &Path, branch: Branch) -> Self {
+        Environment {
+            root: root.to_path_buf(),
+            dir: root.join(branch.env_dir_name()),
+            branch,
+        }
+    }
+
+    pub fn branch(&self) -> &Branch {
+        &self.branch
+    }
+
+    pub fn map_path(&self) -> PathBuf {
+        self.dir.join(ENV_MAP_FILE_NAME)
+    }
+
+    /// Копии файлов локализации ветки: <локаль>.lang и, при lang.scan_tree, дерево по путям ассетов
+    pub fn lang_dir(&self) -> PathBuf {
+        self.dir.join("lang")
+    }
+
+    pub fn lang_path(&self, locale: &str) -> PathBuf {
+        self.lang_dir().join(format!("{}.lang", locale))
+    }
+
+    /// Создаёт папку ветки и копию карты, если её ещё нет
+    pub fn init(&self) -> Result<PathBuf, MapError> {
+        fs::create_dir_all(&self.dir)?;
+        self.migrate_flat_layout()?;
+
+        let env_map = self.map_path();
+        if !env_map.exists() {
+            let game_map = get_branch_map_path(&self.branch)?;
+            copy_with_retry(&game_map, &env_map)?;
+        }
+        Ok(env_map)
+    }
+
+    /// Все ветки, для которых уже есть копия карты
+    pub fn existing(root: &Path) -> Result<Vec<Environment>, MapError> {
+        if !root.exists() {
+            return Ok(Vec::new());
+        }
+        let mut environments = Vec::new();
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(String::from) else {
+                continue;
+            };
+            let branch = match name.as_str() {
+                "live" => Branch::Live,
+                "ots" => Branch::Ots,
+                "snapshots" | "lang" => continue,
+                _ => Branch::Runtime(name),
+            };
+            let environment = Environment::new(root, branch);
+            if environment.map_path().exists() {
+                environments.push(environment);
+            }
+        }
+        Ok(environments)
+    }
+
+    /// Проверяет копию карты и, если её нельзя прочитать, заменяет текущей картой игры. Возвращает true при сбросе
+    pub fn ensure_valid(&self, options: &MapReadOptions) -> Result<bool, MapError> {
+        let problem = match validate_map_file(&self.map_path(), options) {
+            Ok(report) if report.is_valid() => return Ok(false),
+            Ok(report) if report.is_readable() => {
+                eprint!("{}", report);
+                return Ok(false);
+            }
+            Ok(report) => report.to_string(),
+            Err(e) => e.to_string(),
+        };
+        self.reset_baseline(problem.trim_end())?;
+        Ok(true)
+    }
+
+    /// Откладывает текущую копию карты в snapshots как *_reset_* и берёт свежую из папки игры
+    pub fn reset_baseline(&self, reason: &str) -> Result<PathBuf, MapError> {
+        fs::create_dir_all(&self.dir)?;
+        let env_map = self.map_path();
+        if env_map.exists() {
+            let dir = snapshot_dir(&env_map);
+            fs::create_dir_all(&dir)?;
+            let archived = dir.join(format!(
+                "{}_reset_{}.map",
+                snapshot_stem(&env_map),
+                chrono::Local::now().format("%Y%m%d_%H%M%S")
+            ));
+            fs::rename(&env_map, &archived)?;
+            eprintln!("Старая копия карты сохранена: {}", archived.display());
+        }
+
+        copy_with_retry(&get_branch_map_path(&self.branch)?, &env_map)?;
+        eprintln!("==================================================");
+        eprintln!("КОПИЯ КАРТЫ ({}) СБРОШЕНА: {}", self.branch.label(), reason);
+        eprintln!("Новая копия взята из папки игры, патчноут за этот цикл не создаётся");
+        eprintln!("==================================================");
+        Ok(env_map)
+    }
+
+    // Раньше все ветки лежали прямо в environment: environment/stalcraft.map, environment/stalcraft_ots.map,
+    // environment/lang/ru.lang, environment/lang/ru_ots.lang и общая папка environment/snapshots
+    fn migrate_flat_layout(&self) -> Result<(), MapError> {
+        let root = &self.root;
+        let legacy_map = root.join(self.branch.map_file_name());
+        let env_map = self.map_path();
+        if legacy_map.exists() && !env_map.exists() {
+            fs::rename(&legacy_map, &env_map)?;
+            println!("Копия карты перенесена: {} -> {}", legacy_map.display(), env_map.display());
+        }
+
+        // Старые версии хранили копию локализации ОТС рядом с основной как ru_ots.lang
+        let legacy_lang_name = match self.branch {
+            Branch::Live => Some("ru.lang"),
+            Branch::Ots => Some("ru_ots.lang"),
+            Branch::Runtime(_) => None,
+        };
+        if let Some(legacy_lang_name) = legacy_lang_name {
+            let legacy_lang = root.join("lang").join(legacy_lang_name);
+            let env_lang = self.lang_path("ru");
+            if legacy_lang.exists() && !env_lang.exists() {
+                if let Some(lang_dir) = env_lang.parent() {
+                    fs::create_dir_all(lang_dir)?;
+                }
+                fs::rename(&legacy_lang, &env_lang)?;
+                let _ = fs::remove_dir(root.join("lang"));
+            }
+        }
+
+        let legacy_snapshots = list_snapshots(&legacy_map)?;
+        if !legacy_snapshots.is_empty() {
+            let legacy_prefix = format!("{}_", snapshot_stem(&legacy_map));
+            let new_prefix = format!("{}_", snapshot_stem(&env_map));
+            let dir = snapshot_dir(&env_map);
+            fs::create_dir_all(&dir)?;
+            for snapshot in legacy_snapshots {
+                let name = snapshot.file_name().unwrap_or_default().to_string_lossy();
+                let new_name = name.replacen(&legacy_prefix, &new_prefix, 1);
+                fs::rename(&snapshot, dir.join(new_name))?;
+            }
+            let _ = fs::remove_dir(snapshot_dir(&legacy_map));
+        }
+        Ok(())
+    }
+}
+
+fn snapshot_dir(env_map: &Path) -> PathBuf {
+    env_map.parent().unwrap_or(Path::new(".")).join("snapshots")
+}
+
+fn snapshot_stem(env_map: &Path) -> String {
+    env_map
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "stalcraft".to_string())
 }
 
-pub fn init_environment() -> Result<PathBuf, MapError> {
-    let env_dir = PathBuf::from("environment");
-    fs::create_dir_all(&env_dir)?;
+/// Сохраняет текущую копию окружения в environment/snapshots перед её перезаписью
+pub fn archive_snapshot(env_map: &Path) -> Result<PathBuf, MapError> {
+    let dir = snapshot_dir(env_map);
+    fs::create_dir_all(&dir)?;
 
-    let env_map = env_dir.join("stalcraft.map");
-    if !env_map.exists() {
-        let game_map = get_stalcraft_map_path()?;
-        fs::copy(&game_map, &env_map)?;
+    let base = format!("{}_{}", snapshot_stem(env_map), chrono::Local::now().format("%Y%m%d_%H%M%S"));
+    let mut snapshot = dir.join(format!("{}.map", base));
+    let mut counter = 1;
+    while snapshot.exists() {
+        snapshot = dir.join(format!("{}_{}.map", base, counter));
+        counter += 1;
     }
-    Ok(env_map)
+    fs::copy(env_map, &snapshot)?;
+    Ok(snapshot)
 }
 
-pub fn read_map_entries(file_path: &std::path::Path) -> Result<Vec<MapEntry>, MapError> {
-    let mut file = File::open(file_path)?;
-    let file_size = file.metadata()?.len();
-    if file_size < 4 {
-        return Err(MapError::InvalidFormat("Файл слишком мал".to_string()));
+/// Снимки от старых к новым; имена с датой сортируются так же, как время создания
+pub fn list_snapshots(env_map: &Path) -> Result<Vec<PathBuf>, MapError> {
+    let dir = snapshot_dir(env_map);
+    if !dir.exists() {
+        return Ok(Vec::new());
     }
 
-    let mut count_buf = [0u8; 4];
-    file.read_exact(&mut count_buf)?;
-    let count = u32::from_be_bytes(count_buf);
+    let prefix = format!("{}_", snapshot_stem(env_map));
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        // stalcraft_ не должен захватывать снимки stalcraft_ots_
+        let is_snapshot = name.ends_with(".map")
+            && name
+                .strip_prefix(&prefix)
+                .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+        if is_snapshot {
+            snapshots.push(path);
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots)
+}
 
-    let mut entries = Vec::with_capacity(count as usize);
-    for i in 0..count {
-        entries.push(MapEntry::read_from(&mut file).map_err(|e| {
-            MapError::InvalidFormat(format!("Ошибка чтения записи {}/{}: {}", i + 1, count, e))
-        })?);
+pub fn prune_snapshots(env_map: &Path, keep: usize) -> Result<Vec<PathBuf>, MapError> {
+    let snapshots = list_snapshots(env_map)?;
+    let excess = snapshots.len().saturating_sub(keep);
+    let removed: Vec<_> = snapshots.into_iter().take(excess).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
     }
-    Ok(entries)
-}
\ No newline at end of file
+    Ok(removed)
+}
+
+fn listing_dir(env_map: &Path) -> PathBuf {
+    env_map.parent().unwrap_or(Path::new(".")).join("listings")
+}
+
+/// Полный список записей карты: «хэш  путь» по строке на запись, отсортирован по пути, всегда с LF
+pub fn export_listing(entries: &[MapEntry], path: &Path) -> Result<(), MapError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for entry in sorted_by_path(entries) {
+        // writeln! пишет \n и на Windows
+        writeln!(writer, "{}  {}", entry.hash, entry.path)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Сохраняет список в папку listings рядом с копией карты окружения
+pub fn archive_listing(entries: &[MapEntry], env_map: &Path) -> Result<PathBuf, MapError> {
+    let dir = listing_dir(env_map);
+    fs::create_dir_all(&dir)?;
+    let listing = dir.join(format!("listing_{}.txt", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+    export_listing(entries, &listing)?;
+    Ok(listing)
+}
+
+/// Удаляет самые старые списки сверх keep; 0 - хранить все
+pub fn prune_listings(env_map: &Path, keep: usize) -> Result<Vec<PathBuf>, MapError> {
+    let dir = listing_dir(env_map);
+    if keep == 0 || !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut listings = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let is_listing = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|name| name.starts_with("listing_") && name.ends_with(".txt"));
+        if is_listing {
+            listings.push(path);
+        }
+    }
+    listings.sort();
+    let excess = listings.len().saturating_sub(keep);
+    let removed: Vec<_> = listings.into_iter().take(excess).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Ok(removed)
+}
+
+/// Хэш из карты - это SHA-1 содержимого файла ассета
+pub fn sha1_file(file_path: &Path) -> io::Result<MapHash> {
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut hasher = Sha1::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(MapHash::from(<[u8; 20]>::from(hasher.finalize())))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationStatus {
+    Match,
+    Mismatch { actual: MapHash },
+    Missing,
+    Unreadable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct VerificationResult {
+    pub path: String,
+    pub expected: MapHash,
+    pub status: VerificationStatus,
+}
+
+/// Сверяет хэши записей с файлами в папке распакованных ассетов; выборку записей задаёт вызывающий
+pub fn verify_entries(entries: &[MapEntry], assets_root: &Path) -> Vec<VerificationResult> {
+    entries
+        .par_iter()
+        .map(|entry| {
+            let hash_len = entry.hash.as_bytes().len();
+            // Сверять можно только хэши SHA-1; для карт другого формата алгоритм неизвестен
+            let actual = if hash_len == MapFormat::Sha1.hash_len() {
+                sha1_file(&assets_root.join(&entry.path))
+            } else {
+                Err(io::Error::other(format!("хэш по {} байт не SHA-1", hash_len)))
+            };
+            let status = match actual {
+                Ok(actual) if actual == entry.hash => VerificationStatus::Match,
+                Ok(actual) => VerificationStatus::Mismatch { actual },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => VerificationStatus::Missing,
+                Err(e) => VerificationStatus::Unreadable(e.to_string()),
+            };
+            VerificationResult {
+                path: entry.path.clone(),
+                expected: entry.hash,
+                status,
+            }
+        })
+        .collect()
+}
+
+pub fn content_hash(file_path: &Path) -> Result<u64, MapError> {
+    Ok(xxh3_64(&retry_io(|| fs::read(file_path))?))
+}
+
+/// Метаданные файла карты на момент последней проверки
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl WatchState {
+    pub fn of(path: &Path) -> Result<Self, MapError> {
+        let metadata = fs::metadata(path)?;
+        Ok(WatchState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+
+    /// На FAT и сетевых дисках mtime грубый (до 2 секунд), поэтому изменившаяся длина тоже считается сигналом
+    pub fn advanced_since(&self, previous: &WatchState) -> bool {
+        match (self.modified, previous.modified) {
+            (Some(now), Some(before)) => now > before || self.len != previous.len,
+            _ => true,
+        }
+    }
+}
+
+/// Длина файла не годится как основной признак изменений: замена хэша ассета её не меняет,
+/// поэтому при сдвинувшемся mtime содержимое сравнивается целиком
+pub struct MapChangeDetector {
+    env_hash: u64,
+    last_seen: Option<WatchState>,
+    pending: Option<WatchState>,
+}
+
+impl MapChangeDetector {
+    pub fn new(env_map: &Path) -> Result<Self, MapError> {
+        Ok(MapChangeDetector {
+            env_hash: content_hash(env_map)?,
+            last_seen: None,
+            pending: None,
+        })
+    }
+
+    /// Возвращает хэш нового содержимого, если карта игры отличается от копии окружения
+    pub fn check(&mut self, game_map: &Path) -> Result<Option<u64>, MapError> {
+        let state = WatchState::of(game_map)?;
+        if self.last_seen.is_some_and(|last| !state.advanced_since(&last)) {
+            return Ok(None);
+        }
+
+        let hash = content_hash(game_map)?;
+        if hash == self.env_hash {
+            self.last_seen = Some(state);
+            return Ok(None);
+        }
+        self.pending = Some(state);
+        Ok(Some(hash))
+    }
+
+    /// Вызывается после успешного обновления копии окружения
+    pub fn mark_synced(&mut self, hash: u64) {
+        self.env_hash = hash;
+        self.last_seen = self.pending.take();
+    }
+}
+
+// Считает прочитанные байты, чтобы в ошибках можно было указать смещение в файле
+struct CountingReader<R: Read> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+fn describe_previous(previous_path: &str) -> String {
+    if previous_path.is_empty() {
+        "в начале файла".to_string()
+    } else {
+        format!("после «{}»", previous_path)
+    }
+}
+
+pub struct MapEntryIter<R: Read> {
+    reader: CountingReader<R>,
+    count: u32,
+    index: u32,
+    previous_path: String,
+    options: MapReadOptions,
+}
+
+impl MapEntryIter<BufReader<File>> {
+    pub fn open(file_path: &std::path::Path, options: &MapReadOptions) -> Result<Self, MapError> {
+        let file = open_with_retry(file_path)?;
+        let file_size = file.metadata()?.len();
+        if file_size < 4 {
+            return Err(MapError::InvalidFormat("Файл слишком мал".to_string()));
+        }
+
+        // Буферизованное чтение: без него на каждую запись приходится три системных вызова
+        MapEntryIter::new(BufReader::new(file), options)
+    }
+}
+
+impl<R: Read> MapEntryIter<R> {
+    pub fn new(reader: R, options: &MapReadOptions) -> Result<Self, MapError> {
+        let mut reader = CountingReader { inner: reader, position: 0 };
+        let mut count_buf = [0u8; 4];
+        reader.read_exact(&mut count_buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => MapError::InvalidFormat("Файл слишком мал".to_string()),
+            _ => MapError::IoError(e),
+        })?;
+        let count = u32::from_be_bytes(count_buf);
+        Ok(MapEntryIter {
+            reader,
+            count,
+            index: 0,
+            previous_path: String::new(),
+            options: *options,
+        })
+    }
+
+    pub fn declared_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Сколько байт прочитано с начала файла
+    pub fn position(&self) -> u64 {
+        self.reader.position
+    }
+}
+
+impl<R: Read> Iterator for MapEntryIter<R> {
+    type Item = Result<MapEntry, MapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.count {
+            return None;
+        }
+        let i = self.index;
+        let offset = self.reader.position;
+        let result = MapEntry::read_from(&mut self.reader, &self.options).map_err(|e| match e {
+            MapError::IoError(ref io) if io.kind() == io::ErrorKind::UnexpectedEof => MapError::TruncatedFile {
+                expected: self.count,
+                got: i,
+                offset,
+            },
+            MapError::PathTooLong { len, limit, .. } => MapError::PathTooLong {
+                len,
+                limit,
+                previous: self.previous_path.clone(),
+            },
+            e => MapError::InvalidFormat(format!(
+                "Ошибка чтения записи {}/{} (смещение {}, {}): {}",
+                i + 1,
+                self.count,
+                offset,
+                describe_previous(&self.previous_path),
+                e
+            )),
+        });
+        match &result {
+            Ok(entry) => {
+                self.index = i + 1;
+                self.previous_path.clear();
+                self.previous_path.push_str(&entry.path);
+            }
+            // После ошибки поток рассинхронизирован, дальше читать нечего
+            Err(_) => self.index = self.count,
+        }
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.index) as usize;
+        (0, Some(remaining))
+    }
+}
+
+pub fn read_map_entries_from<R: Read>(reader: R, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let iter = MapEntryIter::new(reader, options)?;
+    let mut entries = Vec::with_capacity((iter.declared_count() as usize).min(PREALLOC_LIMIT));
+    for entry in iter {
+        entries.push(entry?);
+    }
+    Ok(entries)
+}
+
+pub fn read_map_entries(file_path: &std::path::Path, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    read_map_entries_with_format(file_path, options).map(|(entries, _)| entries)
+}
+
+/// Читает карту и, если хэши в ней другого размера, перечитывает её в подходящем формате
+pub fn read_map_entries_with_format(
+    file_path: &Path,
+    options: &MapReadOptions,
+) -> Result<(Vec<MapEntry>, MapFormat), MapError> {
+    match read_map_entries_strict(file_path, options) {
+        Err(MapError::UnknownHashSize { expected, detected }) => {
+            eprintln!(
+                "Внимание: хэши в {} не по {} байт, а по {}. Формат карты изменился",
+                file_path.display(),
+                expected,
+                detected.hash_len()
+            );
+            let entries = read_map_entries_strict(file_path, &options.with_format(detected))?;
+            Ok((entries, detected))
+        }
+        result => result.map(|entries| (entries, options.format)),
+    }
+}
+
+/// То же, что read_map_entries, но разбор идёт в пуле блокирующих потоков tokio и не занимает потоки рантайма
+#[cfg(feature = "async")]
+pub async fn read_map_entries_async(file_path: &Path, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let file_path = file_path.to_path_buf();
+    let options = *options;
+    tokio::task::spawn_blocking(move || read_map_entries(&file_path, &options))
+        .await
+        .map_err(|e| MapError::IoError(io::Error::other(e)))?
+}
+
+/// Читает карту только в заданном формате. Если файл не сошёлся по длине, но целиком разбирается
+/// с другим размером хэша, возвращает MapError::UnknownHashSize
+pub fn read_map_entries_strict(file_path: &Path, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let file = open_with_retry(file_path)?;
+    let file_size = file.metadata()?.len();
+    let mut iter = MapEntryIter::new(BufReader::new(file), options)?;
+    let mut entries = Vec::with_capacity((iter.declared_count() as usize).min(PREALLOC_LIMIT));
+    let result = iter.by_ref().try_for_each(|entry| entry.map(|entry| entries.push(entry)));
+    if result.is_ok() && iter.position() == file_size {
+        return Ok(entries);
+    }
+
+    let data = retry_io(|| fs::read(file_path))?;
+    check_hash_size(&data, options)?;
+    if result.is_ok() {
+        eprintln!(
+            "Внимание: {}\n  лишние байты после последней записи: {}",
+            file_path.display(),
+            file_size.saturating_sub(iter.position())
+        );
+    }
+    result.map(|()| entries)
+}
+
+/// Проверяет, что структура файла целиком сходится при заданном размере хэша: записи идут
+/// без перекрытий, пути в UTF-8, последняя запись заканчивается ровно на конце файла
+fn fits_format(data: &[u8], max_path_len: usize, format: MapFormat) -> bool {
+    let Some(count_buf) = data.get(..4) else {
+        return false;
+    };
+    let count = u32::from_be_bytes([count_buf[0], count_buf[1], count_buf[2], count_buf[3]]);
+    let mut position = 4;
+    for _ in 0..count {
+        let Some(size_buf) = data.get(position..position + 2) else {
+            return false;
+        };
+        let size = u16::from_be_bytes([size_buf[0], size_buf[1]]) as usize;
+        if size == 0 || size > max_path_len {
+            return false;
+        }
+        let Some(path) = data.get(position + 2..position + 2 + size) else {
+            return false;
+        };
+        if std::str::from_utf8(path).is_err() {
+            return false;
+        }
+        position += 2 + size + format.hash_len();
+    }
+    position == data.len()
+}
+
+/// Формат, при котором файл разбирается целиком; None, если не подходит ни один
+pub fn detect_map_format(data: &[u8], options: &MapReadOptions) -> Option<MapFormat> {
+    MapFormat::ALL
+        .into_iter()
+        .find(|&format| fits_format(data, options.max_path_len, format))
+}
+
+// Файл, который не читается в заданном формате, но целиком сходится в другом, - не повреждение, а новая версия формата
+fn check_hash_size(data: &[u8], options: &MapReadOptions) -> Result<(), MapError> {
+    match detect_map_format(data, options) {
+        Some(detected) if detected != options.format => Err(MapError::UnknownHashSize {
+            expected: options.format.hash_len(),
+            detected,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn truncated_error(expected: usize, got: usize, offset: usize) -> MapError {
+    MapError::TruncatedFile {
+        expected: expected as u32,
+        got: got as u32,
+        offset: offset as u64,
+    }
+}
+
+pub fn read_map_entries_parallel(file_path: &Path, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let data = retry_io(|| fs::read(file_path))?;
+    match parse_entries_parallel(&data, options) {
+        Err(MapError::UnknownHashSize { detected, .. }) => parse_entries_parallel(&data, &options.with_format(detected)),
+        result => result,
+    }
+}
+
+fn parse_entries_parallel(data: &[u8], options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let hash_len = options.format.hash_len();
+    if data.len() < 4 {
+        return Err(MapError::InvalidFormat("Файл слишком мал".to_string()));
+    }
+    let count = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+
+    // Границы записей находятся последовательно по префиксам длины, сам разбор идёт параллельно
+    let mut offsets = Vec::with_capacity(count.min(PREALLOC_LIMIT));
+    let mut position = 4;
+    for i in 0..count {
+        let size = match data.get(position..position + 2) {
+            Some(size_buf) => u16::from_be_bytes([size_buf[0], size_buf[1]]) as usize,
+            None => {
+                check_hash_size(data, options)?;
+                return Err(truncated_error(count, i, position));
+            }
+        };
+        if position + 2 + size + hash_len > data.len() {
+            check_hash_size(data, options)?;
+            return Err(truncated_error(count, i, position));
+        }
+        offsets.push(position);
+        position += 2 + size + hash_len;
+    }
+    if position != data.len() {
+        check_hash_size(data, options)?;
+    }
+
+    offsets
+        .par_iter()
+        .enumerate()
+        .map(|(i, &offset)| {
+            parse_entry_at(data, offset, options).map(|(entry, _)| entry).map_err(|e| {
+                let previous_path = match i.checked_sub(1) {
+                    Some(prev) => parse_entry_at(data, offsets[prev], options)
+                        .map(|(entry, _)| entry.path)
+                        .unwrap_or_default(),
+                    None => String::new(),
+                };
+                if let MapError::PathTooLong { len, limit, .. } = e {
+                    return MapError::PathTooLong {
+                        len,
+                        limit,
+                        previous: previous_path,
+                    };
+                }
+                MapError::InvalidFormat(format!(
+                    "Ошибка чтения записи {}/{} (смещение {}, {}): {}",
+                    i + 1,
+                    count,
+                    offset,
+                    describe_previous(&previous_path),
+                    e
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Делит записи на попадающие под фильтры и отброшенные; пустой include означает «всё»
+pub fn filter_entries(entries: Vec<MapEntry>, include: &[String], exclude: &[String]) -> (Vec<MapEntry>, Vec<MapEntry>) {
+    if include.is_empty() && exclude.is_empty() {
+        return (entries, Vec::new());
+    }
+    entries.into_iter().partition(|entry| {
+        let included = include.is_empty() || include.iter().any(|p| glob_match_prefix(p, &entry.path));
+        included && !exclude.iter().any(|p| glob_match_prefix(p, &entry.path))
+    })
+}
+
+pub struct LenientMapRead {
+    pub entries: Vec<MapEntry>,
+    pub errors: Vec<MapError>,
+    pub trailing_bytes: u64,
+}
+
+fn parse_entry_at(data: &[u8], position: usize, options: &MapReadOptions) -> Result<(MapEntry, usize), MapError> {
+    let mut slice = &data[position..];
+    let entry = MapEntry::read_from(&mut slice, options)?;
+    let next = position + 2 + entry.path.len() + options.format.hash_len();
+    Ok((entry, next))
+}
+
+fn is_plausible(entry: &MapEntry) -> bool {
+    !entry.path.chars().any(char::is_control)
+}
+
+fn resync(data: &[u8], from: usize, options: &MapReadOptions) -> Option<usize> {
+    // Ищем смещение, с которого подряд читаются две правдоподобные записи (или одна последняя)
+    (from..data.len()).find(|&position| match parse_entry_at(data, position, options) {
+        Ok((entry, next)) if is_plausible(&entry) => {
+            next == data.len()
+                || matches!(parse_entry_at(data, next, options), Ok((following, _)) if is_plausible(&following))
+        }
+        _ => false,
+    })
+}
+
+pub fn read_map_entries_lenient(file_path: &Path, options: &MapReadOptions) -> Result<LenientMapRead, MapError> {
+    let data = retry_io(|| fs::read(file_path))?;
+    // Восстанавливать записи имеет смысл только в том формате, в котором файл сходится
+    let options = &match detect_map_format(&data, options) {
+        Some(format) => options.with_format(format),
+        None => *options,
+    };
+    if data.len() < 4 {
+        return Err(MapError::InvalidFormat("Файл слишком мал".to_string()));
+    }
+    let count = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+
+    let mut entries = Vec::with_capacity(count.min(PREALLOC_LIMIT));
+    let mut errors = Vec::new();
+    let mut position = 4;
+    while entries.len() + errors.len() < count {
+        match parse_entry_at(&data, position, options) {
+            Ok((entry, next)) => {
+                entries.push(entry);
+                position = next;
+            }
+            // Конец файла раньше заявленного количества: всё прочитанное до этого пригодно
+            Err(MapError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                errors.push(truncated_error(count, entries.len(), position));
+                break;
+            }
+            Err(e) => {
+                errors.push(MapError::InvalidFormat(format!(
+                    "Запись {}/{} пропущена (смещение {}): {}",
+                    entries.len() + errors.len() + 1,
+                    count,
+                    position,
+                    e
+                )));
+                match resync(&data, position + 1, options) {
+                    Some(next) => position = next,
+                    None => break,
+                }
+            }
+        }
+    }
+    let trailing_bytes = if entries.len() + errors.len() >= count {
+        data.len().saturating_sub(position) as u64
+    } else {
+        0
+    };
+    Ok(LenientMapRead {
+        entries,
+        errors,
+        trailing_bytes,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct MapValidationReport {
+    pub declared_count: u32,
+    pub parsed_count: u32,
+    pub duplicate_paths: Vec<String>,
+    pub empty_paths: Vec<u32>,
+    pub invalid_utf8: Vec<u32>,
+    pub trailing_bytes: u64,
+    pub structural_error: Option<String>,
+    pub format: MapFormat,
+}
+
+impl MapValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.is_readable() && self.duplicate_paths.is_empty() && self.trailing_bytes == 0
+    }
+
+    /// Повторы путей и лишние байты в конце встречаются и в файлах игры, а вот обрезанную копию сравнивать нельзя
+    pub fn is_readable(&self) -> bool {
+        self.parsed_count == self.declared_count
+            && self.empty_paths.is_empty()
+            && self.invalid_utf8.is_empty()
+            && self.structural_error.is_none()
+    }
+}
+
+impl fmt::Display for MapValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "Файл карты корректен: {} записей", self.parsed_count);
+        }
+        writeln!(f, "Файл карты повреждён:")?;
+        if self.format != MapFormat::default() {
+            writeln!(f, "  размер хэша: {} байт", self.format.hash_len())?;
+        }
+        if self.parsed_count != self.declared_count {
+            writeln!(
+                f,
+                "  заявлено записей: {}, прочитано: {}",
+                self.declared_count, self.parsed_count
+            )?;
+        }
+        if let Some(error) = &self.structural_error {
+            writeln!(f, "  ошибка структуры: {}", error)?;
+        }
+        if !self.duplicate_paths.is_empty() {
+            writeln!(f, "  повторяющиеся пути ({}):", self.duplicate_paths.len())?;
+            for path in &self.duplicate_paths {
+                writeln!(f, "    {}", path)?;
+            }
+        }
+        if !self.empty_paths.is_empty() {
+            writeln!(f, "  записи с пустым путём: {:?}", self.empty_paths)?;
+        }
+        if !self.invalid_utf8.is_empty() {
+            writeln!(f, "  записи с некорректным UTF-8: {:?}", self.invalid_utf8)?;
+        }
+        if self.trailing_bytes > 0 {
+            writeln!(f, "  лишние байты после последней записи: {}", self.trailing_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_map_file(file_path: &Path, options: &MapReadOptions) -> Result<MapValidationReport, MapError> {
+    let report = validate_map_format(file_path, options)?;
+    if report.is_readable() && report.trailing_bytes == 0 {
+        return Ok(report);
+    }
+    // Хэши другого размера дают ложную картину повреждений: проверяем в том формате, в котором файл сходится
+    match detect_map_format(&retry_io(|| fs::read(file_path))?, options) {
+        Some(format) if format != options.format => validate_map_format(file_path, &options.with_format(format)),
+        _ => Ok(report),
+    }
+}
+
+fn validate_map_format(file_path: &Path, options: &MapReadOptions) -> Result<MapValidationReport, MapError> {
+    let hash_len = options.format.hash_len();
+    let file = open_with_retry(file_path)?;
+    let file_size = file.metadata()?.len();
+    if file_size < 4 {
+        return Err(MapError::InvalidFormat("Файл слишком мал".to_string()));
+    }
+
+    let mut reader = BufReader::new(file);
+    let mut count_buf = [0u8; 4];
+    reader.read_exact(&mut count_buf)?;
+
+    let mut report = MapValidationReport {
+        declared_count: u32::from_be_bytes(count_buf),
+        format: options.format,
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let mut position = 4u64;
+
+    // Читаем записи вручную, а не через MapEntry::read_from, чтобы не останавливаться на первой проблеме
+    for i in 1..=report.declared_count {
+        let mut size_buf = [0u8; 2];
+        if reader.read_exact(&mut size_buf).is_err() {
+            report.structural_error = Some(format!("файл закончился перед записью {} (смещение {})", i, position));
+            break;
+        }
+        let size = u16::from_be_bytes(size_buf) as usize;
+        if size > options.max_path_len {
+            report.structural_error = Some(format!(
+                "запись {}: размер пути {} байт превышает лимит {} (смещение {})",
+                i, size, options.max_path_len, position
+            ));
+            break;
+        }
+
+        let mut path_buf = vec![0u8; size];
+        let mut hash = [0u8; MAX_HASH_LEN];
+        if reader.read_exact(&mut path_buf).is_err() || reader.read_exact(&mut hash[..hash_len]).is_err() {
+            report.structural_error = Some(format!("запись {} обрезана (смещение {})", i, position));
+            break;
+        }
+        position += (2 + size + hash_len) as u64;
+        report.parsed_count += 1;
+
+        if size == 0 {
+            report.empty_paths.push(i);
+            continue;
+        }
+        match String::from_utf8(path_buf) {
+            Ok(path) => {
+                if !seen.insert(path.clone()) {
+                    report.duplicate_paths.push(path);
+                }
+            }
+            Err(_) => report.invalid_utf8.push(i),
+        }
+    }
+
+    if report.structural_error.is_none() {
+        report.trailing_bytes = file_size.saturating_sub(position);
+    }
+    Ok(report)
+}
+
+// Количество записей для заголовка; ошибка указывает на первую запись, которую read_map_entries не прочитает
+fn check_writable_entries(entries: &[MapEntry], options: &MapReadOptions) -> Result<u32, MapError> {
+    let count = u32::try_from(entries.len())
+        .map_err(|_| MapError::InvalidFormat(format!("Слишком много записей: {}", entries.len())))?;
+    for (i, entry) in entries.iter().enumerate() {
+        entry.check_writable(options).map_err(|e| match e {
+            MapError::PathTooLong { len, limit, .. } => MapError::PathTooLong {
+                len,
+                limit,
+                previous: i.checked_sub(1).map(|p| entries[p].path.clone()).unwrap_or_default(),
+            },
+            e => e,
+        })?;
+    }
+    Ok(count)
+}
+
+pub fn write_map_entries_to<W: Write>(
+    mut writer: W,
+    entries: &[MapEntry],
+    options: &MapReadOptions,
+) -> Result<(), MapError> {
+    let count = check_writable_entries(entries, options)?;
+    writer.write_all(&count.to_be_bytes())?;
+    for entry in entries {
+        entry.write_to(&mut writer, options)?;
+    }
+    Ok(())
+}
+
+/// Записи проверяются до создания файла: при ошибке на диске не остаётся наполовину записанной карты
+pub fn write_map_entries(file_path: &Path, entries: &[MapEntry], options: &MapReadOptions) -> Result<(), MapError> {
+    check_writable_entries(entries, options)?;
+    let mut writer = BufWriter::new(File::create(file_path)?);
+    write_map_entries_to(&mut writer, entries, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct MapEntryJson<'a> {
+    path: &'a str,
+    hash: String,
+}
+
+fn sorted_by_path(entries: &[MapEntry]) -> Vec<&MapEntry> {
+    // Сортировка по пути, чтобы повторные выгрузки можно было сравнивать диффом
+    let mut sorted: Vec<_> = entries.iter().collect();
+    sorted.sort();
+    sorted
+}
+
+pub fn export_map_json(entries: &[MapEntry], path: &Path) -> Result<(), MapError> {
+    let json: Vec<_> = sorted_by_path(entries)
+        .into_iter()
+        .map(|e| MapEntryJson { path: &e.path, hash: e.hash.to_string() })
+        .collect();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, &json)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+pub fn export_map_csv(entries: &[MapEntry], path: &Path) -> Result<(), MapError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(b"path,hash\n")?;
+    for entry in sorted_by_path(entries) {
+        writeln!(writer, "{},{}", csv_field(&entry.path), entry.hash)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MapFileBuilder, TempDir};
+    use std::io::Cursor;
+
+    fn read_bytes(builder: &MapFileBuilder) -> Result<Vec<MapEntry>, MapError> {
+        read_map_entries_from(Cursor::new(builder.build_bytes()), &MapReadOptions::default())
+    }
+
+    fn entry(path: &str, byte: u8) -> MapEntry {
+        MapEntry {
+            path: path.to_string(),
+            hash: MapHash::from([byte; 20]),
+        }
+    }
+
+    fn read_back(data: &[u8], options: &MapReadOptions) -> Vec<MapEntry> {
+        read_map_entries_from(Cursor::new(data), options).unwrap()
+    }
+
+    #[test]
+    fn reads_zero_entries() {
+        let entries = read_bytes(&MapFileBuilder::new()).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn reads_entries_from_file() {
+        let dir = TempDir::new("read_map");
+        let path = dir.join("stalcraft.map");
+        MapFileBuilder::new()
+            .entry("assets/foo.png", [1u8; 20])
+            .entry("assets/bar/baz.ogg", [2u8; 20])
+            .write_to(&path)
+            .unwrap();
+        let entries = read_map_entries(&path, &MapReadOptions::default());
+
+        let entries = entries.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "assets/foo.png");
+        assert_eq!(entries[0].hash, MapHash::from([1u8; 20]));
+        assert_eq!(entries[1].path, "assets/bar/baz.ogg");
+        assert_eq!(entries[1].hash, MapHash::from([2u8; 20]));
+    }
+
+    #[test]
+    fn reads_synthetic_100k_map() {
+        let data = MapFileBuilder::synthetic(100_000).build_bytes();
+        let options = MapReadOptions::default();
+        let entries = read_map_entries_from(Cursor::new(&data), &options).unwrap();
+
+        assert_eq!(entries.len(), 100_000);
+        assert_eq!(entries[0].path, "assets/stalker/dir_0/sub_0/file_0.png");
+        assert_eq!(entries[99_999].path, "assets/stalker/dir_89/sub_3/file_99999.png");
+        assert_ne!(entries[1].hash, entries[2].hash);
+        assert_eq!(parse_entries_parallel(&data, &options).unwrap(), entries);
+    }
+
+    #[test]
+    fn written_map_reads_back() {
+        let options = MapReadOptions::default();
+        let cases = [
+            vec![],
+            vec![entry("assets/a.png", 1), entry("assets/карта мира.png", 2)],
+            vec![entry(&"a".repeat(DEFAULT_MAX_PATH_LEN), 3)],
+        ];
+        for entries in cases {
+            let mut written = Vec::new();
+            write_map_entries_to(&mut written, &entries, &options).unwrap();
+            assert_eq!(read_back(&written, &options), entries);
+        }
+        for (format, byte) in [(MapFormat::Md5, 4u8), (MapFormat::Sha256, 5)] {
+            let options = options.with_format(format);
+            let entries = [MapEntry {
+                path: "assets/a.png".to_string(),
+                hash: MapHash::from_slice(&vec![byte; format.hash_len()]).unwrap(),
+            }];
+            let mut written = Vec::new();
+            write_map_entries_to(&mut written, &entries, &options).unwrap();
+            assert_eq!(read_back(&written, &options), entries);
+        }
+
+        let dir = TempDir::new("write_map");
+        let path = dir.join("stalcraft.map");
+        let entries: Vec<_> = (0..100).map(|i| entry(&format!("assets/{}.png", i), i as u8)).collect();
+        write_map_entries(&path, &entries, &options).unwrap();
+        assert_eq!(read_map_entries(&path, &options).unwrap(), entries);
+    }
+
+    #[test]
+    fn writer_rejects_paths_the_reader_would_reject() {
+        let long = "a".repeat(DEFAULT_MAX_PATH_LEN + 1);
+        let entries = [entry("assets/ok.png", 1), entry(&long, 0)];
+        let mut written = Vec::new();
+        match write_map_entries_to(&mut written, &entries, &MapReadOptions::default()) {
+            Err(MapError::PathTooLong { len, limit, previous }) => {
+                assert_eq!((len, limit), (DEFAULT_MAX_PATH_LEN + 1, DEFAULT_MAX_PATH_LEN));
+                assert_eq!(previous, "assets/ok.png");
+            }
+            other => panic!("ожидалась ошибка PathTooLong, получено {:?}", other),
+        }
+        assert!(written.is_empty());
+
+        // С поднятым лимитом тот же путь записывается и читается обратно
+        let raised = MapReadOptions {
+            max_path_len: 4096,
+            ..MapReadOptions::default()
+        };
+        write_map_entries_to(&mut written, &entries, &raised).unwrap();
+        assert_eq!(read_back(&written, &raised), entries);
+
+        let huge = MapReadOptions {
+            max_path_len: usize::MAX,
+            ..MapReadOptions::default()
+        };
+        let over_u16 = entry(&"a".repeat(u16::MAX as usize + 1), 0);
+        assert!(matches!(
+            over_u16.write_to(&mut Vec::new(), &huge),
+            Err(MapError::PathTooLong { limit, .. }) if limit == u16::MAX as usize
+        ));
+        assert!(matches!(entry("", 0).write_to(&mut Vec::new(), &huge), Err(MapError::InvalidFormat(_))));
+
+        let dir = TempDir::new("write_rejected");
+        let path = dir.join("stalcraft.map");
+        assert!(write_map_entries(&path, &entries, &MapReadOptions::default()).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn writer_rejects_hashes_of_another_width() {
+        let entries = [entry("assets/a.png", 1)];
+        let sha256 = MapReadOptions::default().with_format(MapFormat::Sha256);
+        let mut written = Vec::new();
+        let result = write_map_entries_to(&mut written, &entries, &sha256);
+        assert!(matches!(result, Err(MapError::InvalidFormat(msg)) if msg.contains("20 байт") && msg.contains("32")));
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn accepts_path_of_max_length() {
+        let path = "a".repeat(DEFAULT_MAX_PATH_LEN);
+        let entries = read_bytes(&MapFileBuilder::new().entry(&path, [0u8; 20])).unwrap();
+        assert_eq!(entries[0].path.len(), DEFAULT_MAX_PATH_LEN);
+    }
+
+    #[test]
+    fn rejects_path_over_max_length() {
+        let path = "a".repeat(DEFAULT_MAX_PATH_LEN + 1);
+        let builder = MapFileBuilder::new().entry("assets/ok.png", [0u8; 20]).entry(&path, [0u8; 20]);
+        match read_bytes(&builder) {
+            Err(MapError::PathTooLong { len, limit, previous }) => {
+                assert_eq!((len, limit), (DEFAULT_MAX_PATH_LEN + 1, DEFAULT_MAX_PATH_LEN));
+                assert_eq!(previous, "assets/ok.png");
+            }
+            other => panic!("ожидалась ошибка PathTooLong, получено {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepts_long_path_with_raised_limit() {
+        let path = "a".repeat(4096);
+        let builder = MapFileBuilder::new().entry(&path, [0u8; 20]);
+        let options = MapReadOptions {
+            max_path_len: 4096,
+            ..MapReadOptions::default()
+        };
+        let entries = read_map_entries_from(Cursor::new(builder.build_bytes()), &options).unwrap();
+        assert_eq!(entries[0].path, path);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let builder = MapFileBuilder::new()
+            .entry("assets/ok.png", [0u8; 20])
+            .raw_entry(&[0x61, 0xff, 0xfe], [0u8; 20]);
+        let result = read_bytes(&builder);
+        assert!(matches!(result, Err(MapError::InvalidFormat(msg)) if msg.contains("UTF-8")));
+    }
+
+    #[test]
+    fn rejects_corrupted_size() {
+        let builder = MapFileBuilder::new()
+            .entry("assets/a.png", [0u8; 20])
+            .entry("assets/b.png", [0u8; 20])
+            .corrupt_entry_at(1);
+        assert!(matches!(read_bytes(&builder), Err(MapError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn reports_truncation() {
+        let builder = MapFileBuilder::new()
+            .entry("assets/a.png", [0u8; 20])
+            .entry("assets/b.png", [0u8; 20])
+            .entry("assets/c.png", [0u8; 20])
+            .truncate_after(1);
+        match read_bytes(&builder) {
+            Err(MapError::TruncatedFile { expected, got, offset }) => {
+                assert_eq!((expected, got), (3, 1));
+                assert_eq!(offset, 4 + 2 + 12 + 20);
+            }
+            other => panic!("ожидалась ошибка TruncatedFile, получено {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lenient_read_reports_trailing_bytes() {
+        let dir = TempDir::new("trailing");
+        let path = dir.join("stalcraft.map");
+        MapFileBuilder::new()
+            .entry("assets/a.png", [0u8; 20])
+            .entry("assets/b.png", [0u8; 20])
+            .declared_count(1)
+            .trailing_bytes(&[0u8; 3])
+            .write_to(&path)
+            .unwrap();
+        let result = read_map_entries_lenient(&path, &MapReadOptions::default());
+
+        let result = result.unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trailing_bytes, (2 + 12 + 20 + 3) as u64);
+    }
+
+    #[test]
+    fn rejects_file_without_header() {
+        let result = read_map_entries_from(Cursor::new(vec![0u8; 2]), &MapReadOptions::default());
+        assert!(matches!(result, Err(MapError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn verifies_entries_against_extracted_files() {
+        let root = TempDir::new("assets");
+        fs::create_dir_all(root.join("assets")).unwrap();
+        fs::write(root.join("assets/a.txt"), b"abc").unwrap();
+        fs::write(root.join("assets/b.txt"), b"abd").unwrap();
+        let abc = sha1_file(&root.join("assets/a.txt")).unwrap();
+        let entries = vec![
+            MapEntry { path: "assets/a.txt".to_string(), hash: abc },
+            MapEntry { path: "assets/b.txt".to_string(), hash: abc },
+            MapEntry { path: "assets/c.txt".to_string(), hash: abc },
+        ];
+        let results = verify_entries(&entries, &root);
+
+        assert_eq!(abc.to_string(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(results[0].status, VerificationStatus::Match);
+        assert!(matches!(results[1].status, VerificationStatus::Mismatch { .. }));
+        assert_eq!(results[2].status, VerificationStatus::Missing);
+    }
+
+    struct MockSources {
+        env: Option<&'static str>,
+        registry: Option<&'static str>,
+        defaults: Vec<&'static str>,
+        installed: Option<&'static str>,
+    }
+
+    impl GamePathSources for MockSources {
+        fn env_var(&self, _name: &str) -> Option<std::ffi::OsString> {
+            self.env.map(Into::into)
+        }
+
+        fn registry_root(&self) -> Result<PathBuf, String> {
+            self.registry.map(PathBuf::from).ok_or_else(|| "ключ не найден".to_string())
+        }
+
+        fn default_locations(&self) -> Vec<PathBuf> {
+            self.defaults.iter().map(PathBuf::from).collect()
+        }
+
+        fn is_installation(&self, path: &Path) -> bool {
+            self.installed.is_some_and(|installed| path == Path::new(installed))
+        }
+    }
+
+    fn empty_sources() -> MockSources {
+        MockSources {
+            env: None,
+            registry: None,
+            defaults: vec!["/games/a/EXBO", "/games/b/EXBO"],
+            installed: None,
+        }
+    }
+
+    #[test]
+    fn game_path_prefers_config_then_env_then_registry() {
+        let configured = GameConfig {
+            path: Some(PathBuf::from("/config/EXBO")),
+        };
+        let all = MockSources {
+            env: Some("/env/EXBO"),
+            registry: Some("/registry/EXBO"),
+            ..empty_sources()
+        };
+        assert_eq!(resolve_game_path(&configured, &all).unwrap(), PathBuf::from("/config/EXBO"));
+        assert_eq!(resolve_game_path(&GameConfig::default(), &all).unwrap(), PathBuf::from("/env/EXBO"));
+
+        let registry_only = MockSources {
+            registry: Some("/registry/EXBO"),
+            ..empty_sources()
+        };
+        assert_eq!(
+            resolve_game_path(&GameConfig::default(), &registry_only).unwrap(),
+            PathBuf::from("/registry/EXBO")
+        );
+    }
+
+    #[test]
+    fn game_path_falls_back_to_default_locations() {
+        let sources = MockSources {
+            installed: Some("/games/b/EXBO"),
+            ..empty_sources()
+        };
+        assert_eq!(resolve_game_path(&GameConfig::default(), &sources).unwrap(), PathBuf::from("/games/b/EXBO"));
+    }
+
+    #[test]
+    fn game_path_error_lists_every_location() {
+        let error = resolve_game_path(&GameConfig::default(), &empty_sources()).unwrap_err().to_string();
+        for expected in ["game.path", GAME_PATH_ENV, "HKCU", "/games/a/EXBO", "/games/b/EXBO"] {
+            assert!(error.contains(expected), "{} не упомянут в {}", expected, error);
+        }
+    }
+
+    #[test]
+    fn detects_wider_hashes() {
+        let dir = TempDir::new("sha256");
+        let path = dir.join("stalcraft.map");
+        MapFileBuilder::new()
+            .entry("assets/a.png", [1u8; 32])
+            .entry("assets/b.png", [2u8; 32])
+            .write_to(&path)
+            .unwrap();
+        let options = MapReadOptions::default();
+        let strict = read_map_entries_strict(&path, &options);
+        let detected = read_map_entries_with_format(&path, &options);
+        let parallel = read_map_entries_parallel(&path, &options);
+        let report = validate_map_file(&path, &options);
+
+        assert!(matches!(
+            strict,
+            Err(MapError::UnknownHashSize {
+                expected: 20,
+                detected: MapFormat::Sha256
+            })
+        ));
+        let (entries, format) = detected.unwrap();
+        assert_eq!(format, MapFormat::Sha256);
+        assert_eq!(entries[1].path, "assets/b.png");
+        assert_eq!(entries[1].hash.as_bytes(), [2u8; 32]);
+        assert_eq!(parallel.unwrap(), entries);
+        assert!(report.unwrap().is_valid());
+    }
+
+    #[test]
+    fn keeps_sha1_when_file_has_trailing_bytes() {
+        let data = MapFileBuilder::new()
+            .entry("assets/a.png", [1u8; 20])
+            .trailing_bytes(&[0u8; 5])
+            .build_bytes();
+        assert_eq!(detect_map_format(&data, &MapReadOptions::default()), None);
+        let data = MapFileBuilder::new().entry("assets/a.png", [1u8; 16]).build_bytes();
+        assert_eq!(detect_map_format(&data, &MapReadOptions::default()), Some(MapFormat::Md5));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn reads_entries_async() {
+        let dir = TempDir::new("async");
+        let path = dir.join("stalcraft.map");
+        MapFileBuilder::new().entry("assets/a.png", [1u8; 20]).write_to(&path).unwrap();
+        let entries = read_map_entries_async(&path, &MapReadOptions::default()).await;
+        let missing = read_map_entries_async(&path.with_extension("missing"), &MapReadOptions::default()).await;
+
+        assert_eq!(entries.unwrap(), read_bytes(&MapFileBuilder::new().entry("assets/a.png", [1u8; 20])).unwrap());
+        assert!(matches!(missing, Err(MapError::IoError(_))));
+    }
+
+    #[test]
+    fn probe_reports_missing_parts() {
+        let root = TempDir::new("probe");
+        fs::create_dir_all(root.join("runtime")).unwrap();
+        let incomplete = probe_game_installation(&root).unwrap_err().to_string();
+
+        fs::create_dir_all(root.join("runtime/stalcraft/modassets")).unwrap();
+        fs::write(root.join("ExboLauncher.exe"), b"").unwrap();
+        let complete = probe_game_installation(&root);
+
+        assert!(incomplete.contains("лаунчера"), "{}", incomplete);
+        assert!(incomplete.contains("modassets"), "{}", incomplete);
+        let info = complete.unwrap();
+        assert_eq!(info.runtimes.len(), 1);
+        assert_eq!(info.runtimes[0].branch, Branch::Live);
+    }
+
+    #[test]
+    fn json_export_is_sorted_with_hex_hashes() {
+        let dir = TempDir::new("export_json");
+        let path = dir.join("map.json");
+        let entries = [
+            MapEntry {
+                path: "assets/b.png".to_string(),
+                hash: MapHash::from([0xab; 20]),
+            },
+            MapEntry {
+                path: "assets/a \"кв\".png".to_string(),
+                hash: MapHash::from_slice(&[0x00, 0x0f, 0xf0, 0xff]).unwrap(),
+            },
+        ];
+        export_map_json(&entries, &path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+
+        let expected = format!(
+            "[\n  {{\n    \"path\": \"assets/a \\\"кв\\\".png\",\n    \"hash\": \"000ff0ff\"\n  }},\n  \
+             {{\n    \"path\": \"assets/b.png\",\n    \"hash\": \"{}\"\n  }}\n]\n",
+            "ab".repeat(20)
+        );
+        assert_eq!(json, expected);
+        let parsed: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[1], serde_json::json!({ "path": "assets/b.png", "hash": "ab".repeat(20) }));
+        assert_eq!(parsed[0].as_object().unwrap().len(), 2);
+
+        export_map_json(&[], &path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn csv_quotes_only_fields_that_need_it() {
+        assert_eq!(csv_field("assets/a.png"), "assets/a.png");
+        assert_eq!(csv_field("assets/my file.png"), "assets/my file.png");
+        assert_eq!(csv_field("assets/карта мира.png"), "assets/карта мира.png");
+        assert_eq!(csv_field("assets/a,b.png"), "\"assets/a,b.png\"");
+        assert_eq!(csv_field("assets/\"a\".png"), "\"assets/\"\"a\"\".png\"");
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+        assert!(matches!(csv_field("assets/карта мира.png"), std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn csv_export_escapes_tricky_paths() {
+        let dir = TempDir::new("export_csv");
+        let path = dir.join("map.csv");
+        let entries: Vec<MapEntry> = ["assets/z, \"кавычки\".png", "assets/с пробелом.ogg", "assets/a,b.png"]
+            .iter()
+            .enumerate()
+            .map(|(i, path)| MapEntry {
+                path: path.to_string(),
+                hash: MapHash::from([i as u8; 20]),
+            })
+            .collect();
+        export_map_csv(&entries, &path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+
+        // Сортировка побайтовая: кириллица идёт после латиницы
+        let expected = format!(
+            "path,hash\n\"assets/a,b.png\",{}\n\"assets/z, \"\"кавычки\"\".png\",{}\nassets/с пробелом.ogg,{}\n",
+            "02".repeat(20),
+            "00".repeat(20),
+            "01".repeat(20)
+        );
+        assert_eq!(csv, expected);
+    }
+
+    // Записи карты с заданными путями и хэшем по номеру
+    fn entries_with_paths(paths: &[&str]) -> Vec<MapEntry> {
+        paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| MapEntry {
+                path: path.to_string(),
+                hash: MapHash::from([i as u8; 20]),
+            })
+            .collect()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn paths(entries: &[MapEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[test]
+    fn overlapping_includes_keep_entry_once() {
+        let entries = entries_with_paths(&["assets/stalker/items/ak.png", "assets/stalker/ui/menu.png", "sound/a.ogg"]);
+        let include = patterns(&["assets/stalker/", "assets/stalker/items/", "*/items/*.png"]);
+
+        let (kept, filtered) = filter_entries(entries, &include, &[]);
+        assert_eq!(paths(&kept), ["assets/stalker/items/ak.png", "assets/stalker/ui/menu.png"]);
+        assert_eq!(paths(&filtered), ["sound/a.ogg"]);
+    }
+
+    #[test]
+    fn exclude_wins_over_overlapping_include() {
+        let entries = entries_with_paths(&[
+            "assets/stalker/items/ak.png",
+            "assets/stalker/items/weapons/svd.png",
+            "assets/stalker/ui/menu.png",
+        ]);
+        let include = patterns(&["assets/stalker/items/"]);
+        let exclude = patterns(&["assets/stalker/items/weapons/"]);
+
+        let (kept, filtered) = filter_entries(entries, &include, &exclude);
+        assert_eq!(paths(&kept), ["assets/stalker/items/ak.png"]);
+        assert_eq!(paths(&filtered), ["assets/stalker/items/weapons/svd.png", "assets/stalker/ui/menu.png"]);
+    }
+
+    #[test]
+    fn exclude_equal_to_include_filters_everything() {
+        let entries = entries_with_paths(&["assets/stalker/items/ak.png", "assets/stalker/ui/menu.png"]);
+        let both = patterns(&["assets/stalker/"]);
+
+        let (kept, filtered) = filter_entries(entries, &both, &both);
+        assert!(kept.is_empty());
+        assert_eq!(filtered.len(), 2);
+    }
+
+    #[test]
+    fn overlapping_excludes_filter_entry_once() {
+        let entries = entries_with_paths(&["assets/stalker/items/ak.png", "assets/stalker/ui/menu.png"]);
+        let exclude = patterns(&["assets/", "assets/stalker/items/", "*.png"]);
+
+        let (kept, filtered) = filter_entries(entries, &[], &exclude);
+        assert!(kept.is_empty());
+        assert_eq!(paths(&filtered), ["assets/stalker/items/ak.png", "assets/stalker/ui/menu.png"]);
+    }
+}
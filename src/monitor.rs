@@ -0,0 +1,864 @@
+use std::collections::{hash_map, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::changelog::{
+    generate_branch_comparison, generate_changelog, lang_changes_since_publish, mark_published, ChangelogError,
+    LangRenderOptions,
+};
+use crate::compact::CompactMap;
+use crate::config::{ChangelogConfig, Config, LangConfig, MapConfig, MonitorConfig, WatchMode};
+use crate::console::{colors_enabled, lang_summary};
+use crate::diff::{diff_owned_entries, MapDiff};
+use crate::error::AppError;
+use crate::github::publish_html;
+use crate::lang::{
+    compare_game_branches, lang_diffs_hash, lang_file_path, lang_history_dir, load_last_lang_hash,
+    migrate_legacy_lang_history, process_lang_files, process_lang_tree, rebaseline_lang_file, rebaseline_lang_tree,
+    save_last_lang_hash, LangDiff,
+};
+use crate::map::{
+    archive_listing, archive_snapshot, branch_map_path, copy_with_retry, discover_runtimes, filter_entries,
+    get_game_path, probe_game_installation, prune_listings, prune_snapshots, read_map_entries, read_map_entries_lenient,
+    read_map_entries_parallel, resolve_environment_root, verify_entries, watched_paths, Branch, Environment,
+    GamePathResolver, MapChangeDetector, MapEntry, MapError, MapReadOptions, VerificationStatus,
+};
+
+pub fn read_entries_or_recover(path: &Path, parallel: bool, options: &MapReadOptions) -> Result<Vec<MapEntry>, MapError> {
+    let result = if parallel {
+        read_map_entries_parallel(path, options)
+    } else {
+        read_map_entries(path, options)
+    };
+    match result {
+        Ok(entries) => Ok(entries),
+        Err(e) => {
+            eprintln!("Ошибка чтения {}: {}. Пробуем восстановить записи...", path.display(), e);
+            let recovered = read_map_entries_lenient(path, options)?;
+            for error in &recovered.errors {
+                eprintln!("  {}", error);
+            }
+            if recovered.trailing_bytes > 0 {
+                eprintln!("  лишние байты после последней записи: {}", recovered.trailing_bytes);
+            }
+            println!(
+                "Восстановлено {} записей, пропущено {}",
+                recovered.entries.len(),
+                recovered.errors.len()
+            );
+            Ok(recovered.entries)
+        }
+    }
+}
+
+/// Копия карты окружения вместе с уже разобранными записями: после копирования файл не перечитывается
+pub struct MapWatch {
+    detector: MapChangeDetector,
+    // Между изменениями хранится в сжатом виде: копия живёт всё время работы
+    baseline: CompactMap,
+}
+
+impl MapWatch {
+    pub fn new(env_map: &Path, options: &MapReadOptions) -> Result<Self, MapError> {
+        Ok(MapWatch {
+            detector: MapChangeDetector::new(env_map)?,
+            baseline: CompactMap::from_entries(&read_entries_or_recover(env_map, false, options)?),
+        })
+    }
+
+    /// Запоминает new_entries как новую копию и возвращает изменения относительно прежней. Прежняя копия
+    /// разворачивается в записи только на время сравнения, new_entries переходят в diff без копирования
+    pub fn replace_baseline(&mut self, new_entries: Vec<MapEntry>, map_config: &MapConfig, parallel: bool) -> MapDiff {
+        let old = std::mem::replace(&mut self.baseline, CompactMap::from_entries(&new_entries));
+        diff_filtered(old.into_entries(), new_entries, map_config, parallel)
+    }
+}
+
+/// Сравнивает карту игры с копией окружения и при отличиях обновляет копию
+pub fn sync_map_changes(
+    game_map: &Path,
+    env_map: &Path,
+    watch: &mut MapWatch,
+    map_config: &MapConfig,
+) -> Result<Option<MapDiff>, MapError> {
+    let Some(new_hash) = watch.detector.check(game_map)? else {
+        return Ok(None);
+    };
+
+    let parallel = std::fs::metadata(game_map)?.len() >= map_config.parallel_threshold;
+    let new_entries = read_entries_or_recover(game_map, parallel, &MapReadOptions::from_config(map_config))?;
+    if map_config.snapshot_retention > 0 {
+        let snapshot = archive_snapshot(env_map)?;
+        println!("Предыдущая версия карты сохранена: {}", snapshot.display());
+        prune_snapshots(env_map, map_config.snapshot_retention)?;
+    }
+    copy_with_retry(game_map, env_map)?;
+    watch.detector.mark_synced(new_hash);
+    if map_config.export_listing {
+        let listing = archive_listing(&new_entries, env_map)?;
+        println!("Список файлов карты сохранён: {}", listing.display());
+        prune_listings(env_map, map_config.listing_retention)?;
+    }
+    let mut diff = watch.replace_baseline(new_entries, map_config, parallel);
+    if let Some(assets_root) = &map_config.assets_root {
+        verify_modified(&mut diff, assets_root, map_config.verify_sample);
+    }
+    if diff.filtered > 0 {
+        println!("Изменений в отфильтрованных путях: {}", diff.filtered);
+    }
+    Ok(Some(diff))
+}
+
+// Отмечает изменённые файлы, содержимое которых на диске по-прежнему совпадает со старым хэшем
+fn verify_modified(diff: &mut MapDiff, assets_root: &Path, sample: usize) {
+    let entries: Vec<_> = diff
+        .modified
+        .iter()
+        .take(sample)
+        .map(|m| MapEntry {
+            path: m.path.clone(),
+            hash: m.new_hash,
+        })
+        .collect();
+    let results = verify_entries(&entries, assets_root);
+
+    let (mut matched, mut mismatched, mut missing) = (0, 0, 0);
+    for (result, modified) in results.iter().zip(diff.modified.iter_mut()) {
+        match &result.status {
+            VerificationStatus::Match => matched += 1,
+            VerificationStatus::Mismatch { actual } => {
+                mismatched += 1;
+                modified.content_unchanged = *actual == modified.old_hash;
+                if !modified.content_unchanged {
+                    eprintln!("Файл {} не совпадает с картой: ожидался {}, на диске {}", result.path, result.expected, actual);
+                }
+            }
+            VerificationStatus::Missing => missing += 1,
+            VerificationStatus::Unreadable(e) => eprintln!("Не удалось прочитать {}: {}", result.path, e),
+        }
+    }
+    println!(
+        "Сверка с распакованными ассетами: совпало {}, не совпало {}, нет файла {} (из {})",
+        matched,
+        mismatched,
+        missing,
+        results.len()
+    );
+}
+
+pub fn diff_filtered(old_entries: Vec<MapEntry>, new_entries: Vec<MapEntry>, map_config: &MapConfig, parallel: bool) -> MapDiff {
+    let (old_entries, old_filtered) = filter_entries(old_entries, &map_config.include, &map_config.exclude);
+    let (new_entries, new_filtered) = filter_entries(new_entries, &map_config.include, &map_config.exclude);
+    let mut diff = diff_owned_entries(old_entries, new_entries, parallel);
+    let filtered = diff_owned_entries(old_filtered, new_filtered, parallel);
+    diff.filtered = filtered.total();
+    diff.total_old += filtered.total_old;
+    diff.total_new += filtered.total_new;
+    diff
+}
+
+/// Дополнительные ветки помимо основного сервера, за которыми нужно следить
+pub fn monitored_branches(game_dir: &Path, monitor: &MonitorConfig) -> Vec<Branch> {
+    let mut branches = Vec::new();
+    if monitor.all_runtimes {
+        branches.extend(
+            discover_runtimes(game_dir)
+                .into_iter()
+                .filter(|runtime| runtime.branch != Branch::Live && runtime.map_path.is_some())
+                .map(|runtime| runtime.branch),
+        );
+    }
+    if monitor.ots && !branches.contains(&Branch::Ots) {
+        branches.push(Branch::Ots);
+    }
+    branches
+}
+
+/// Ветки из lang.branches без повторов, в порядке из config.toml
+pub fn lang_branches(lang: &LangConfig) -> Vec<Branch> {
+    let mut branches = Vec::new();
+    for name in &lang.branches {
+        let branch = Branch::from_env_dir_name(name);
+        if !branches.contains(&branch) {
+            branches.push(branch);
+        }
+    }
+    branches
+}
+
+pub fn process_branch_map(
+    game_dir: &Path,
+    env_root: &Path,
+    branch: &Branch,
+    watches: &mut HashMap<Branch, MapWatch>,
+    map_config: &MapConfig,
+    changelog: &ChangelogConfig,
+) -> Result<(), AppError> {
+    let game_map = branch_map_path(game_dir, branch);
+    if !game_map.exists() {
+        return Ok(());
+    }
+
+    let env = Environment::new(env_root, branch.clone());
+    let env_map = env.init()?;
+    let watch = match watches.entry(branch.clone()) {
+        hash_map::Entry::Occupied(entry) => entry.into_mut(),
+        hash_map::Entry::Vacant(entry) => {
+            let read_options = MapReadOptions::from_config(map_config);
+            env.ensure_valid(&read_options)?;
+            entry.insert(MapWatch::new(&env_map, &read_options)?)
+        }
+    };
+    if let Some(diff) = sync_map_changes(&game_map, &env_map, watch, map_config)? {
+        println!("Обнаружены изменения в файле карты ({})!", branch.label());
+        let output_dir = Path::new("docs").join(branch.env_dir_name());
+        let render = LangRenderOptions::default();
+        let stats = generate_changelog(Some(&diff), &output_dir, branch, &[], &render, changelog)?;
+        println!("Изменения: {}", stats);
+        publish_html()?;
+        println!("Изменения ({}) сохранены в HTML документе и опубликованы", branch.label());
+    }
+    Ok(())
+}
+
+/// Отчёт о различиях локализации ОТС и основного сервера; пересчитывается, только когда меняется один из файлов
+#[derive(Default)]
+pub struct BranchComparison {
+    // Время изменения файлов локализаций обеих веток при последнем сравнении
+    seen: Vec<Option<SystemTime>>,
+}
+
+impl BranchComparison {
+    /// Возвращает true, если отчёт в output_dir переписан
+    pub fn refresh(
+        &mut self,
+        game_dir: &Path,
+        lang: &LangConfig,
+        output_dir: &Path,
+        changelog: &ChangelogConfig,
+    ) -> Result<bool, AppError> {
+        let modified = |path: PathBuf| path.metadata().and_then(|m| m.modified()).ok();
+        let seen: Vec<_> = lang
+            .locales
+            .iter()
+            .flat_map(|locale| [Branch::Live, Branch::Ots].map(|branch| modified(lang_file_path(game_dir, &branch, locale))))
+            .collect();
+        if seen == self.seen {
+            return Ok(false);
+        }
+        let diffs = compare_game_branches(game_dir, &lang.locales)?;
+        generate_branch_comparison(&diffs, output_dir, &LangRenderOptions::from_config(lang), changelog)?;
+        self.seen = seen;
+        Ok(true)
+    }
+}
+
+/// После смены папки игры старые копии окружения относятся к другой установке и сравнивать с ними нельзя
+pub fn rebaseline_environment(
+    game_dir: &Path,
+    env_root: &Path,
+    env_map: &Path,
+    watch: &mut MapWatch,
+    branch_watches: &mut HashMap<Branch, MapWatch>,
+    read_options: &MapReadOptions,
+    lang: &LangConfig,
+) -> Result<(), AppError> {
+    let game_map = branch_map_path(game_dir, &Branch::Live);
+    if game_map.exists() {
+        copy_with_retry(&game_map, env_map)?;
+        *watch = MapWatch::new(env_map, read_options)?;
+    }
+
+    for branch in branch_watches.keys() {
+        let branch_map = branch_map_path(game_dir, branch);
+        let branch_env_map = Environment::new(env_root, branch.clone()).map_path();
+        if branch_map.exists() && branch_env_map.exists() {
+            copy_with_retry(&branch_map, &branch_env_map)?;
+        }
+    }
+    branch_watches.clear();
+
+    for branch in lang_branches(lang) {
+        let env = Environment::new(env_root, branch);
+        if lang.scan_tree {
+            rebaseline_lang_tree(game_dir, &env)?;
+        }
+        for locale in &lang.locales {
+            rebaseline_lang_file(game_dir, &env, locale)?;
+        }
+    }
+    Ok(())
+}
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Даже при уведомлениях цикл иногда проходит без события: повтор публикации, пропущенные события
+const NOTIFY_FALLBACK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Ожидание изменений в файлах игры между проходами цикла: по уведомлениям файловой системы
+/// или, если они недоступны или отключены в config.toml, опросом раз в секунду
+pub struct ChangeWatcher {
+    watcher: Option<RecommendedWatcher>,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    debounce: Duration,
+}
+
+impl ChangeWatcher {
+    pub fn new(monitor: &MonitorConfig) -> Self {
+        let (sender, events) = mpsc::channel();
+        let watcher = match monitor.watch {
+            WatchMode::Poll => None,
+            WatchMode::Notify => notify::recommended_watcher(sender)
+                .inspect_err(|e| eprintln!("Уведомления файловой системы недоступны, используется опрос: {}", e))
+                .ok(),
+        };
+        ChangeWatcher {
+            watcher,
+            events,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            debounce: Duration::from_millis(monitor.debounce_ms),
+        }
+    }
+
+    /// Следит за папками, в которых лежат файлы; лаунчер заменяет файлы целиком, поэтому сами файлы не подходят
+    pub fn watch(&mut self, files: Vec<PathBuf>) {
+        let Some(watcher) = &mut self.watcher else {
+            self.files = files;
+            return;
+        };
+
+        // Папки, которых ещё нет, добавятся на одном из следующих проходов
+        let mut dirs: Vec<PathBuf> = files
+            .iter()
+            .filter_map(|f| f.parent().map(Path::to_path_buf))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in self.dirs.iter().filter(|dir| !dirs.contains(dir)) {
+            let _ = watcher.unwatch(dir);
+        }
+        dirs.retain(|dir| {
+            self.dirs.contains(dir)
+                || watcher
+                    .watch(dir, RecursiveMode::NonRecursive)
+                    .inspect_err(|e| eprintln!("Не удалось следить за {}: {}", dir.display(), e))
+                    .is_ok()
+        });
+        self.dirs = dirs;
+        self.files = files;
+    }
+
+    /// Ждёт изменения одного из отслеживаемых файлов и паузы в записи длиной debounce_ms
+    pub fn wait(&mut self) {
+        if self.watcher.is_none() || self.dirs.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+            return;
+        }
+
+        let deadline = Instant::now() + NOTIFY_FALLBACK_INTERVAL;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(timeout) {
+                Ok(event) if self.is_relevant(&event) => break,
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return,
+                Err(RecvTimeoutError::Disconnected) => {
+                    eprintln!("Наблюдение за файлами прервано, используется опрос");
+                    self.watcher = None;
+                    return;
+                }
+            }
+        }
+        // Лаунчер может переписать файл несколько раз подряд: сравниваем только итоговое состояние
+        loop {
+            match self.events.recv_timeout(self.debounce) {
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.watcher = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn is_relevant(&self, event: &notify::Result<Event>) -> bool {
+        match event {
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|path| self.files.contains(path))
+            }
+            // Переполнение очереди событий и прочие ошибки: надёжнее перепроверить файлы
+            Err(_) => true,
+        }
+    }
+}
+
+// Сколько изменений локализации выводить в консоль с --verbose
+const CONSOLE_SUMMARY_LIMIT: usize = 20;
+
+/// Параметры запуска мониторинга из командной строки
+#[derive(Debug, Clone, Default)]
+pub struct MonitorOptions {
+    // --env-dir: папка окружения вместо environment.dir из config.toml
+    pub env_dir: Option<PathBuf>,
+    // --verbose: сводка изменений локализации в консоли
+    pub verbose: bool,
+    // --force-rebaseline: копии окружения пересоздаются по текущим файлам игры
+    pub force_rebaseline: bool,
+}
+
+/// Основной цикл мониторинга: копии окружения, наблюдение за картами и локализацией, отложенная публикация
+pub struct Monitor {
+    config: Config,
+    env_root: PathBuf,
+    live_env: Environment,
+    env_map: PathBuf,
+    read_options: MapReadOptions,
+    lang_branches: Vec<Branch>,
+    verbose: bool,
+    colors: bool,
+    resolver: GamePathResolver,
+    watch: MapWatch,
+    branch_watches: HashMap<Branch, MapWatch>,
+    publish_pending: bool,
+    change_watcher: ChangeWatcher,
+    not_found_streak: u32,
+    branch_comparison: BranchComparison,
+}
+
+impl Monitor {
+    /// Готовит окружение: переносит старую историю локализации, создаёт копии или пересоздаёт их по --force-rebaseline
+    pub fn start(config: Config, options: &MonitorOptions) -> Result<Self, AppError> {
+        let env_root = resolve_environment_root(options.env_dir.as_deref(), &config.environment);
+        println!("Папка окружения: {}", env_root.display());
+        report_installation();
+        let lang_branches = lang_branches(&config.lang);
+        match migrate_legacy_lang_history() {
+            Ok(true) => println!("История локализации перенесена из changes/history в changes/live/history"),
+            Ok(false) => {}
+            Err(e) => eprintln!("Не удалось перенести историю локализации в changes/live/history: {}", e),
+        }
+        let live_env = Environment::new(&env_root, Branch::Live);
+        let env_map = live_env.init()?;
+        let read_options = MapReadOptions::from_config(&config.map);
+        if options.force_rebaseline {
+            for env in Environment::existing(&env_root)? {
+                env.reset_baseline("запрошено через --force-rebaseline")?;
+            }
+            let game_dir = get_game_path()?;
+            for branch in &lang_branches {
+                let env = Environment::new(&env_root, branch.clone());
+                if config.lang.scan_tree {
+                    rebaseline_lang_tree(&game_dir, &env)?;
+                }
+                for locale in &config.lang.locales {
+                    rebaseline_lang_file(&game_dir, &env, locale)?;
+                }
+            }
+        } else {
+            // Сброшенная копия совпадает с картой игры, так что ложного патчноута «всё добавлено» не будет
+            live_env.ensure_valid(&read_options)?;
+        }
+
+        Ok(Monitor {
+            watch: MapWatch::new(&env_map, &read_options)?,
+            resolver: GamePathResolver::new(),
+            branch_watches: HashMap::new(),
+            publish_pending: false,
+            change_watcher: ChangeWatcher::new(&config.monitor),
+            not_found_streak: 0,
+            branch_comparison: BranchComparison::default(),
+            verbose: options.verbose,
+            colors: colors_enabled(),
+            config,
+            env_root,
+            live_env,
+            env_map,
+            read_options,
+            lang_branches,
+        })
+    }
+
+    /// Проходы цикла до ошибки генерации патчноута; остальные ошибки выводятся и повторяются в следующем проходе
+    pub fn run(&mut self) -> Result<(), AppError> {
+        loop {
+            self.poll()?;
+        }
+    }
+
+    /// Один проход цикла вместе с ожиданием следующего изменения файлов игры
+    pub fn poll(&mut self) -> Result<(), AppError> {
+        if self.publish_pending {
+            match publish(&self.lang_branches) {
+                Ok(()) => {
+                    self.publish_pending = false;
+                    println!("Отложенная публикация выполнена");
+                }
+                Err(e) => eprintln!("Повторная публикация не удалась: {}", e),
+            }
+        }
+
+        match self.resolve_game_map() {
+            Ok((game_dir, game_map)) => {
+                self.not_found_streak = 0;
+                self.check_game(&game_dir, &game_map)?;
+                self.change_watcher.wait();
+            }
+            Err(MapError::GameFileNotFound) => {
+                println!("Файл игры не найден, повторная попытка через 1 секунду...");
+                // Диагностика при первой ошибке и затем примерно раз в минуту, чтобы не засорять консоль
+                if self.not_found_streak.is_multiple_of(60) {
+                    report_installation();
+                }
+                self.not_found_streak += 1;
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                eprintln!("Ошибка при получении пути к файлу: {}", e);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+        Ok(())
+    }
+
+    // Папка игры и карта основного сервера; после смены папки игры копии окружения пересоздаются
+    fn resolve_game_map(&mut self) -> Result<(PathBuf, PathBuf), MapError> {
+        let (game_dir, changed) = self.resolver.resolve()?;
+        if changed {
+            println!(
+                "Путь к игре изменился: {}. Копии окружения обновлены без генерации патчноута",
+                game_dir.display()
+            );
+            let rebaseline = rebaseline_environment(
+                &game_dir,
+                &self.env_root,
+                &self.env_map,
+                &mut self.watch,
+                &mut self.branch_watches,
+                &self.read_options,
+                &self.config.lang,
+            );
+            if let Err(e) = rebaseline {
+                eprintln!("Ошибка при обновлении копий окружения: {}", e);
+            }
+        }
+
+        let path = branch_map_path(&game_dir, &Branch::Live);
+        if path.exists() {
+            Ok((game_dir, path))
+        } else {
+            Err(MapError::GameFileNotFound)
+        }
+    }
+
+    fn check_game(&mut self, game_dir: &Path, game_map: &Path) -> Result<(), AppError> {
+        let branches = monitored_branches(game_dir, &self.config.monitor);
+        let mut all_branches = vec![Branch::Live];
+        for branch in branches.iter().chain(&self.lang_branches) {
+            if !all_branches.contains(branch) {
+                all_branches.push(branch.clone());
+            }
+        }
+        self.change_watcher.watch(watched_paths(game_dir, &all_branches, &self.config.lang.locales));
+
+        if self.config.lang.compare_ots && branches.contains(&Branch::Ots) {
+            let output_dir = Path::new("docs").join("ots_compare");
+            match self.branch_comparison.refresh(game_dir, &self.config.lang, &output_dir, &self.config.changelog) {
+                Ok(true) => println!("Сравнение локализации ОТС и основного сервера обновлено"),
+                Ok(false) => {}
+                Err(e) => eprintln!("Ошибка при сравнении локализации ОТС и основного сервера: {}", e),
+            }
+        }
+        for branch in branches {
+            self.check_branch_map(game_dir, &branch);
+        }
+
+        let map_diff = self.check_live_map(game_map);
+        let lang_diffs = self.check_lang(game_dir);
+        if self.verbose {
+            for lang_diff in &lang_diffs {
+                print!("{}", lang_summary(lang_diff, CONSOLE_SUMMARY_LIMIT, self.colors));
+            }
+        }
+        let mut changes_detected = map_diff.is_some();
+        if !lang_diffs.is_empty() {
+            // Порог считается по всем изменениям с последней публикации, поэтому мелкие правки копятся
+            let pending = pending_lang_changes(&self.lang_branches).unwrap_or_else(|e| {
+                eprintln!("Не удалось прочитать историю локализации: {}", e);
+                lang_diffs.clone()
+            });
+            let lang = &self.config.lang;
+            let count: usize = pending.iter().map(|diff| diff.publishable_count(&lang.quiet_keys)).sum();
+            if count >= lang.min_changes {
+                changes_detected = true;
+            } else {
+                println!(
+                    "Изменений локализации с последней публикации {} из {} (lang.min_changes), публикация отложена",
+                    count, lang.min_changes
+                );
+            }
+        }
+
+        // Генерация и публикация ChangeLog, если есть изменения
+        if changes_detected {
+            self.publish_changelog(game_dir, map_diff, lang_diffs)?;
+        }
+        Ok(())
+    }
+
+    fn check_branch_map(&mut self, game_dir: &Path, branch: &Branch) {
+        let (map, changelog) = (&self.config.map, &self.config.changelog);
+        match process_branch_map(game_dir, &self.env_root, branch, &mut self.branch_watches, map, changelog) {
+            Ok(()) => {}
+            Err(AppError::Map(e)) if e.is_corruption() => {
+                eprintln!("Повреждённая карта ({}): {}", branch.label(), e);
+                // Испорченная копия окружения пересоздаётся, испорченная карта игры читается заново в следующем цикле
+                self.branch_watches.remove(branch);
+                if let Err(e) = Environment::new(&self.env_root, branch.clone()).ensure_valid(&self.read_options) {
+                    eprintln!("Не удалось пересоздать копию окружения ({}): {}", branch.label(), e);
+                }
+            }
+            Err(AppError::Publish(e)) => {
+                eprintln!("Ошибка публикации ({}), повтор в следующем цикле: {}", branch.label(), e);
+                self.publish_pending = true;
+            }
+            Err(e) => eprintln!("Ошибка при обработке карты ({}): {}", branch.label(), e),
+        }
+    }
+
+    // Проверка изменений в файле карты; изменения учитываются только после полного копирования
+    fn check_live_map(&mut self, game_map: &Path) -> Option<MapDiff> {
+        match sync_map_changes(game_map, &self.env_map, &mut self.watch, &self.config.map) {
+            Ok(Some(diff)) => {
+                println!("Обнаружены изменения в файле карты!");
+                println!("Изменения в файле карты сохранены");
+                return Some(diff);
+            }
+            Ok(None) => {}
+            Err(e) if e.is_corruption() => {
+                eprintln!("Повреждённая карта, повтор в следующем цикле: {}", e);
+                match self.live_env.ensure_valid(&self.read_options) {
+                    Ok(true) => match MapWatch::new(&self.env_map, &self.read_options) {
+                        Ok(new_watch) => self.watch = new_watch,
+                        Err(e) => eprintln!("Не удалось прочитать новую копию окружения: {}", e),
+                    },
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Не удалось пересоздать копию окружения: {}", e),
+                }
+            }
+            Err(e) => eprintln!("Ошибка при обработке файла карты, повтор в следующем цикле: {}", e),
+        }
+        None
+    }
+
+    // Проверка изменений в файлах локализации каждой ветки из lang.branches; у ветки свои копии
+    // в окружении, своя история и свой хэш, поэтому ветки не влияют друг на друга
+    fn check_lang(&self, game_dir: &Path) -> Vec<LangDiff> {
+        let lang = &self.config.lang;
+        let mut lang_diffs = Vec::new();
+        for branch in &self.lang_branches {
+            let env = Environment::new(&self.env_root, branch.clone());
+            let mut branch_diffs = Vec::new();
+            if lang.scan_tree {
+                match process_lang_tree(game_dir, &env, lang) {
+                    Ok(diffs) => branch_diffs.extend(diffs),
+                    Err(e) => eprintln!("Ошибка при обработке дерева файлов локализации ({}): {}", branch.label(), e),
+                }
+            }
+            for (locale, result) in process_lang_files(game_dir, &env, self.locales(), lang) {
+                match result {
+                    Ok(Some(lang_diff)) => branch_diffs.push(lang_diff),
+                    Ok(None) => {}
+                    Err(e) => eprintln!("Ошибка при обработке lang файла {} ({}): {}", locale, branch.label(), e),
+                }
+            }
+            tag_lang_branch(&mut branch_diffs, branch, &self.lang_branches);
+            if !branch_diffs.is_empty() {
+                // Тот же набор изменений (в другом порядке или повторно после перезапуска) не публикуется
+                // снова; хэш хранится на диске рядом с историей ветки
+                let history_dir = lang_history_dir(branch);
+                let hash = lang_diffs_hash(&branch_diffs);
+                if load_last_lang_hash(&history_dir) == Some(hash) {
+                    println!(
+                        "Изменения локализации ({}) совпадают с уже обработанными, повторная публикация не нужна",
+                        branch.label()
+                    );
+                    branch_diffs.clear();
+                } else if let Err(e) = save_last_lang_hash(&history_dir, hash) {
+                    eprintln!("Не удалось сохранить хэш изменений локализации ({}): {}", branch.label(), e);
+                }
+            }
+            lang_diffs.extend(branch_diffs);
+        }
+        lang_diffs
+    }
+
+    // Локали из lang.locales; при lang.scan_tree файлы находятся обходом дерева
+    fn locales(&self) -> &[String] {
+        if self.config.lang.scan_tree {
+            &[]
+        } else {
+            &self.config.lang.locales
+        }
+    }
+
+    fn publish_changelog(
+        &mut self,
+        game_dir: &Path,
+        map_diff: Option<MapDiff>,
+        lang_diffs: Vec<LangDiff>,
+    ) -> Result<(), AppError> {
+        // Изменения локализации с последней публикации, чтобы не потерять те, что не успели опубликовать
+        let mut lang_diffs = pending_lang_changes(&self.lang_branches).unwrap_or_else(|e| {
+            eprintln!("Не удалось прочитать историю локализации: {}", e);
+            lang_diffs
+        });
+        // Установленные локали без изменений тоже участвуют в поиске ключей без перевода
+        for branch in &self.lang_branches {
+            let mut unchanged: Vec<LangDiff> = self
+                .locales()
+                .iter()
+                .filter(|locale| lang_file_path(game_dir, branch, locale).exists())
+                .map(|locale| LangDiff {
+                    locale: locale.clone(),
+                    ..LangDiff::default()
+                })
+                .collect();
+            tag_lang_branch(&mut unchanged, branch, &self.lang_branches);
+            unchanged.retain(|u| !lang_diffs.iter().any(|d| d.locale == u.locale && d.branch == u.branch));
+            lang_diffs.extend(unchanged);
+        }
+        // Если изменилась только локализация, map_diff нет и раздел файловой структуры не строится
+        let stats = generate_changelog(
+            map_diff.as_ref(),
+            Path::new("docs"),
+            &Branch::Live,
+            &lang_diffs,
+            &LangRenderOptions::from_config(&self.config.lang),
+            &self.config.changelog,
+        )?;
+        println!("Изменения: {}", stats);
+        match publish(&self.lang_branches) {
+            Ok(()) => println!("Изменения сохранены в HTML документе и опубликованы"),
+            Err(e) => {
+                eprintln!("Ошибка публикации, повтор в следующем цикле: {}", e);
+                self.publish_pending = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Объясняет, почему карта игры не находится: неверный путь из реестра, папка от другой программы и т.п.
+fn report_installation() {
+    match get_game_path().and_then(|path| probe_game_installation(&path)) {
+        Ok(info) => println!(
+            "Папка игры: {} (сборок в runtime: {})",
+            info.path.display(),
+            info.runtimes.len()
+        ),
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Укажите правильную папку игры в game.path (config.toml) или переменной окружения KREVETKA_GAME_PATH");
+        }
+    }
+}
+
+// Публикует патчноут и запоминает время публикации в истории локализации каждой ветки
+fn publish(lang_branches: &[Branch]) -> Result<(), AppError> {
+    publish_html()?;
+    for branch in lang_branches {
+        if let Err(e) = mark_published(&lang_history_dir(branch)) {
+            eprintln!("Не удалось сохранить время публикации ({}): {}", branch.label(), e);
+        }
+    }
+    Ok(())
+}
+
+// При нескольких ветках в lang.branches изменения подписываются веткой, чтобы патчноут их различал
+fn tag_lang_branch(diffs: &mut [LangDiff], branch: &Branch, lang_branches: &[Branch]) {
+    if lang_branches.len() > 1 {
+        for diff in diffs {
+            diff.branch = Some(branch.env_dir_name().to_string());
+        }
+    }
+}
+
+// Изменения локализации с последней публикации по всем веткам; история каждой ветки читается отдельно
+fn pending_lang_changes(lang_branches: &[Branch]) -> Result<Vec<LangDiff>, ChangelogError> {
+    let mut diffs = Vec::new();
+    for branch in lang_branches {
+        let mut branch_diffs = lang_changes_since_publish(&lang_history_dir(branch))?;
+        tag_lang_branch(&mut branch_diffs, branch, lang_branches);
+        diffs.extend(branch_diffs);
+    }
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changelog::generate_comparison_changelog;
+    use crate::diff::diff_map_entries;
+    use crate::map::MapHash;
+    use crate::testing::{MapFileBuilder, TempDir};
+
+    // Прежний путь сравнения: копия развёрнута целиком, новые записи склонированы, diff строится по срезам
+    fn reference_diff(old: &[MapEntry], new: &[MapEntry], map_config: &MapConfig) -> MapDiff {
+        let (old_entries, old_filtered) = filter_entries(old.to_vec(), &map_config.include, &map_config.exclude);
+        let (new_entries, new_filtered) = filter_entries(new.to_vec(), &map_config.include, &map_config.exclude);
+        let mut diff = diff_map_entries(&old_entries, &new_entries);
+        let filtered = diff_map_entries(&old_filtered, &new_filtered);
+        diff.filtered = filtered.total();
+        diff.total_old += filtered.total_old;
+        diff.total_new += filtered.total_new;
+        diff
+    }
+
+    #[test]
+    fn replaced_baseline_gives_byte_identical_changelog() {
+        let dir = TempDir::new("replace_baseline");
+        let env_map = dir.join("stalcraft.map");
+        MapFileBuilder::synthetic(3000).write_to(&env_map).unwrap();
+        let options = MapReadOptions::default();
+        let old = read_map_entries(&env_map, &options).unwrap();
+        let mut new: Vec<MapEntry> = old
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 40 != 1)
+            .map(|(i, e)| MapEntry {
+                path: if i % 90 == 5 { e.path.replace("sub_", "moved_") } else { e.path.clone() },
+                hash: if i % 25 == 2 { MapHash::from([i as u8; 20]) } else { e.hash },
+            })
+            .collect();
+        new.push(MapEntry {
+            path: "assets/new.png".to_string(),
+            hash: MapHash::from([0xfe; 20]),
+        });
+        let map_config = MapConfig {
+            exclude: vec!["assets/stalker/dir_1/*".to_string()],
+            ..MapConfig::default()
+        };
+
+        for parallel in [false, true] {
+            let mut watch = MapWatch::new(&env_map, &options).unwrap();
+            let diff = watch.replace_baseline(new.clone(), &map_config, parallel);
+            let expected = reference_diff(&old, &new, &map_config);
+            assert_eq!(diff, expected);
+            assert!(diff.filtered > 0 && !diff.renamed.is_empty());
+            assert_eq!(watch.baseline.to_entries(), new);
+
+            let render = |diff: &MapDiff, name: &str| {
+                let out = dir.join(name);
+                generate_comparison_changelog(diff, &out, "test", &ChangelogConfig::default()).unwrap();
+                std::fs::read(out.join("index.html")).unwrap()
+            };
+            assert_eq!(render(&diff, "current"), render(&expected, "reference"));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use crate::lang::{canonical_value, normalize_lang_content, LangDiff, LangEntry, LangParseOptions, ModifiedLangEntry};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Собирает .map файлы побайтово, в том числе заведомо повреждённые, чтобы не хранить бинарники в репозитории
+#[derive(Debug, Default)]
+pub struct MapFileBuilder {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    declared_count: Option<u32>,
+    corrupted: Vec<usize>,
+    truncate_after: Option<usize>,
+    trailing: Vec<u8>,
+}
+
+impl MapFileBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Размер хэша берётся как есть, так можно собрать карту в другом формате
+    pub fn entry(self, path: &str, hash: impl AsRef<[u8]>) -> Self {
+        self.raw_entry(path.as_bytes(), hash)
+    }
+
+    /// Путь записывается как есть, без проверки длины и UTF-8
+    pub fn raw_entry(mut self, path: &[u8], hash: impl AsRef<[u8]>) -> Self {
+        self.entries.push((path.to_vec(), hash.as_ref().to_vec()));
+        self
+    }
+
+    pub fn declared_count(mut self, count: u32) -> Self {
+        self.declared_count = Some(count);
+        self
+    }
+
+    /// Поле длины пути у записи n обнуляется: такой размер недопустим при любом лимите
+    pub fn corrupt_entry_at(mut self, n: usize) -> Self {
+        self.corrupted.push(n);
+        self
+    }
+
+    /// В файл попадают только первые n записей, заявленное количество не меняется
+    pub fn truncate_after(mut self, n: usize) -> Self {
+        self.truncate_after = Some(n);
+        self
+    }
+
+    pub fn trailing_bytes(mut self, bytes: &[u8]) -> Self {
+        self.trailing.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn build_bytes(&self) -> Vec<u8> {
+        let count = self.declared_count.unwrap_or(self.entries.len() as u32);
+        let mut data = count.to_be_bytes().to_vec();
+        let written = self.truncate_after.unwrap_or(self.entries.len()).min(self.entries.len());
+        for (i, (path, hash)) in self.entries[..written].iter().enumerate() {
+            let size = if self.corrupted.contains(&i) {
+                0
+            } else {
+                path.len() as u16
+            };
+            data.extend_from_slice(&size.to_be_bytes());
+            data.extend_from_slice(path);
+            data.extend_from_slice(hash);
+        }
+        if written == self.entries.len() {
+            data.extend_from_slice(&self.trailing);
+        }
+        data
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.build_bytes())
+    }
+
+    /// Карта из count записей, похожая на настоящую: несколько уровней папок и 20-байтовый хэш из номера записи
+    pub fn synthetic(count: usize) -> Self {
+        (0..count).fold(Self::new(), |builder, i| {
+            let path = format!("assets/stalker/dir_{}/sub_{}/file_{}.png", i % 97, i % 13, i);
+            let mut hash = [0u8; 20];
+            for (n, byte) in hash.iter_mut().enumerate() {
+                *byte = (i >> (n % 4 * 8)) as u8 ^ n as u8;
+            }
+            builder.entry(&path, hash)
+        })
+    }
+}
+
+/// Временная папка теста: krevetka_<name>_<pid> в системной временной папке. Создаётся пустой и удаляется
+/// вместе со значением, даже если тест упал на assert
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("krevetka_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("не удалось создать временную папку");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Прежняя реализация diff_lang_content: оба файла разбираются в HashMap. Тесты lang и benches/lang_diff.rs
+// сравнивают с ней текущую реализацию
+
+/// Строки файла локализации: ключ -> (номер строки, значение); у повторяющегося ключа - последнее вхождение
+pub type LangLines<'a> = HashMap<&'a str, (usize, Option<Cow<'a, str>>)>;
+
+pub fn reference_lang_lines<'a>(content: &'a str, options: &LangParseOptions) -> LangLines<'a> {
+    let mut lines = HashMap::new();
+    for (line, l) in content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let mut parts = l.splitn(2, '=');
+        let key = parts.next().unwrap_or_default().trim();
+        lines.insert(key, (line, parts.next().map(|value| canonical_value(value, options))));
+    }
+    lines
+}
+
+/// Ключи, встречающиеся в файле больше одного раза, в порядке второго вхождения
+pub fn reference_duplicate_keys(content: &str) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for l in content.lines().filter(|l| !l.trim().is_empty()) {
+        let key = l.split('=').next().unwrap_or_default().trim();
+        let count = counts.entry(key).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            duplicates.push(key.to_string());
+        }
+    }
+    duplicates
+}
+
+pub fn reference_keys_in_file_order<'a>(lines: &LangLines<'a>) -> Vec<&'a str> {
+    let mut keys: Vec<_> = lines.iter().map(|(key, (line, _))| (*line, *key)).collect();
+    keys.sort_unstable();
+    keys.into_iter().map(|(_, key)| key).collect()
+}
+
+fn change_positions<'a>(old_lines: &LangLines<'a>, new_lines: &LangLines<'a>) -> HashMap<&'a str, usize> {
+    let mut removed_after: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
+    let mut last_kept = None;
+    for key in reference_keys_in_file_order(old_lines) {
+        if new_lines.contains_key(key) {
+            last_kept = Some(key);
+        } else {
+            removed_after.entry(last_kept).or_default().push(key);
+        }
+    }
+
+    let mut order: Vec<&str> = removed_after.remove(&None).unwrap_or_default();
+    for key in reference_keys_in_file_order(new_lines) {
+        order.push(key);
+        order.extend(removed_after.remove(&Some(key)).unwrap_or_default());
+    }
+    order.into_iter().enumerate().map(|(position, key)| (key, position)).collect()
+}
+
+/// Тот же результат, что должен давать diff_lang_content_with, но через HashMap
+pub fn reference_diff(locale: &str, old_content: &str, new_content: &str, options: &LangParseOptions) -> LangDiff {
+    let old_content = normalize_lang_content(old_content);
+    let new_content = normalize_lang_content(new_content);
+    let old_lines = reference_lang_lines(&old_content, options);
+    let new_lines = reference_lang_lines(&new_content, options);
+    let positions = change_positions(&old_lines, &new_lines);
+    let owned = |value: &Option<Cow<str>>| value.as_deref().map(String::from);
+
+    let mut diff = LangDiff {
+        locale: locale.to_string(),
+        duplicates: reference_duplicate_keys(&new_content),
+        ..LangDiff::default()
+    };
+    for key in reference_keys_in_file_order(&new_lines) {
+        let (_, new_value) = &new_lines[key];
+        let position = positions[key];
+        match old_lines.get(key) {
+            Some((_, old_value)) if old_value != new_value => diff.modified.push(ModifiedLangEntry {
+                key: key.to_string(),
+                old: owned(old_value),
+                new: owned(new_value),
+                revert: false,
+                position,
+            }),
+            None => diff.added.push(LangEntry {
+                key: key.to_string(),
+                value: owned(new_value),
+                position,
+            }),
+            _ => {}
+        }
+    }
+    for key in reference_keys_in_file_order(&old_lines) {
+        if !new_lines.contains_key(key) {
+            diff.removed.push(LangEntry {
+                key: key.to_string(),
+                value: owned(&old_lines[key].1),
+                position: positions[key],
+            });
+        }
+    }
+    diff.removed.sort_by_key(|e| e.position);
+    diff.detect_renames();
+    diff
+}
@@ -0,0 +1,219 @@
+use std::fmt::Write;
+
+// Дальше этого расстояния редактирования середина считается заменённой целиком: память поиска растёт квадратично
+const MAX_EDIT_DISTANCE: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOp<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// Построчное сравнение (алгоритм Майерса) с отсечением общего начала и конца
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<LineOp<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ops: Vec<_> = old[..prefix].iter().map(|line| LineOp::Equal(line)).collect();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    match myers(old_middle, new_middle) {
+        Some(middle) => ops.extend(middle),
+        None => {
+            ops.extend(old_middle.iter().map(|line| LineOp::Delete(line)));
+            ops.extend(new_middle.iter().map(|line| LineOp::Insert(line)));
+        }
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|line| LineOp::Equal(line)));
+    ops
+}
+
+fn myers<'a>(old: &[&'a str], new: &[&'a str]) -> Option<Vec<LineOp<'a>>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    let mut v = vec![0isize; (2 * offset + 1) as usize];
+    // Для каждого шага d сохраняются позиции диагоналей -d..=d, по ним потом восстанавливается путь
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=(n + m).min(MAX_EDIT_DISTANCE as isize) {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                v[(k + 1 + offset) as usize]
+            } else {
+                v[(k - 1 + offset) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + offset) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                return Some(backtrack(old, new, &trace));
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+    None
+}
+
+fn backtrack<'a>(old: &[&'a str], new: &[&'a str], trace: &[Vec<isize>]) -> Vec<LineOp<'a>> {
+    let (mut x, mut y) = (old.len() as isize, new.len() as isize);
+    let mut ops = Vec::new();
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        if d == 0 {
+            break;
+        }
+        let previous = &trace[(d - 1) as usize];
+        let prev_at = |k: isize| previous[(k + d - 1) as usize];
+        let prev_k = if k == -d || (k != d && prev_at(k - 1) < prev_at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = prev_at(prev_k);
+        let prev_y = prev_x - prev_k;
+        debug_assert!(at(k) == x);
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(LineOp::Equal(old[x as usize]));
+        }
+        if x == prev_x {
+            y -= 1;
+            ops.push(LineOp::Insert(new[y as usize]));
+        } else {
+            x -= 1;
+            ops.push(LineOp::Delete(old[x as usize]));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        ops.push(LineOp::Equal(old[x as usize]));
+    }
+    ops.reverse();
+    ops
+}
+
+/// Unified diff с заголовками ---/+++ и `context` строками контекста вокруг изменений; пустая строка, если файлы совпадают
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str, context: usize) -> String {
+    let old_lines: Vec<_> = old.lines().collect();
+    let new_lines: Vec<_> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+    let changed: Vec<usize> = (0..ops.len()).filter(|&i| !matches!(ops[i], LineOp::Equal(_))).collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Изменения, между которыми не больше 2 * context одинаковых строк, попадают в один фрагмент
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {}", old_name);
+    let _ = writeln!(out, "+++ {}", new_name);
+    // Номера строк в начале каждой операции
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for op in &ops {
+        positions.push((old_line, new_line));
+        match op {
+            LineOp::Equal(_) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            LineOp::Delete(_) => old_line += 1,
+            LineOp::Insert(_) => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let (old_count, new_count) = (old_end - old_start, new_end - new_start);
+        // По соглашению unified diff пустой диапазон указывает на строку перед ним
+        let line_number = |start: usize, count: usize| if count == 0 { start } else { start + 1 };
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            line_number(old_start, old_count),
+            old_count,
+            line_number(new_start, new_count),
+            new_count
+        );
+        for op in &ops[start..end] {
+            let _ = match op {
+                LineOp::Equal(line) => writeln!(out, " {}", line),
+                LineOp::Delete(line) => writeln!(out, "-{}", line),
+                LineOp::Insert(line) => writeln!(out, "+{}", line),
+            };
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &str, ops: &[LineOp]) -> (String, String) {
+        let mut rebuilt_old = Vec::new();
+        let mut rebuilt_new = Vec::new();
+        for op in ops {
+            match op {
+                LineOp::Equal(line) => {
+                    rebuilt_old.push(*line);
+                    rebuilt_new.push(*line);
+                }
+                LineOp::Delete(line) => rebuilt_old.push(*line),
+                LineOp::Insert(line) => rebuilt_new.push(*line),
+            }
+        }
+        assert_eq!(rebuilt_old, old.lines().collect::<Vec<_>>());
+        (rebuilt_old.join("\n"), rebuilt_new.join("\n"))
+    }
+
+    #[test]
+    fn diff_reconstructs_both_sides() {
+        let old = "a\nb\nc\nd\ne\nf";
+        let new = "a\nx\nc\nd\nf\ng";
+        let old_lines: Vec<_> = old.lines().collect();
+        let new_lines: Vec<_> = new.lines().collect();
+        let ops = diff_lines(&old_lines, &new_lines);
+        let (_, rebuilt_new) = apply(old, &ops);
+        assert_eq!(rebuilt_new, new);
+        assert_eq!(ops.iter().filter(|op| !matches!(op, LineOp::Equal(_))).count(), 4);
+    }
+
+    #[test]
+    fn unified_diff_has_headers_and_hunks() {
+        let old: String = (1..=20).map(|i| format!("key{} = {}\n", i, i)).collect();
+        let new = old.replace("key3 = 3", "key3 = three").replace("key18 = 18\n", "");
+        let diff = unified_diff("a/ru.lang", "b/ru.lang", &old, &new, 3);
+        assert_eq!(
+            diff,
+            "--- a/ru.lang\n+++ b/ru.lang\n\
+             @@ -1,6 +1,6 @@\n key1 = 1\n key2 = 2\n-key3 = 3\n+key3 = three\n key4 = 4\n key5 = 5\n key6 = 6\n\
+             @@ -15,6 +15,5 @@\n key15 = 15\n key16 = 16\n key17 = 17\n-key18 = 18\n key19 = 19\n key20 = 20\n"
+        );
+        assert!(unified_diff("a", "b", &old, &old, 3).is_empty());
+    }
+}
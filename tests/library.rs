@@ -0,0 +1,35 @@
+use krevetka::changelog::generate_comparison_changelog;
+use krevetka::config::ChangelogConfig;
+use krevetka::diff::diff_map_entries;
+use krevetka::map::{read_map_entries, write_map_entries, MapEntry, MapHash, MapReadOptions};
+use std::fs;
+
+fn entry(path: &str, byte: u8) -> MapEntry {
+    MapEntry {
+        path: path.to_string(),
+        hash: MapHash::from([byte; 20]),
+    }
+}
+
+#[test]
+fn map_round_trip_and_changelog() {
+    let dir = std::env::temp_dir().join(format!("krevetka_library_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let old_map = dir.join("old.map");
+    let new_map = dir.join("new.map");
+    let options = MapReadOptions::default();
+    write_map_entries(&old_map, &[entry("assets/a.png", 1), entry("assets/b.png", 2)], &options).unwrap();
+    write_map_entries(&new_map, &[entry("assets/a.png", 3), entry("assets/c.png", 4)], &options).unwrap();
+
+    let old_entries = read_map_entries(&old_map, &options).unwrap();
+    let new_entries = read_map_entries(&new_map, &options).unwrap();
+    assert_eq!(old_entries, [entry("assets/a.png", 1), entry("assets/b.png", 2)]);
+
+    let diff = diff_map_entries(&old_entries, &new_entries);
+    let stats = generate_comparison_changelog(&diff, &dir.join("docs"), "old → new", &ChangelogConfig::default()).unwrap();
+    let html = fs::read_to_string(dir.join("docs").join("index.html")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((stats.added, stats.modified, stats.deleted), (1, 1, 1));
+    assert!(html.contains("c.png"));
+}